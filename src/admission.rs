@@ -0,0 +1,257 @@
+// Admission Control
+//
+// Keeps a provider from selling more than its node holds. What can be sold is
+// the node's total CPU, memory and disk scaled by the overcommit ratios, minus
+// what the specs of active workloads and in-flight spawns already claim. Spawns
+// that don't fit are refused with "capacity_exceeded" before their token is redeemed.
+
+use std::fmt;
+use std::ops::Add;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::nostr::{ErrorResponseContent, PodSpec};
+
+/// CPU, memory and disk in the units specs are sold in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resources {
+    pub cpu_millicores: u64,
+    pub memory_mb: u64,
+    pub storage_gb: u64,
+}
+
+impl Resources {
+    /// What one workload of `spec` claims
+    pub fn of_spec(spec: &PodSpec) -> Self {
+        Self {
+            cpu_millicores: spec.cpu_millicores,
+            memory_mb: spec.memory_mb,
+            storage_gb: spec.storage_gb,
+        }
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self {
+            cpu_millicores: self.cpu_millicores.saturating_sub(other.cpu_millicores),
+            memory_mb: self.memory_mb.saturating_sub(other.memory_mb),
+            storage_gb: self.storage_gb.saturating_sub(other.storage_gb),
+        }
+    }
+}
+
+impl Add for Resources {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            cpu_millicores: self.cpu_millicores + other.cpu_millicores,
+            memory_mb: self.memory_mb + other.memory_mb,
+            storage_gb: self.storage_gb + other.storage_gb,
+        }
+    }
+}
+
+impl std::iter::Sum for Resources {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+/// How far each resource may be oversold: a cpu ratio of 2.0 sells twice the node's cores.
+/// Memory above 1.0 risks tenants being OOM-killed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OvercommitRatios {
+    #[serde(default = "default_ratio")]
+    pub cpu: f64,
+    #[serde(default = "default_ratio")]
+    pub memory: f64,
+    #[serde(default = "default_ratio")]
+    pub disk: f64,
+}
+
+fn default_ratio() -> f64 {
+    1.0
+}
+
+impl Default for OvercommitRatios {
+    fn default() -> Self {
+        Self {
+            cpu: default_ratio(),
+            memory: default_ratio(),
+            disk: default_ratio(),
+        }
+    }
+}
+
+/// A spawn refused because the node is full, reported as "capacity_exceeded"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityExceeded(pub String);
+
+impl CapacityExceeded {
+    pub const ERROR_TYPE: &'static str = "capacity_exceeded";
+
+    pub fn to_error_response(&self) -> ErrorResponseContent {
+        ErrorResponseContent {
+            error_type: Self::ERROR_TYPE.to_string(),
+            message: self.0.clone(),
+            details: Some("Try a smaller tier or another provider".to_string()),
+            accepted_mints: vec![],
+            refund_token: None,
+        }
+    }
+}
+
+impl fmt::Display for CapacityExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CapacityExceeded {}
+
+/// Tracks spawns in flight and decides whether new ones fit
+#[derive(Debug, Default)]
+pub struct AdmissionController {
+    ratios: OvercommitRatios,
+    pending: Mutex<Resources>, // Admitted spawns not yet among the committed workloads
+}
+
+impl AdmissionController {
+    pub fn new(ratios: OvercommitRatios) -> Self {
+        Self {
+            ratios,
+            pending: Mutex::new(Resources::default()),
+        }
+    }
+
+    /// `total` scaled by the overcommit ratios
+    fn sellable_total(&self, total: Resources) -> Resources {
+        Resources {
+            cpu_millicores: (total.cpu_millicores as f64 * self.ratios.cpu) as u64,
+            memory_mb: (total.memory_mb as f64 * self.ratios.memory) as u64,
+            storage_gb: (total.storage_gb as f64 * self.ratios.disk) as u64,
+        }
+    }
+
+    /// What can still be sold on a node with `total` resources while `committed` are claimed
+    pub fn sellable(&self, total: Resources, committed: Resources) -> Resources {
+        let pending = *self.pending.lock().unwrap();
+        self.sellable_total(total).saturating_sub(committed + pending)
+    }
+
+    /// How many more workloads of `spec` fit, or None if the backend reports
+    /// none of the totals `spec` needs
+    pub fn slots(&self, spec: &PodSpec, total: Resources, committed: Resources) -> Option<u32> {
+        let available = self.sellable(total, committed);
+        let request = Resources::of_spec(spec);
+
+        [
+            (total.cpu_millicores, available.cpu_millicores, request.cpu_millicores),
+            (total.memory_mb, available.memory_mb, request.memory_mb),
+            (total.storage_gb, available.storage_gb, request.storage_gb),
+        ]
+        .into_iter()
+        .filter(|&(total, _, request)| total > 0 && request > 0)
+        .map(|(_, available, request)| (available / request).min(u32::MAX as u64) as u32)
+        .min()
+    }
+
+    /// Claim `spec`'s resources if they fit. The claim is held until the returned
+    /// reservation is dropped, by which time the workload should be in `committed`.
+    /// A total of 0 means the backend doesn't report that resource, so it isn't checked.
+    pub fn admit(&self, spec: &PodSpec, total: Resources, committed: Resources) -> Result<Reservation<'_>, CapacityExceeded> {
+        let mut pending = self.pending.lock().unwrap();
+        let available = self.sellable_total(total).saturating_sub(committed + *pending);
+        let request = Resources::of_spec(spec);
+
+        let mut short = Vec::new();
+        if total.cpu_millicores > 0 && request.cpu_millicores > available.cpu_millicores {
+            short.push(format!("{}m CPU requested, {}m left", request.cpu_millicores, available.cpu_millicores));
+        }
+        if total.memory_mb > 0 && request.memory_mb > available.memory_mb {
+            short.push(format!("{} MB memory requested, {} MB left", request.memory_mb, available.memory_mb));
+        }
+        if total.storage_gb > 0 && request.storage_gb > available.storage_gb {
+            short.push(format!("{} GB disk requested, {} GB left", request.storage_gb, available.storage_gb));
+        }
+        if !short.is_empty() {
+            return Err(CapacityExceeded(format!("Not enough capacity for tier {}: {}", spec.id, short.join("; "))));
+        }
+
+        *pending = *pending + request;
+        Ok(Reservation { controller: self, resources: request })
+    }
+}
+
+/// Resources claimed by a spawn in flight, released on drop
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    controller: &'a AdmissionController,
+    resources: Resources,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut pending = self.controller.pending.lock().unwrap();
+        *pending = pending.saturating_sub(self.resources);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(cpu_millicores: u64, memory_mb: u64, storage_gb: u64) -> PodSpec {
+        serde_json::from_value(serde_json::json!({
+            "id": "basic",
+            "name": "Basic",
+            "description": "",
+            "cpu_millicores": cpu_millicores,
+            "memory_mb": memory_mb,
+            "storage_gb": storage_gb,
+            "rate_msats_per_sec": 50,
+        }))
+        .unwrap()
+    }
+
+    const NODE: Resources = Resources { cpu_millicores: 4000, memory_mb: 4096, storage_gb: 100 };
+
+    #[test]
+    fn test_admit_until_full() {
+        let admission = AdmissionController::default();
+        let basic = spec(1000, 2048, 10);
+
+        let first = admission.admit(&basic, NODE, Resources::default()).unwrap();
+        let second = admission.admit(&basic, NODE, Resources::default()).unwrap();
+        let err = admission.admit(&basic, NODE, Resources::default()).unwrap_err();
+        assert!(err.0.contains("2048 MB memory requested, 0 MB left"), "{}", err);
+        assert_eq!(err.to_error_response().error_type, "capacity_exceeded");
+
+        // Once created, workloads count as committed instead of pending
+        drop((first, second));
+        let committed = Resources::of_spec(&basic) + Resources::of_spec(&basic);
+        assert!(admission.admit(&basic, NODE, committed).is_err());
+        assert_eq!(admission.sellable(NODE, committed), Resources { cpu_millicores: 2000, memory_mb: 0, storage_gb: 80 });
+    }
+
+    #[test]
+    fn test_slots() {
+        let admission = AdmissionController::default();
+        let basic = spec(1000, 1024, 10);
+        assert_eq!(admission.slots(&basic, NODE, Resources::default()), Some(4));
+        assert_eq!(admission.slots(&basic, NODE, Resources::of_spec(&spec(2500, 512, 10))), Some(1));
+        assert_eq!(admission.slots(&basic, Resources::default(), Resources::default()), None);
+    }
+
+    #[test]
+    fn test_overcommit_and_unreported_totals() {
+        let admission = AdmissionController::new(OvercommitRatios { cpu: 2.0, memory: 1.0, disk: 1.0 });
+        let cpu_heavy = spec(8000, 1024, 10);
+        assert!(admission.admit(&cpu_heavy, NODE, Resources::default()).is_ok());
+
+        // A backend that reports no disk total doesn't limit disk
+        let no_disk = Resources { storage_gb: 0, ..NODE };
+        assert!(admission.admit(&spec(1000, 1024, 500), no_disk, Resources::default()).is_ok());
+    }
+}
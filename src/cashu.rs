@@ -1,22 +1,48 @@
 // Cashu Token Utilities
 //
-// Provides token amount extraction only
-// Payment verification handled by ngx_l402 at nginx layer
+// Provides token amount extraction and token redemption (swap at the issuing mint).
+// HTTP path: payment verification handled by ngx_l402 at nginx layer
+// Nostr provider path: tokens are received into the local redb wallet before provisioning
 
 use std::sync::{Arc, OnceLock};
 use std::path::Path;
 
+use crate::nostr::ErrorResponseContent;
+
 const MSAT_PER_SAT: u64 = 1000;
 
-// Database singleton (kept for compatibility, may not be needed)
+// Wallet database singleton, shared by every mint the service receives from
 static CASHU_DB: OnceLock<Arc<cdk_redb::wallet::WalletRedbDatabase>> = OnceLock::new();
 
+// Path CASHU_DB was opened from. redb locks the file, so it is opened once per process.
+static CASHU_DB_PATH: OnceLock<String> = OnceLock::new();
+
+// Wallet seed singleton, persisted next to the database
+static CASHU_SEED: OnceLock<[u8; 64]> = OnceLock::new();
+
 pub async fn initialize_cashu(db_path: &str) -> Result<(), String> {
-    // Initialize database for compatibility
+    if let Some(open_path) = CASHU_DB_PATH.get() {
+        if open_path == db_path {
+            return Ok(());
+        }
+        return Err(format!("Cashu wallet is already open at {}", open_path));
+    }
+
+    if let Some(parent) = Path::new(db_path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create Cashu database directory: {}", e))?;
+        }
+    }
+
+    let seed = load_or_create_seed(&format!("{}.seed", db_path))?;
+    let _ = CASHU_SEED.set(seed);
+
     match cdk_redb::wallet::WalletRedbDatabase::new(Path::new(db_path)) {
         Ok(db) => {
             tracing::debug!("Cashu database initialized at: {}", db_path);
             let _ = CASHU_DB.set(Arc::new(db));
+            let _ = CASHU_DB_PATH.set(db_path.to_string());
             Ok(())
         },
         Err(e) => {
@@ -27,34 +53,311 @@ pub async fn initialize_cashu(db_path: &str) -> Result<(), String> {
     }
 }
 
+/// Load the wallet seed from disk, generating and persisting a new one on first run.
+/// The seed is what lets the wallet restore its proofs, so it is written owner-only.
+fn load_or_create_seed(seed_path: &str) -> Result<[u8; 64], String> {
+    let path = Path::new(seed_path);
+
+    if path.exists() {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Failed to read wallet seed {}: {}", seed_path, e))?;
+        return bytes.as_slice().try_into()
+            .map_err(|_| format!("Wallet seed {} is corrupt (expected 64 bytes, found {})", seed_path, bytes.len()));
+    }
+
+    let mut seed = [0u8; 64];
+    rand::Rng::fill(&mut rand::thread_rng(), &mut seed[..]);
+    std::fs::write(path, seed)
+        .map_err(|e| format!("Failed to write wallet seed {}: {}", seed_path, e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600));
+    }
+
+    tracing::info!("Generated new Cashu wallet seed at {}", seed_path);
+    Ok(seed)
+}
+
+/// Build a cdk wallet for a single mint on top of the shared redb database
+pub fn wallet_for_mint(mint_url: &str, unit: cdk::nuts::CurrencyUnit) -> anyhow::Result<cdk::wallet::Wallet> {
+    let db = CASHU_DB.get()
+        .ok_or_else(|| anyhow::anyhow!("Cashu wallet not initialized"))?;
+    let seed = CASHU_SEED.get()
+        .ok_or_else(|| anyhow::anyhow!("Cashu wallet seed not initialized"))?;
+
+    let wallet = cdk::wallet::Wallet::new(mint_url, unit, db.clone(), seed, None)
+        .map_err(|e| anyhow::anyhow!("Failed to create wallet for mint {}: {}", mint_url, e))?;
+
+    Ok(wallet)
+}
+
+/// Mint URLs the wallet database has seen (any mint we have received from)
+pub async fn known_mints() -> anyhow::Result<Vec<String>> {
+    use cdk::cdk_database::WalletDatabase;
+
+    let db = CASHU_DB.get()
+        .ok_or_else(|| anyhow::anyhow!("Cashu wallet not initialized"))?;
+    let mints = db.get_mints().await
+        .map_err(|e| anyhow::anyhow!("Failed to list wallet mints: {}", e))?;
+
+    let mut urls: Vec<String> = mints.keys().map(|m| m.to_string()).collect();
+    urls.sort();
+    Ok(urls)
+}
+
+/// Convert an amount in the token's unit to msats
+fn to_msats(amount: u64, unit: &cdk::nuts::CurrencyUnit) -> anyhow::Result<u64> {
+    match unit {
+        cdk::nuts::CurrencyUnit::Sat => Ok(amount * MSAT_PER_SAT),
+        cdk::nuts::CurrencyUnit::Msat => Ok(amount),
+        unit => Err(anyhow::anyhow!("Unsupported token unit: {:?}", unit)),
+    }
+}
+
 // verify_cashu_token removed - ngx_l402 handles all payment verification
 // Payment validation now happens at nginx layer before requests reach Paygress
 
 /// Process a Cashu token and extract its total value in msats
 pub async fn extract_token_value(token_str: &str) -> anyhow::Result<u64> {
     use std::str::FromStr;
-    
+
     // Decode the token to get its value
     let token = cdk::nuts::Token::from_str(token_str)
         .map_err(|e| anyhow::anyhow!("Failed to decode Cashu token: {}", e))?;
-    
+
     // Check if the token is valid
     if token.proofs().is_empty() {
         return Err(anyhow::anyhow!("Token has no proofs"));
     }
-    
+
     // Calculate total token amount
-    let total_amount: u64 = token.proofs().iter().map(|p| { 
+    let total_amount: u64 = token.proofs().iter().map(|p| {
         let amt: u64 = p.amount.into();
         amt
     }).sum();
 
     // Unit handling
-    let total_amount_msats: u64 = match token.unit().unwrap_or(cdk::nuts::CurrencyUnit::Sat) {
-        cdk::nuts::CurrencyUnit::Sat => total_amount * MSAT_PER_SAT,
-        cdk::nuts::CurrencyUnit::Msat => total_amount,
-        unit => return Err(anyhow::anyhow!("Unsupported token unit: {:?}", unit)),
+    to_msats(total_amount, &token.unit().unwrap_or(cdk::nuts::CurrencyUnit::Sat))
+}
+
+/// Normalize a mint URL for comparison (case-insensitive, ignoring trailing slashes)
+fn normalize_mint_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_lowercase()
+}
+
+/// Whether two mint URLs refer to the same mint
+pub fn same_mint(a: &str, b: &str) -> bool {
+    normalize_mint_url(a) == normalize_mint_url(b)
+}
+
+/// Check that a token was issued by one of the accepted mints.
+///
+/// Returns a typed `mint_not_accepted` error listing the accepted mints on rejection,
+/// or `invalid_token` if the token cannot be decoded.
+pub fn check_token_mint(token_str: &str, accepted_mints: &[String]) -> Result<(), ErrorResponseContent> {
+    use std::str::FromStr;
+
+    let token = cdk::nuts::Token::from_str(token_str).map_err(|e| ErrorResponseContent {
+        error_type: "invalid_token".to_string(),
+        message: format!("Failed to decode Cashu token: {}", e),
+        details: None,
+        accepted_mints: vec![],
+        refund_token: None,
+    })?;
+
+    let mint_url = token.mint_url().map_err(|e| ErrorResponseContent {
+        error_type: "invalid_token".to_string(),
+        message: format!("Token has no mint URL: {}", e),
+        details: None,
+        accepted_mints: vec![],
+        refund_token: None,
+    })?.to_string();
+
+    if accepted_mints.iter().any(|m| same_mint(m, &mint_url)) {
+        return Ok(());
+    }
+
+    tracing::warn!("Rejected token from non-whitelisted mint: {}", mint_url);
+    Err(ErrorResponseContent {
+        error_type: "mint_not_accepted".to_string(),
+        message: format!("Tokens from mint {} are not accepted", mint_url),
+        details: Some(format!("Accepted mints: {}", accepted_mints.join(", "))),
+        accepted_mints: accepted_mints.to_vec(),
+        refund_token: None,
+    })
+}
+
+/// Receive a Cashu token into the local wallet by swapping its proofs at the issuing mint.
+///
+/// Returns the amount actually received in msats (after any mint input fees).
+/// Fails if the proofs were already spent, so a token can only ever pay once.
+pub async fn receive_token(token_str: &str) -> anyhow::Result<u64> {
+    use std::str::FromStr;
+
+    let token = cdk::nuts::Token::from_str(token_str)
+        .map_err(|e| anyhow::anyhow!("Failed to decode Cashu token: {}", e))?;
+
+    let mint_url = token.mint_url()
+        .map_err(|e| anyhow::anyhow!("Token has no mint URL: {}", e))?;
+    let unit = token.unit().unwrap_or(cdk::nuts::CurrencyUnit::Sat);
+
+    let wallet = wallet_for_mint(&mint_url.to_string(), unit.clone())?;
+
+    // Swap proofs with the mint; this is what actually transfers ownership
+    let received = wallet.receive(token_str, cdk::wallet::ReceiveOptions::default()).await?;
+    let received_msats = to_msats(received.into(), &unit)?;
+
+    tracing::info!("Received {} msats from mint {}", received_msats, mint_url);
+    Ok(received_msats)
+}
+
+/// Mint URL and unit a token was issued in (where any refund for it must come from)
+pub fn token_source(token_str: &str) -> anyhow::Result<(String, cdk::nuts::CurrencyUnit)> {
+    use std::str::FromStr;
+
+    let token = cdk::nuts::Token::from_str(token_str)
+        .map_err(|e| anyhow::anyhow!("Failed to decode Cashu token: {}", e))?;
+    let mint_url = token.mint_url()
+        .map_err(|e| anyhow::anyhow!("Token has no mint URL: {}", e))?;
+
+    Ok((mint_url.to_string(), token.unit().unwrap_or(cdk::nuts::CurrencyUnit::Sat)))
+}
+
+/// Create a refund token worth `amount_msats` at `mint_url` in `unit`.
+///
+/// Paid out of the local wallet, so it only succeeds if the wallet holds enough at that mint.
+/// Amounts below one unit (e.g. sub-sat remainders) cannot be refunded and yield `None`.
+pub async fn create_refund(mint_url: &str, unit: cdk::nuts::CurrencyUnit, amount_msats: u64) -> anyhow::Result<Option<String>> {
+    let amount = match unit {
+        cdk::nuts::CurrencyUnit::Sat => amount_msats / MSAT_PER_SAT,
+        cdk::nuts::CurrencyUnit::Msat => amount_msats,
+        ref unit => return Err(anyhow::anyhow!("Unsupported token unit: {:?}", unit)),
     };
-    
-    Ok(total_amount_msats)
+    if amount == 0 {
+        return Ok(None);
+    }
+
+    let wallet = wallet_for_mint(mint_url, unit)?;
+    let prepared = wallet.prepare_send(cdk::Amount::from(amount), cdk::wallet::SendOptions::default()).await?;
+    let refund = wallet.send(prepared, None).await?;
+
+    tracing::info!("Created refund token for {} msats at {}", amount_msats, mint_url);
+    Ok(Some(refund.to_string()))
+}
+
+/// Create a change token worth `amount_msats` at the same mint and unit as `paid_token`
+pub async fn create_refund_token(paid_token: &str, amount_msats: u64) -> anyhow::Result<Option<String>> {
+    let (mint_url, unit) = token_source(paid_token)?;
+    create_refund(&mint_url, unit, amount_msats).await
+}
+
+/// Best-effort refund: logs instead of failing, since the caller is usually already
+/// reporting another error (or a successful provision) to the user.
+pub async fn refund_or_log(paid_token: &str, amount_msats: u64) -> Option<String> {
+    if amount_msats == 0 {
+        return None;
+    }
+
+    match create_refund_token(paid_token, amount_msats).await {
+        Ok(refund) => refund,
+        Err(e) => {
+            tracing::warn!("Failed to create refund of {} msats: {}", amount_msats, e);
+            None
+        }
+    }
+}
+
+/// Best-effort refund of a token this service redeemed itself, capped at `received_msats`.
+///
+/// `None` means the token was not redeemed here (e.g. nginx already took it), so there is
+/// nothing of the payer's in the wallet to give back and no refund is made.
+pub async fn refund_received_or_log(paid_token: &str, received_msats: Option<u64>, amount_msats: u64) -> Option<String> {
+    match received_msats {
+        Some(received) => refund_or_log(paid_token, amount_msats.min(received)).await,
+        None => None,
+    }
+}
+
+/// Best-effort refund from a recorded payment source (`unit` as stored, e.g. "sat")
+pub async fn refund_at_mint_or_log(mint_url: &str, unit: &str, amount_msats: u64) -> Option<String> {
+    use std::str::FromStr;
+
+    if amount_msats == 0 || mint_url.is_empty() {
+        return None;
+    }
+
+    let unit = cdk::nuts::CurrencyUnit::from_str(unit).unwrap_or(cdk::nuts::CurrencyUnit::Sat);
+    match create_refund(mint_url, unit, amount_msats).await {
+        Ok(refund) => refund,
+        Err(e) => {
+            tracing::warn!("Failed to create refund of {} msats at {}: {}", amount_msats, mint_url, e);
+            None
+        }
+    }
+}
+
+/// Whether a redemption error means the token's proofs were already spent
+pub fn is_token_spent_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<cdk::Error>(),
+        Some(cdk::Error::TokenAlreadySpent)
+    )
+}
+
+/// Payment operations the Nostr provider performs on incoming tokens.
+///
+/// `CashuPayments` does them against real mints with the local wallet;
+/// `mock::MockPayments` fakes them so the provider can be tested offline.
+#[async_trait::async_trait]
+pub trait PaymentProcessor: Send + Sync {
+    /// Face value of a token in msats, without redeeming it
+    async fn extract_token_value(&self, token_str: &str) -> anyhow::Result<u64>;
+
+    /// Reject tokens not issued by one of `accepted_mints`
+    fn check_token_mint(&self, token_str: &str, accepted_mints: &[String]) -> Result<(), ErrorResponseContent>;
+
+    /// Redeem a token, returning the msats actually received
+    async fn receive_token(&self, token_str: &str) -> anyhow::Result<u64>;
+
+    /// Mint URL and unit (e.g. "sat") a token was issued in
+    fn token_source(&self, token_str: &str) -> anyhow::Result<(String, String)>;
+
+    /// Best-effort change token at the same mint as `paid_token`
+    async fn refund_or_log(&self, paid_token: &str, amount_msats: u64) -> Option<String>;
+
+    /// Best-effort refund from a recorded payment source
+    async fn refund_at_mint_or_log(&self, mint_url: &str, unit: &str, amount_msats: u64) -> Option<String>;
+}
+
+/// Payments through the Cashu wallet initialized by `initialize_cashu`
+pub struct CashuPayments;
+
+#[async_trait::async_trait]
+impl PaymentProcessor for CashuPayments {
+    async fn extract_token_value(&self, token_str: &str) -> anyhow::Result<u64> {
+        extract_token_value(token_str).await
+    }
+
+    fn check_token_mint(&self, token_str: &str, accepted_mints: &[String]) -> Result<(), ErrorResponseContent> {
+        check_token_mint(token_str, accepted_mints)
+    }
+
+    async fn receive_token(&self, token_str: &str) -> anyhow::Result<u64> {
+        receive_token(token_str).await
+    }
+
+    fn token_source(&self, token_str: &str) -> anyhow::Result<(String, String)> {
+        let (mint_url, unit) = token_source(token_str)?;
+        Ok((mint_url, unit.to_string()))
+    }
+
+    async fn refund_or_log(&self, paid_token: &str, amount_msats: u64) -> Option<String> {
+        refund_or_log(paid_token, amount_msats).await
+    }
+
+    async fn refund_at_mint_or_log(&self, mint_url: &str, unit: &str, amount_msats: u64) -> Option<String> {
+        refund_at_mint_or_log(mint_url, unit, amount_msats).await
+    }
 }
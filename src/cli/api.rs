@@ -20,8 +20,10 @@ pub struct SpawnResponse {
     pub ssh_username: Option<String>,
     pub expires_at: Option<String>,
     pub duration_seconds: Option<u64>,
+    #[allow(dead_code)]
     pub message: Option<String>,
     pub error: Option<String>,
+    pub refund_token: Option<String>,
 }
 
 /// Response from topup endpoint
@@ -33,6 +35,7 @@ pub struct TopupResponse {
     pub added_seconds: Option<u64>,
     pub message: Option<String>,
     pub error: Option<String>,
+    pub refund_token: Option<String>,
 }
 
 /// Response from status endpoint
@@ -46,6 +49,7 @@ pub struct StatusResponse {
     pub ssh_username: Option<String>,
     pub expires_at: Option<String>,
     pub time_remaining_seconds: Option<i64>,
+    #[allow(dead_code)]
     pub message: Option<String>,
     pub error: Option<String>,
 }
@@ -64,13 +68,17 @@ pub struct PodOffer {
 /// Response from offers endpoint
 #[derive(Debug, Deserialize)]
 pub struct OffersResponse {
+    #[serde(default)]
     pub success: bool,
+    #[serde(alias = "pod_specs")]
     pub offers: Option<Vec<PodOffer>>,
+    #[serde(alias = "whitelisted_mints")]
     pub mint_urls: Option<Vec<String>>,
     pub error: Option<String>,
 }
 
-/// Health check response
+/// Health check response (only its arrival is checked)
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct HealthResponse {
     pub status: String,
@@ -86,6 +94,10 @@ pub struct SpawnRequest {
     pub ssh_password: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cashu_token: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ssh_public_keys: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disable_password_auth: bool,
 }
 
 /// Topup request payload
@@ -96,6 +108,24 @@ pub struct TopupRequest {
     pub cashu_token: Option<String>,
 }
 
+/// Terminate request payload
+#[derive(Debug, Serialize)]
+pub struct TerminateRequest {
+    pub pod_npub: String,
+    pub ssh_password: String,
+}
+
+/// Response from terminate endpoint
+#[derive(Debug, Deserialize)]
+pub struct TerminateResponse {
+    pub success: bool,
+    pub message: Option<String>,
+    pub unused_seconds: Option<u64>,
+    pub refund_msats: Option<u64>,
+    pub refund_token: Option<String>,
+    pub error: Option<String>,
+}
+
 /// Status request payload
 #[derive(Debug, Serialize)]
 pub struct PodStatusRequest {
@@ -199,6 +229,27 @@ impl PaygressClient {
             .map_err(|e| anyhow!("Failed to parse response: {}", e))
     }
 
+    /// Terminate a pod early
+    pub async fn terminate_pod(&self, request: TerminateRequest) -> Result<TerminateResponse> {
+        let url = format!("{}/pods/terminate", self.base_url);
+
+        let response = self.client
+            .post(&url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to connect to server: {}", e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Server returned error {}: {}", status, body));
+        }
+
+        response.json().await
+            .map_err(|e| anyhow!("Failed to parse response: {}", e))
+    }
+
     /// Get pod status
     pub async fn get_pod_status(&self, pod_id: &str) -> Result<StatusResponse> {
         let url = format!("{}/pods/status", self.base_url);
@@ -226,7 +226,7 @@ pub async fn execute(args: BootstrapArgs, verbose: bool) -> Result<()> {
                 println!("{}", "Not found".yellow());
                 println!();
                 println!("  {} Installing Proxmox VE...", "⚙".yellow());
-                println!("  {} This may take 10-15 minutes", "⏳".to_string());
+                println!("  ⏳ This may take 10-15 minutes");
                 println!();
                 
                 // Run Proxmox installation script
@@ -438,8 +438,8 @@ pub async fn execute(args: BootstrapArgs, verbose: bool) -> Result<()> {
   "public_ip": "{}",
   "capabilities": ["lxc", "vm"],
   "specs": [
-    {{"id": "basic", "name": "Basic", "description": "1 vCPU, 1GB RAM", "cpu_millicores": 1000, "memory_mb": 1024, "rate_msats_per_sec": 50}},
-    {{"id": "standard", "name": "Standard", "description": "2 vCPU, 2GB RAM", "cpu_millicores": 2000, "memory_mb": 2048, "rate_msats_per_sec": 100}}
+    {{"id": "basic", "name": "Basic", "description": "1 vCPU, 1GB RAM", "cpu_millicores": 1000, "memory_mb": 1024, "storage_gb": 10, "rate_msats_per_sec": 50}},
+    {{"id": "standard", "name": "Standard", "description": "2 vCPU, 2GB RAM", "cpu_millicores": 2000, "memory_mb": 2048, "storage_gb": 20, "rate_msats_per_sec": 100}}
   ],
   "whitelisted_mints": ["{}"],
   "heartbeat_interval_secs": 60,
@@ -546,7 +546,7 @@ WantedBy=multi-user.target
     if !use_lxd {
         println!("  Proxmox UI:    https://{}:8006", args.host);
         println!();
-        println!("  {} Next Steps:", "📋".to_string());
+        println!("  📋 Next Steps:");
         println!("    1. SSH into {} and get your API token", args.host);
         println!("    2. Update the config with the token secret");
         println!("    3. Start the service: systemctl start paygress-provider");
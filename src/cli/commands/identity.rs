@@ -4,7 +4,7 @@ use anyhow::Result;
 use colored::Colorize;
 use nostr_sdk::{Keys, ToBech32};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub const DEFAULT_RELAYS: &[&str] = &[
     "wss://relay.damus.io",
@@ -22,16 +22,22 @@ pub fn parse_relays(relays: Option<String>) -> Vec<String> {
     }
 }
 
-pub fn get_or_create_identity(explicit_key: Option<String>) -> Result<String> {
-    if let Some(key) = explicit_key {
-        return Ok(key);
-    }
-
+/// Local Paygress state directory (~/.paygress), created on first use
+pub fn paygress_dir() -> Result<PathBuf> {
     let home = std::env::var("HOME").map_err(|_| anyhow::anyhow!("Could not determine home directory"))?;
     let paygress_dir = Path::new(&home).join(".paygress");
     if !paygress_dir.exists() {
         std::fs::create_dir_all(&paygress_dir)?;
     }
+    Ok(paygress_dir)
+}
+
+pub fn get_or_create_identity(explicit_key: Option<String>) -> Result<String> {
+    if let Some(key) = explicit_key {
+        return Ok(key);
+    }
+
+    let paygress_dir = paygress_dir()?;
 
     let identity_file = paygress_dir.join("identity");
     if identity_file.exists() {
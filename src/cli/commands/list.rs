@@ -27,6 +27,14 @@ pub struct ListArgs {
     #[arg(long)]
     pub capability: Option<String>,
 
+    /// Only show providers offering at least this much disk (GB)
+    #[arg(long)]
+    pub min_storage: Option<u64>,
+
+    /// Only show providers with room for at least this many more workloads of some tier
+    #[arg(long)]
+    pub min_slots: Option<u32>,
+
     /// Sort by (price, uptime, capacity, jobs)
     #[arg(long, default_value = "price")]
     pub sort: String,
@@ -90,6 +98,8 @@ async fn execute_nostr_list(args: ListArgs, verbose: bool) -> Result<()> {
         min_uptime: None,
         min_memory_mb: None,
         min_cpu: None,
+        min_storage_gb: args.min_storage,
+        min_available_slots: args.min_slots,
     };
 
     let mut providers = client.list_providers(Some(filter)).await?;
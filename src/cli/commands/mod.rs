@@ -5,6 +5,8 @@ pub mod list;
 pub mod spawn;
 pub mod topup;
 pub mod status;
+pub mod stop;
 pub mod provider;
 pub mod bootstrap;
 pub mod system;
+pub mod wallet;
@@ -9,7 +9,9 @@ use nostr_sdk::ToBech32;
 use std::process::Command;
 
 use paygress::provider::{ProviderConfig, ProviderService, load_config, save_config};
-use paygress::nostr::PodSpec;
+use paygress::nostr::{PodSpec, WorkloadKind};
+use paygress::images::default_catalog;
+use paygress::wallet::{admin_socket_path, request_from_service, CashuWallet, WalletReply, WalletRequest};
 
 const CONFIG_PATH: &str = "/etc/paygress/provider-config.json";
 
@@ -20,6 +22,7 @@ pub struct ProviderArgs {
     pub action: ProviderAction,
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum ProviderAction {
     /// Initial setup - configure Proxmox connection and provider settings
@@ -39,6 +42,9 @@ pub enum ProviderAction {
 
     /// Setup WireGuard VPN tunnel for providers behind NAT
     Tunnel(TunnelArgs),
+
+    /// Manage earnings held in the provider's Cashu wallet
+    Wallet(WalletArgs),
 }
 
 #[derive(Args)]
@@ -133,6 +139,49 @@ pub struct TunnelArgs {
     pub interface: String,
 }
 
+#[derive(Args)]
+pub struct WalletArgs {
+    /// Path to configuration file (used to locate the wallet database)
+    #[arg(long, default_value = "/etc/paygress/provider-config.json")]
+    pub config: String,
+
+    #[command(subcommand)]
+    pub action: WalletAction,
+}
+
+#[derive(Subcommand)]
+pub enum WalletAction {
+    /// Show balance per mint
+    Balance,
+
+    /// Export ecash as a Cashu token
+    Send {
+        /// Amount in sats
+        amount: u64,
+
+        /// Mint to send from (defaults to the mint with the largest balance)
+        #[arg(long)]
+        mint: Option<String>,
+    },
+
+    /// Withdraw to Lightning by paying a BOLT11 invoice
+    Melt {
+        /// BOLT11 invoice to pay
+        invoice: String,
+
+        /// Mint to pay from (defaults to the first mint that can cover the invoice)
+        #[arg(long)]
+        mint: Option<String>,
+    },
+
+    /// Show transaction history
+    History {
+        /// Maximum number of entries to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+}
+
 pub async fn execute(args: ProviderArgs, verbose: bool) -> Result<()> {
     match args.action {
         ProviderAction::Setup(setup_args) => execute_setup(setup_args, verbose).await,
@@ -141,10 +190,11 @@ pub async fn execute(args: ProviderArgs, verbose: bool) -> Result<()> {
         ProviderAction::Status => execute_status(verbose).await,
         ProviderAction::Config(config_args) => execute_config(config_args, verbose).await,
         ProviderAction::Tunnel(tunnel_args) => execute_tunnel(tunnel_args, verbose).await,
+        ProviderAction::Wallet(wallet_args) => execute_wallet(wallet_args, verbose).await,
     }
 }
 
-async fn execute_setup(args: SetupArgs, verbose: bool) -> Result<()> {
+async fn execute_setup(args: SetupArgs, _verbose: bool) -> Result<()> {
     println!("{}", "🔧 Paygress Provider Setup".blue().bold());
     println!("{}", "━".repeat(50).blue());
     println!();
@@ -172,26 +222,38 @@ async fn execute_setup(args: SetupArgs, verbose: bool) -> Result<()> {
         PodSpec {
             id: "basic".to_string(),
             name: "Basic".to_string(),
-            description: "1 vCPU, 1GB RAM - Great for testing".to_string(),
+            description: "1 vCPU, 1GB RAM, 10GB disk - Great for testing".to_string(),
             cpu_millicores: 1000,
             memory_mb: 1024,
+            storage_gb: 10,
+            disk_iops: None,
             rate_msats_per_sec: 50,
+            kind: WorkloadKind::Container,
+            network: Default::default(),
         },
         PodSpec {
             id: "standard".to_string(),
             name: "Standard".to_string(),
-            description: "2 vCPU, 2GB RAM - General purpose".to_string(),
+            description: "2 vCPU, 2GB RAM, 20GB disk - General purpose".to_string(),
             cpu_millicores: 2000,
             memory_mb: 2048,
+            storage_gb: 20,
+            disk_iops: None,
             rate_msats_per_sec: 100,
+            kind: WorkloadKind::Container,
+            network: Default::default(),
         },
         PodSpec {
             id: "premium".to_string(),
             name: "Premium".to_string(),
-            description: "4 vCPU, 4GB RAM - High performance".to_string(),
+            description: "4 vCPU, 4GB RAM, 40GB disk - High performance".to_string(),
             cpu_millicores: 4000,
             memory_mb: 4096,
+            storage_gb: 40,
+            disk_iops: None,
             rate_msats_per_sec: 200,
+            kind: WorkloadKind::Container,
+            network: Default::default(),
         },
     ];
 
@@ -224,7 +286,8 @@ async fn execute_setup(args: SetupArgs, verbose: bool) -> Result<()> {
         }
     };
 
-    // Create configuration
+    // Create configuration (with the built-in image catalog written out so operators can extend it)
+    let images = default_catalog(&args.template);
     let config = ProviderConfig {
         backend_type: Default::default(),
         public_ip,
@@ -235,8 +298,17 @@ async fn execute_setup(args: SetupArgs, verbose: bool) -> Result<()> {
         proxmox_storage: args.storage,
         proxmox_template: args.template,
         proxmox_bridge: args.bridge,
+        proxmox_port_forwarding: true,
         vmid_range_start: 1000,
         vmid_range_end: 1999,
+        lxd_socket_path: None,
+        lxd_url: None,
+        lxd_client_cert: None,
+        lxd_client_key: None,
+        lxd_server_cert: None,
+        docker_socket_path: None,
+        kubernetes_namespace: None,
+        kubernetes_networking: Default::default(),
         nostr_private_key: nostr_key,
         nostr_relays: vec![
             "wss://relay.damus.io".to_string(),
@@ -254,6 +326,10 @@ async fn execute_setup(args: SetupArgs, verbose: bool) -> Result<()> {
         tunnel_interface: None,
         ssh_port_start: None,
         ssh_port_end: None,
+        cashu_db_path: "/var/lib/paygress/cashu.db".to_string(),
+        ledger_path: "/var/lib/paygress/ledger.redb".to_string(),
+        images,
+        overcommit: Default::default(),
     };
 
     // Save configuration
@@ -300,7 +376,7 @@ async fn execute_setup(args: SetupArgs, verbose: bool) -> Result<()> {
     Ok(())
 }
 
-async fn execute_start(args: StartArgs, verbose: bool) -> Result<()> {
+async fn execute_start(args: StartArgs, _verbose: bool) -> Result<()> {
     println!("{}", "🚀 Starting Paygress Provider".blue().bold());
     println!();
 
@@ -319,6 +395,20 @@ async fn execute_start(args: StartArgs, verbose: bool) -> Result<()> {
             println!("  Backend:  LXD");
             println!("  Storage:  {}", config.proxmox_storage); // Used as pool name
         }
+        paygress::provider::BackendType::LxdApi => {
+            println!("  Backend:  LXD API");
+            println!("  Server:   {}", config.lxd_url.as_deref().unwrap_or("local socket"));
+            println!("  Storage:  {}", config.proxmox_storage); // Used as pool name
+        }
+        paygress::provider::BackendType::Docker => {
+            println!("  Backend:  Docker");
+            println!("  Socket:   {}", config.docker_socket_path.as_deref().unwrap_or(paygress::docker::DEFAULT_SOCKET_PATH));
+        }
+        paygress::provider::BackendType::Kubernetes => {
+            println!("  Backend:  Kubernetes");
+            println!("  Namespace: {}", config.kubernetes_namespace.as_deref().unwrap_or(paygress::kubernetes::DEFAULT_NAMESPACE));
+            println!("  Network:  {:?}", config.kubernetes_networking);
+        }
     }
     println!();
 
@@ -388,18 +478,18 @@ async fn execute_status(_verbose: bool) -> Result<()> {
             println!("  Proxmox URL:    {}", config.proxmox_url);
             println!("  Node:           {}", config.proxmox_node);
             println!();
-            println!("  {} Tiers configured:", "📦".to_string());
+            println!("  📦 Tiers configured:");
             for spec in &config.specs {
                 println!("    • {} - {} msat/sec", spec.name, spec.rate_msats_per_sec);
             }
             println!();
-            println!("  {} Accepted mints:", "💰".to_string());
+            println!("  💰 Accepted mints:");
             for mint in &config.whitelisted_mints {
                 println!("    • {}", mint);
             }
             if config.tunnel_enabled {
                 println!();
-                println!("  {} Tunnel:", "🔒".to_string());
+                println!("  🔒 Tunnel:");
                 println!("    Interface: {}", config.tunnel_interface.as_deref().unwrap_or("wg0"));
                 println!("    Public IP: {}", config.public_ip);
                 if let (Some(ps), Some(pe)) = (config.ssh_port_start, config.ssh_port_end) {
@@ -444,6 +534,85 @@ async fn execute_config(args: ConfigArgs, _verbose: bool) -> Result<()> {
     Ok(())
 }
 
+async fn execute_wallet(args: WalletArgs, _verbose: bool) -> Result<()> {
+    let config = load_config(&args.config)?;
+
+    let (request, limit) = match args.action {
+        WalletAction::Balance => (WalletRequest::Balance, 0),
+        WalletAction::Send { amount, mint } => (WalletRequest::Send { amount_sats: amount, mint }, 0),
+        WalletAction::Melt { invoice, mint } => {
+            println!("  {} Paying invoice...", "⚙".yellow());
+            (WalletRequest::Melt { invoice, mint }, 0)
+        }
+        WalletAction::History { limit } => (WalletRequest::History, limit),
+    };
+
+    // A running provider holds the database's lock, so ask it; otherwise open the wallet here
+    let socket = admin_socket_path(&config.cashu_db_path);
+    let reply = match request_from_service(&socket, &request).await? {
+        Some(reply) => reply,
+        None => {
+            let wallet = CashuWallet::open(&config.cashu_db_path).await?;
+            request.execute(&wallet).await?
+        }
+    };
+
+    match reply {
+        WalletReply::Balance { balances, total_msats } => {
+            println!("{}", "💰 Provider Wallet".blue().bold());
+            println!("{}", "━".repeat(50).blue());
+            println!();
+
+            if balances.is_empty() {
+                println!("  {} Wallet is empty.", "⚠".yellow());
+            }
+            for balance in &balances {
+                println!("  {} {} {}", balance.mint_url, balance.amount.to_string().green(), balance.unit);
+            }
+            println!();
+            println!("  Total: {} msats", total_msats.to_string().yellow());
+        }
+        WalletReply::Token { token, amount_sats } => {
+            println!("  {} Exported {} sats", "✓".green(), amount_sats);
+            println!();
+            println!("{}", token);
+        }
+        WalletReply::Melt { result } => {
+            if result.paid {
+                println!("  {} Paid {} sats (fee {} sats) from {}",
+                    "✓".green(), result.amount_sats, result.fee_paid_sats, result.mint_url);
+                if let Some(preimage) = result.preimage {
+                    println!("      Preimage: {}", preimage);
+                }
+            } else {
+                println!("  {} Payment pending at {}, check history later", "⚠".yellow(), result.mint_url);
+            }
+        }
+        WalletReply::History { transactions: history } => {
+            println!("{}", "📜 Wallet History".blue().bold());
+            println!("{}", "━".repeat(50).blue());
+            println!();
+
+            if history.is_empty() {
+                println!("  No transactions yet.");
+            }
+            for tx in history.iter().take(limit) {
+                let when = chrono::DateTime::from_timestamp(tx.timestamp as i64, 0)
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default();
+                let amount = if tx.direction == "incoming" {
+                    format!("+{}", tx.amount).green()
+                } else {
+                    format!("-{}", tx.amount).red()
+                };
+                println!("  {}  {:>10} {}  {}", when, amount, tx.unit, tx.mint_url);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Check if the current process is running as root (uid 0).
 fn nix_is_root() -> bool {
     Command::new("id").arg("-u").output()
@@ -649,8 +818,8 @@ fn parse_wg_config(config: &str) -> Option<(String, Option<u16>, Option<u16>)> {
         .find(|l| l.contains("Public Ports:") || l.contains("Port Range:"))
         .and_then(|l| {
             // Extract "11000-11999" from the line
-            let re_part = l.split(':').last()?;
-            let range_str = re_part.trim().split(':').last()?.trim();
+            let re_part = l.split(':').next_back()?;
+            let range_str = re_part.trim().split(':').next_back()?.trim();
             let mut parts = range_str.split('-');
             let start: u16 = parts.next()?.trim().parse().ok()?;
             let end: u16 = parts.next()?.trim().parse().ok()?;
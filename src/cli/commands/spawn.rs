@@ -9,11 +9,14 @@ use clap::Args;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
 use rand::Rng;
+use std::path::PathBuf;
 
 use super::identity::{parse_relays, get_or_create_identity};
+use super::wallet::{reclaim_payment, store_refund, token_or_pay};
 use crate::api::{PaygressClient, SpawnRequest};
 use paygress::discovery::DiscoveryClient;
-use paygress::nostr::{EncryptedSpawnPodRequest, AccessDetailsContent, ErrorResponseContent};
+use paygress::provisioning::validate_credentials;
+use paygress::nostr::{validate_ssh_public_keys, EncryptedSpawnPodRequest, AccessDetailsContent, ErrorResponseContent};
 
 fn generate_password(len: usize) -> String {
     const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
@@ -38,11 +41,15 @@ pub struct SpawnArgs {
     #[arg(short, long, default_value = "basic")]
     pub tier: String,
 
-    /// Cashu token for payment
+    /// Cashu token for payment (omit to pay for --duration from the local wallet)
     #[arg(short = 'k', long)]
-    pub token: String,
+    pub token: Option<String>,
 
-    /// Container image (HTTP mode only)
+    /// How long to run the workload, e.g. 30m, 2h, 1d (paid from the local wallet)
+    #[arg(short, long)]
+    pub duration: Option<String>,
+
+    /// Image to run (Nostr mode: a name from the provider's catalog, e.g. ubuntu-22.04 or debian-12)
     #[arg(short, long, default_value = "ubuntu:22.04")]
     pub image: String,
 
@@ -54,6 +61,14 @@ pub struct SpawnArgs {
     #[arg(short = 'p', long)]
     pub ssh_pass: Option<String>,
 
+    /// SSH public key file to install (e.g. ~/.ssh/id_ed25519.pub); repeatable
+    #[arg(long = "ssh-key", value_name = "PATH")]
+    pub ssh_keys: Vec<PathBuf>,
+
+    /// Disable password login so only the --ssh-key keys can log in
+    #[arg(long, requires = "ssh_keys")]
+    pub no_password: bool,
+
     /// Your Nostr private key (nsec) - uses ~/.paygress/identity if not provided
     #[arg(long)]
     pub nostr_key: Option<String>,
@@ -67,21 +82,39 @@ pub async fn execute(mut args: SpawnArgs, verbose: bool) -> Result<()> {
     // Auto-generate SSH credentials if not provided
     let ssh_user = args.ssh_user.take().unwrap_or_else(|| "user".to_string());
     let ssh_pass = args.ssh_pass.take().unwrap_or_else(|| generate_password(16));
+    validate_credentials(&ssh_user, &ssh_pass)?;
+    let ssh_public_keys = read_ssh_keys(&args.ssh_keys)?;
+    validate_ssh_public_keys(&ssh_public_keys, args.no_password)
+        .map_err(|e| anyhow::anyhow!(e))?;
 
     // If --provider is given, use Nostr mode
     if args.provider.is_some() {
         let provider = args.provider.clone().unwrap();
-        return execute_nostr_spawn(provider, args, ssh_user, ssh_pass, verbose).await;
+        return execute_nostr_spawn(provider, args, ssh_user, ssh_pass, ssh_public_keys, verbose).await;
     }
 
     // Otherwise require --server for HTTP mode
     let server = args.server.clone()
         .ok_or_else(|| anyhow::anyhow!("Either --provider (Nostr) or --server (HTTP) is required"))?;
 
-    execute_http_spawn(&server, args, ssh_user, ssh_pass, verbose).await
+    execute_http_spawn(&server, args, ssh_user, ssh_pass, ssh_public_keys, verbose).await
 }
 
-async fn execute_http_spawn(server: &str, args: SpawnArgs, ssh_user: String, ssh_pass: String, verbose: bool) -> Result<()> {
+/// Read OpenSSH public keys from files (one key per non-empty, non-comment line)
+fn read_ssh_keys(paths: &[PathBuf]) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for path in paths {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read SSH key {}: {}", path.display(), e))?;
+        keys.extend(contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string));
+    }
+    Ok(keys)
+}
+
+async fn execute_http_spawn(server: &str, args: SpawnArgs, ssh_user: String, ssh_pass: String, ssh_public_keys: Vec<String>, verbose: bool) -> Result<()> {
     if verbose {
         println!("{} Spawning pod via HTTP...", "->".blue());
         println!("  Server: {}", server);
@@ -89,33 +122,62 @@ async fn execute_http_spawn(server: &str, args: SpawnArgs, ssh_user: String, ssh
         println!("  Image: {}", args.image);
     }
 
+    let client = PaygressClient::new(server);
+
+    // Check the server is up before any payment leaves the wallet
+    client.health().await?;
+
+    // Without an explicit token, price the requested duration from the server's offers
+    let paid_from_wallet = args.token.is_none();
+    let cashu_token = match args.token {
+        Some(token) => token,
+        None => {
+            let offers = client.get_offers().await?;
+            let offer = offers.offers.unwrap_or_default().into_iter()
+                .find(|o| o.id == args.tier)
+                .ok_or_else(|| anyhow::anyhow!("Tier '{}' not available on this server", args.tier))?;
+            token_or_pay(None, args.duration.as_deref(), offer.rate_msats_per_sec, &offers.mint_urls.unwrap_or_default()).await?
+        }
+    };
+
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.blue} {msg}")
             .unwrap()
     );
-    spinner.set_message("Connecting to Paygress server...");
-    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
-
-    let client = PaygressClient::new(server);
-
-    spinner.set_message("Checking server health...");
-    client.health().await?;
-
     spinner.set_message("Spawning pod with Cashu payment...");
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
 
     let request = SpawnRequest {
         pod_spec_id: args.tier,
         pod_image: args.image,
         ssh_username: ssh_user,
         ssh_password: ssh_pass,
-        cashu_token: Some(args.token),
+        cashu_token: Some(cashu_token.clone()),
+        ssh_public_keys,
+        disable_password_auth: args.no_password,
     };
 
-    let response = client.spawn_pod(request).await?;
+    let response = client.spawn_pod(request).await;
     spinner.finish_and_clear();
 
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            if paid_from_wallet {
+                reclaim_payment(&cashu_token).await;
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(refund) = &response.refund_token {
+        store_refund(refund).await;
+    } else if !response.success && paid_from_wallet {
+        reclaim_payment(&cashu_token).await;
+    }
+
     if response.success {
         println!("{}", "Pod spawned successfully!".green().bold());
         println!();
@@ -153,7 +215,7 @@ async fn execute_http_spawn(server: &str, args: SpawnArgs, ssh_user: String, ssh
     Ok(())
 }
 
-async fn execute_nostr_spawn(provider_npub: String, args: SpawnArgs, ssh_user: String, ssh_pass: String, verbose: bool) -> Result<()> {
+async fn execute_nostr_spawn(provider_npub: String, args: SpawnArgs, ssh_user: String, ssh_pass: String, ssh_public_keys: Vec<String>, _verbose: bool) -> Result<()> {
     println!("{}", "Spawning Workload".blue().bold());
     println!("{}", "-".repeat(50).blue());
     println!();
@@ -187,26 +249,46 @@ async fn execute_nostr_spawn(provider_npub: String, args: SpawnArgs, ssh_user: S
 
     println!("  {} Found tier: {} ({} msat/sec)", "OK".green(), spec.name, spec.rate_msats_per_sec);
 
+    let paid_from_wallet = args.token.is_none();
+    let cashu_token = token_or_pay(
+        args.token.clone(),
+        args.duration.as_deref(),
+        spec.rate_msats_per_sec,
+        &provider.whitelisted_mints,
+    ).await?;
+
     // Build and send spawn request
     println!("  {} user: {}, pass: {}", "SSH Credentials:".bold(), ssh_user.cyan(), ssh_pass.cyan());
+    if !ssh_public_keys.is_empty() {
+        println!("  {} {} key(s){}", "SSH Keys:".bold(), ssh_public_keys.len(),
+            if args.no_password { ", password login disabled" } else { "" });
+    }
 
     let request = EncryptedSpawnPodRequest {
-        cashu_token: args.token.clone(),
+        cashu_token: cashu_token.clone(),
         pod_spec_id: Some(args.tier.clone()),
         pod_image: args.image,
         ssh_username: ssh_user,
         ssh_password: ssh_pass,
+        ssh_public_keys,
+        disable_password_auth: args.no_password,
     };
 
     println!();
     print!("  Sending spawn request... ");
 
     let request_json = serde_json::to_string(&request)?;
-    let _event_id = client.nostr().send_encrypted_private_message(
+    if let Err(e) = client.nostr().send_encrypted_private_message(
         &provider.npub,
         request_json,
         "nip04",
-    ).await?;
+    ).await {
+        println!("{}", "FAILED".red());
+        if paid_from_wallet {
+            reclaim_payment(&cashu_token).await;
+        }
+        return Err(e);
+    }
 
     println!("{}", "SENT".green());
     println!();
@@ -228,6 +310,10 @@ async fn execute_nostr_spawn(provider_npub: String, args: SpawnArgs, ssh_user: S
                 for inst in access.instructions {
                     println!("  - {}", inst);
                 }
+                if let Some(refund) = &access.refund_token {
+                    println!();
+                    store_refund(refund).await;
+                }
             } else if let Ok(err) = serde_json::from_str::<ErrorResponseContent>(&response.content) {
                 println!("{}", "Provider Error".red().bold());
                 println!();
@@ -236,6 +322,14 @@ async fn execute_nostr_spawn(provider_npub: String, args: SpawnArgs, ssh_user: S
                 if let Some(details) = err.details {
                     println!("  Details: {}", details);
                 }
+                // Without a refund the provider rejected the request before redeeming the token
+                if let Some(refund) = &err.refund_token {
+                    println!();
+                    store_refund(refund).await;
+                } else if paid_from_wallet {
+                    println!();
+                    reclaim_payment(&cashu_token).await;
+                }
             } else {
                 println!("{}", "Received Unknown Response".yellow().bold());
                 println!();
@@ -248,6 +342,10 @@ async fn execute_nostr_spawn(provider_npub: String, args: SpawnArgs, ssh_user: S
             println!("  {} {}", "Warning:".yellow(), e.to_string().yellow());
             println!();
             println!("The request was sent, but the provider didn't respond in time.");
+            if paid_from_wallet {
+                // Taking the payment back also stops a late provider from provisioning with it
+                reclaim_payment(&cashu_token).await;
+            }
             println!("You may check your status later with: paygress-cli status --pod-id <ID> --provider <npub>");
         }
     }
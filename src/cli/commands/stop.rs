@@ -0,0 +1,148 @@
+// Stop command - Terminate a workload early and get unused time refunded
+//
+// Unified command that works in both modes:
+//   - Nostr mode (--provider): sends an encrypted terminate request to the provider
+//   - HTTP mode (--server): calls a Paygress HTTP server (authenticated by the SSH password)
+
+use anyhow::Result;
+use clap::Args;
+use colored::Colorize;
+
+use super::identity::{parse_relays, get_or_create_identity};
+use super::wallet::store_refund;
+use crate::api::{PaygressClient, TerminateRequest};
+use paygress::discovery::DiscoveryClient;
+use paygress::nostr::{ErrorResponseContent, TerminateRequestContent, TerminateResponseContent};
+
+#[derive(Args)]
+pub struct StopArgs {
+    /// Pod/workload ID to terminate
+    #[arg(short, long)]
+    pub pod_id: String,
+
+    /// Provider npub (Nostr mode)
+    #[arg(long)]
+    pub provider: Option<String>,
+
+    /// HTTP server URL (e.g., http://localhost:8080)
+    #[arg(long)]
+    pub server: Option<String>,
+
+    /// SSH password of the pod (HTTP mode only, proves ownership)
+    #[arg(long)]
+    pub ssh_pass: Option<String>,
+
+    /// Your Nostr private key (nsec) - uses ~/.paygress/identity if not provided
+    #[arg(long)]
+    pub nostr_key: Option<String>,
+
+    /// Custom Nostr relays (comma-separated)
+    #[arg(long)]
+    pub relays: Option<String>,
+}
+
+pub async fn execute(args: StopArgs, verbose: bool) -> Result<()> {
+    if args.provider.is_some() {
+        let provider = args.provider.clone().unwrap();
+        return execute_nostr_stop(provider, args, verbose).await;
+    }
+
+    let server = args.server.clone()
+        .ok_or_else(|| anyhow::anyhow!("Either --provider (Nostr) or --server (HTTP) is required"))?;
+
+    execute_http_stop(&server, args, verbose).await
+}
+
+async fn execute_http_stop(server: &str, args: StopArgs, verbose: bool) -> Result<()> {
+    if verbose {
+        println!("{} Terminating pod via HTTP...", "->".blue());
+        println!("  Server: {}", server);
+        println!("  Pod ID: {}", args.pod_id);
+    }
+
+    let ssh_password = args.ssh_pass
+        .ok_or_else(|| anyhow::anyhow!("--ssh-pass is required to terminate a pod over HTTP"))?;
+
+    let client = PaygressClient::new(server);
+    let response = client.terminate_pod(TerminateRequest {
+        pod_npub: args.pod_id.clone(),
+        ssh_password,
+    }).await?;
+
+    if !response.success {
+        let error_msg = response.error.or(response.message).unwrap_or_else(|| "Unknown error".to_string());
+        return Err(anyhow::anyhow!("Failed to terminate pod: {}", error_msg));
+    }
+
+    println!("{}", "Pod terminated".green().bold());
+    if let Some(unused) = response.unused_seconds {
+        println!("  {} {}s ({} msats)", "Unused:".bold(), unused, response.refund_msats.unwrap_or(0));
+    }
+    if let Some(refund) = &response.refund_token {
+        store_refund(refund).await;
+    }
+
+    Ok(())
+}
+
+async fn execute_nostr_stop(provider_npub: String, args: StopArgs, _verbose: bool) -> Result<()> {
+    println!("{}", "Terminating Workload".blue().bold());
+    println!("{}", "-".repeat(50).blue());
+    println!();
+
+    let relays = parse_relays(args.relays);
+    let nostr_key = get_or_create_identity(args.nostr_key)?;
+
+    let client = DiscoveryClient::new_with_key(relays, nostr_key).await?;
+
+    println!("  Pod ID:   {}", args.pod_id.cyan());
+    println!("  Provider: {}", provider_npub);
+    println!();
+
+    let request = TerminateRequestContent {
+        pod_id: args.pod_id.clone(),
+        terminate: true,
+    };
+
+    print!("  Sending terminate request... ");
+
+    let request_json = serde_json::to_string(&request)?;
+    client.nostr().send_encrypted_private_message(
+        &provider_npub,
+        request_json,
+        "nip04",
+    ).await?;
+
+    println!("{}", "SENT".green());
+    println!();
+    println!("  Waiting for provider response (timeout: 60s)...");
+
+    match client.nostr().wait_for_decrypted_message(&provider_npub, 60).await {
+        Ok(response) => {
+            println!();
+
+            if let Ok(resp) = serde_json::from_str::<TerminateResponseContent>(&response.content) {
+                println!("{}", "Workload terminated".green().bold());
+                println!("  {} {}s ({} msats)", "Unused:".bold(), resp.unused_seconds, resp.refund_msats);
+                println!("  {}", resp.message);
+                if let Some(refund) = &resp.refund_token {
+                    store_refund(refund).await;
+                }
+            } else if let Ok(err) = serde_json::from_str::<ErrorResponseContent>(&response.content) {
+                println!("{}", "Terminate failed".red().bold());
+                println!("  Type:    {}", err.error_type);
+                println!("  Message: {}", err.message);
+            } else {
+                println!("Provider response: {}", response.content);
+            }
+        }
+        Err(e) => {
+            println!();
+            println!("  {} {}", "Warning:".yellow(), e.to_string().yellow());
+            println!("The terminate request was sent but the provider didn't respond in time.");
+            println!("Check status with: paygress-cli status --pod-id {} --provider {}", args.pod_id, provider_npub);
+        }
+    }
+
+    Ok(())
+}
@@ -10,8 +10,10 @@ use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
 
 use super::identity::{parse_relays, get_or_create_identity};
+use super::wallet::{reclaim_payment, store_refund, token_or_pay};
 use crate::api::{PaygressClient, TopupRequest};
 use paygress::discovery::DiscoveryClient;
+use paygress::nostr::{EncryptedTopUpPodRequest, ErrorResponseContent, TopUpResponseContent};
 
 #[derive(Args)]
 pub struct TopupArgs {
@@ -19,9 +21,17 @@ pub struct TopupArgs {
     #[arg(short, long)]
     pub pod_id: String,
 
-    /// Cashu token for payment
+    /// Cashu token for payment (omit to pay for --duration from the local wallet)
     #[arg(short = 'k', long)]
-    pub token: String,
+    pub token: Option<String>,
+
+    /// Extra time to buy, e.g. 30m, 2h, 1d (paid from the local wallet)
+    #[arg(short, long, requires = "tier")]
+    pub duration: Option<String>,
+
+    /// Tier the workload was spawned with, used to price --duration
+    #[arg(short, long)]
+    pub tier: Option<String>,
 
     /// Provider npub (Nostr mode) - if omitted, uses --server for HTTP mode
     #[arg(long)]
@@ -52,6 +62,11 @@ pub async fn execute(args: TopupArgs, verbose: bool) -> Result<()> {
     execute_http_topup(&server, args, verbose).await
 }
 
+// --duration requires --tier, so without a tier there is nothing to pay with
+fn missing_payment() -> anyhow::Error {
+    anyhow::anyhow!("Provide either --token or --duration with the workload's --tier")
+}
+
 async fn execute_http_topup(server: &str, args: TopupArgs, verbose: bool) -> Result<()> {
     if verbose {
         println!("{} Topping up pod via HTTP...", "->".blue());
@@ -59,6 +74,25 @@ async fn execute_http_topup(server: &str, args: TopupArgs, verbose: bool) -> Res
         println!("  Pod ID: {}", args.pod_id);
     }
 
+    let client = PaygressClient::new(server);
+
+    // Check the server is up before any payment leaves the wallet
+    client.health().await?;
+
+    // Without an explicit token, price the requested duration from the server's offers
+    let paid_from_wallet = args.token.is_none();
+    let cashu_token = match args.token {
+        Some(token) => token,
+        None => {
+            let tier = args.tier.as_deref().ok_or_else(missing_payment)?;
+            let offers = client.get_offers().await?;
+            let offer = offers.offers.unwrap_or_default().into_iter()
+                .find(|o| o.id == tier)
+                .ok_or_else(|| anyhow::anyhow!("Tier '{}' not available on this server", tier))?;
+            token_or_pay(None, args.duration.as_deref(), offer.rate_msats_per_sec, &offers.mint_urls.unwrap_or_default()).await?
+        }
+    };
+
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(
         ProgressStyle::default_spinner()
@@ -68,16 +102,30 @@ async fn execute_http_topup(server: &str, args: TopupArgs, verbose: bool) -> Res
     spinner.set_message("Processing top-up payment...");
     spinner.enable_steady_tick(std::time::Duration::from_millis(100));
 
-    let client = PaygressClient::new(server);
-
     let request = TopupRequest {
         pod_id: args.pod_id.clone(),
-        cashu_token: Some(args.token),
+        cashu_token: Some(cashu_token.clone()),
     };
 
-    let response = client.topup_pod(request).await?;
+    let response = client.topup_pod(request).await;
     spinner.finish_and_clear();
 
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            if paid_from_wallet {
+                reclaim_payment(&cashu_token).await;
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(refund) = &response.refund_token {
+        store_refund(refund).await;
+    } else if !response.success && paid_from_wallet {
+        reclaim_payment(&cashu_token).await;
+    }
+
     if response.success {
         println!("{}", "Pod topped up successfully!".green().bold());
         println!();
@@ -118,21 +166,39 @@ async fn execute_nostr_topup(provider_npub: String, args: TopupArgs, _verbose: b
     println!("  Provider: {}", provider_npub);
     println!();
 
-    // Build topup request (same structure as spawn but with just token + pod_id)
-    let request = serde_json::json!({
-        "type": "topup",
-        "pod_id": args.pod_id,
-        "cashu_token": args.token,
-    });
+    let paid_from_wallet = args.token.is_none();
+    let cashu_token = match args.token.clone() {
+        Some(token) => token,
+        None => {
+            let tier = args.tier.as_deref().ok_or_else(missing_payment)?;
+            let provider = client.get_provider(&provider_npub).await?
+                .ok_or_else(|| anyhow::anyhow!("Provider not found"))?;
+            let spec = provider.specs.iter()
+                .find(|s| s.id == tier)
+                .ok_or_else(|| anyhow::anyhow!("Tier '{}' not available on this provider", tier))?;
+            token_or_pay(None, args.duration.as_deref(), spec.rate_msats_per_sec, &provider.whitelisted_mints).await?
+        }
+    };
+
+    let request = EncryptedTopUpPodRequest {
+        pod_npub: args.pod_id.clone(),
+        cashu_token: cashu_token.clone(),
+    };
 
     print!("  Sending topup request... ");
 
     let request_json = serde_json::to_string(&request)?;
-    client.nostr().send_encrypted_private_message(
+    if let Err(e) = client.nostr().send_encrypted_private_message(
         &provider_npub,
         request_json,
         "nip04",
-    ).await?;
+    ).await {
+        println!("{}", "FAILED".red());
+        if paid_from_wallet {
+            reclaim_payment(&cashu_token).await;
+        }
+        return Err(e);
+    }
 
     println!("{}", "SENT".green());
     println!();
@@ -142,19 +208,22 @@ async fn execute_nostr_topup(provider_npub: String, args: TopupArgs, _verbose: b
         Ok(response) => {
             println!();
 
-            // Try to parse response
-            if let Ok(resp) = serde_json::from_str::<serde_json::Value>(&response.content) {
-                if resp.get("error").is_some() {
-                    println!("{}", "Topup failed".red().bold());
-                    println!("  {}", resp["error"].as_str().unwrap_or("Unknown error"));
-                } else {
-                    println!("{}", "Topup successful!".green().bold());
-                    if let Some(expires) = resp.get("expires_at") {
-                        println!("  {} {}", "New Expiry:".bold(), expires);
-                    }
-                    if let Some(added) = resp.get("added_seconds") {
-                        println!("  {} +{}s", "Added:".bold(), added);
-                    }
+            if let Ok(resp) = serde_json::from_str::<TopUpResponseContent>(&response.content) {
+                println!("{}", "Topup successful!".green().bold());
+                println!("  {} {}", "New Expiry:".bold(), resp.new_expires_at);
+                println!("  {} +{}s", "Added:".bold(), resp.extended_duration_seconds);
+                if let Some(refund) = &resp.refund_token {
+                    store_refund(refund).await;
+                }
+            } else if let Ok(err) = serde_json::from_str::<ErrorResponseContent>(&response.content) {
+                println!("{}", "Topup failed".red().bold());
+                println!("  Type:    {}", err.error_type);
+                println!("  Message: {}", err.message);
+                // Without a refund the provider rejected the top-up before redeeming the token
+                if let Some(refund) = &err.refund_token {
+                    store_refund(refund).await;
+                } else if paid_from_wallet {
+                    reclaim_payment(&cashu_token).await;
                 }
             } else {
                 println!("Provider response: {}", response.content);
@@ -164,6 +233,10 @@ async fn execute_nostr_topup(provider_npub: String, args: TopupArgs, _verbose: b
             println!();
             println!("  {} {}", "Warning:".yellow(), e.to_string().yellow());
             println!("The topup request was sent but the provider didn't respond in time.");
+            if paid_from_wallet {
+                // Taking the payment back also stops a late provider from extending with it
+                reclaim_payment(&cashu_token).await;
+            }
             println!("Check status with: paygress-cli status --pod-id {} --provider {}", args.pod_id, provider_npub);
         }
     }
@@ -0,0 +1,219 @@
+// Wallet command - Local consumer Cashu wallet
+//
+// Stores ecash in ~/.paygress/wallet.redb (next to the Nostr identity) so that
+// spawn/topup can pay providers directly instead of needing a pre-minted token.
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use colored::Colorize;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use super::identity::paygress_dir;
+use paygress::wallet::CashuWallet;
+
+#[derive(Args)]
+pub struct WalletArgs {
+    #[command(subcommand)]
+    pub action: WalletAction,
+}
+
+#[derive(Subcommand)]
+pub enum WalletAction {
+    /// Mint ecash by paying a Lightning invoice
+    Mint {
+        /// Amount in sats
+        #[arg(required_unless_present = "quote")]
+        amount: Option<u64>,
+
+        /// Mint URL to mint at
+        #[arg(long)]
+        mint: String,
+
+        /// Claim the quote of an invoice requested earlier instead of requesting a new one
+        #[arg(long, conflicts_with = "amount")]
+        quote: Option<String>,
+
+        /// How long to wait for the invoice to be paid (seconds)
+        #[arg(long, default_value = "600")]
+        timeout: u64,
+    },
+
+    /// Receive a Cashu token into the wallet
+    Receive {
+        /// Cashu token string
+        token: String,
+    },
+
+    /// Show balance per mint
+    Balance,
+}
+
+pub async fn execute(args: WalletArgs, _verbose: bool) -> Result<()> {
+    let wallet = open_wallet().await?;
+
+    match args.action {
+        WalletAction::Mint { amount, mint, quote, timeout } => execute_mint(&wallet, amount, &mint, quote, timeout).await,
+        WalletAction::Receive { token } => {
+            let received_msats = wallet.receive(&token).await?;
+            println!("  {} Received {} sats", "✓".green(), received_msats / 1000);
+            Ok(())
+        }
+        WalletAction::Balance => {
+            println!("{}", "💰 Wallet".blue().bold());
+            println!("{}", "━".repeat(50).blue());
+            println!();
+
+            let balances = wallet.balances().await?;
+            if balances.is_empty() {
+                println!("  {} Wallet is empty. Fund it with 'paygress-cli wallet mint <SATS> --mint <URL>'", "⚠".yellow());
+            }
+            for balance in &balances {
+                println!("  {} {} {}", balance.mint_url, balance.amount.to_string().green(), balance.unit);
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn execute_mint(wallet: &CashuWallet, amount: Option<u64>, mint_url: &str, quote: Option<String>, timeout: u64) -> Result<()> {
+    let quote_id = match quote {
+        Some(quote_id) => quote_id,
+        None => {
+            let amount = amount.ok_or_else(|| anyhow::anyhow!("Give an amount or --quote"))?;
+            let invoice = wallet.mint_invoice(mint_url, amount).await?;
+
+            println!("{}", "Pay this Lightning invoice to fund your wallet:".bold());
+            println!();
+            println!("{}", invoice.invoice);
+            println!();
+            println!("  Quote: {}", invoice.quote_id);
+            println!();
+            invoice.quote_id
+        }
+    };
+
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.blue} {msg}")
+            .unwrap()
+    );
+    spinner.set_message("Waiting for payment...");
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout);
+    while std::time::Instant::now() < deadline {
+        if let Some(minted) = wallet.claim_mint_quote(mint_url, &quote_id).await? {
+            spinner.finish_and_clear();
+            println!("  {} Minted {} sats at {}", "✓".green(), minted, mint_url);
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    }
+
+    spinner.finish_and_clear();
+    Err(anyhow::anyhow!(
+        "Invoice not paid within {}s. Once it is paid, claim it with 'paygress-cli wallet mint --mint {} --quote {}'",
+        timeout,
+        mint_url,
+        quote_id
+    ))
+}
+
+/// Open the consumer wallet at ~/.paygress/wallet.redb
+pub async fn open_wallet() -> Result<CashuWallet> {
+    let path = paygress_dir()?.join("wallet.redb");
+    CashuWallet::open(&path.to_string_lossy()).await
+}
+
+/// Pay `amount_msats` from the local wallet as a token from one of the provider's accepted mints
+pub async fn pay_from_wallet(amount_msats: u64, accepted_mints: &[String]) -> Result<String> {
+    // Wallet holds sats; round up so the provider never sees less than the quoted price
+    let amount_sats = amount_msats.div_ceil(1000);
+
+    println!("  {} Paying {} sats from local wallet", "⚙".yellow(), amount_sats);
+    let wallet = open_wallet().await?;
+    wallet.send_for_mints(accepted_mints, amount_sats).await
+}
+
+/// Parse a duration like "90", "90s", "30m", "2h" or "1d" into seconds
+pub fn parse_duration(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last() {
+        Some('s') => (&s[..s.len() - 1], 1),
+        Some('m') => (&s[..s.len() - 1], 60),
+        Some('h') => (&s[..s.len() - 1], 3600),
+        Some('d') => (&s[..s.len() - 1], 86400),
+        _ => (s, 1),
+    };
+
+    let value: u64 = number.trim().parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration '{}'. Use e.g. 90s, 30m, 2h or 1d", s))?;
+    if value == 0 {
+        return Err(anyhow::anyhow!("Duration must be greater than zero"));
+    }
+
+    value.checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("Duration '{}' is too long", s))
+}
+
+/// Use the explicit token if one was given, otherwise pay for `duration` at
+/// `rate_msats_per_sec` from the local wallet.
+pub async fn token_or_pay(
+    token: Option<String>,
+    duration: Option<&str>,
+    rate_msats_per_sec: u64,
+    accepted_mints: &[String],
+) -> Result<String> {
+    if let Some(token) = token {
+        return Ok(token);
+    }
+
+    let duration = duration
+        .ok_or_else(|| anyhow::anyhow!("Provide either --token or --duration (paid from the local wallet)"))?;
+    let seconds = parse_duration(duration)?;
+    let amount_msats = rate_msats_per_sec.checked_mul(seconds)
+        .ok_or_else(|| anyhow::anyhow!("Duration '{}' is too long to pay for", duration))?;
+
+    println!("  {} {}s at {} msat/sec = {} msats", "Price:".bold(), seconds, rate_msats_per_sec, amount_msats);
+    pay_from_wallet(amount_msats, accepted_mints).await
+}
+
+/// Put a token paid from the local wallet back after the provider didn't take it
+/// (the request failed, was rejected before redemption, or went unanswered).
+/// If that fails, e.g. because the provider redeemed it after all, the token is printed.
+pub async fn reclaim_payment(token: &str) {
+    let result = async {
+        let wallet = open_wallet().await?;
+        wallet.receive(token).await
+    }.await;
+
+    match result {
+        Ok(received_msats) => {
+            println!("  {} Payment of {} sats returned to your wallet", "✓".green(), received_msats / 1000);
+        }
+        Err(e) => {
+            println!("  {} Could not return the payment to your wallet: {}", "⚠".yellow(), e);
+            println!("  Save this token: {}", token);
+        }
+    }
+}
+
+/// Store a refund/change token from a provider in the local wallet.
+/// If that fails the token is printed so the funds are never lost.
+pub async fn store_refund(token: &str) {
+    let result = async {
+        let wallet = open_wallet().await?;
+        wallet.receive(token).await
+    }.await;
+
+    match result {
+        Ok(received_msats) => {
+            println!("  {} Refund of {} sats added to your wallet", "✓".green(), received_msats / 1000);
+        }
+        Err(e) => {
+            println!("  {} Could not store refund in wallet: {}", "⚠".yellow(), e);
+            println!("  Save this refund token: {}", token);
+        }
+    }
+}
@@ -6,7 +6,7 @@ use colored::Colorize;
 mod api;
 mod commands;
 
-use commands::{list, spawn, topup, status, provider, bootstrap, system};
+use commands::{list, spawn, topup, status, stop, wallet, provider, bootstrap, system};
 
 /// Paygress CLI - Pay-per-Use Compute with Lightning + Nostr
 #[derive(Parser)]
@@ -39,6 +39,12 @@ enum Commands {
     /// Get status of a workload
     Status(status::StatusArgs),
 
+    /// Terminate a workload early and get unused time refunded
+    Stop(stop::StopArgs),
+
+    /// Local Cashu wallet - mint, receive, balance
+    Wallet(wallet::WalletArgs),
+
     // ============ Provider Commands ============
 
     /// Provider management - setup, start, stop, status
@@ -76,6 +82,8 @@ async fn main() {
         Commands::Spawn(args) => spawn::execute(args, cli.verbose).await,
         Commands::Topup(args) => topup::execute(args, cli.verbose).await,
         Commands::Status(args) => status::execute(args, cli.verbose).await,
+        Commands::Stop(args) => stop::execute(args, cli.verbose).await,
+        Commands::Wallet(args) => wallet::execute(args, cli.verbose).await,
 
         // Provider
         Commands::Provider(args) => provider::execute(args, cli.verbose).await,
@@ -1,14 +1,20 @@
 // Compute Backend Trait
 //
-// Abstracts the underlying container/VM platform (Proxmox vs LXD)
+// Abstracts the underlying container/VM platform (Proxmox, LXD, Docker or Kubernetes)
 
 use async_trait::async_trait;
+use std::process::Command;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+pub use crate::network_policy::NetworkPolicy;
+pub use crate::nostr::WorkloadKind;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeStatus {
     pub cpu_usage: f64,    // 0.0 to 1.0
+    pub cpu_total: u64,    // millicores
     pub memory_used: u64,  // bytes
     pub memory_total: u64, // bytes
     pub disk_used: u64,    // bytes
@@ -18,14 +24,19 @@ pub struct NodeStatus {
 #[derive(Debug, Clone)]
 pub struct ContainerConfig {
     pub id: u32,
+    pub kind: WorkloadKind,
     pub name: String,
     pub image: String,
     pub cpu_cores: u32,
     pub memory_mb: u32,
     pub storage_gb: u32,
+    pub disk_iops: Option<u32>, // Read and write IOPS cap; ignored by backends that can't enforce one
     pub password: String,
-    pub ssh_key: Option<String>,
+    pub ssh_public_keys: Vec<String>, // Installed in root's authorized_keys
+    pub disable_password_auth: bool,
     pub host_port: Option<u16>,
+    pub duration_secs: u64, // Paid time; the provider deletes the workload when it runs out
+    pub network: NetworkPolicy, // From the spec; enforced by backends that support it
 }
 
 #[async_trait]
@@ -33,21 +44,94 @@ pub trait ComputeBackend: Send + Sync {
     /// Find an available ID in the given range
     async fn find_available_id(&self, range_start: u32, range_end: u32) -> Result<u32>;
     
-    /// Create a new container
+    /// Workload kinds this backend can provision
+    fn supported_kinds(&self) -> Vec<WorkloadKind>;
+
+    /// Create a new container or VM (`config.kind`)
     async fn create_container(&self, config: &ContainerConfig) -> Result<String>; // Returns container ID/Name
     
-    /// Start a container
-    async fn start_container(&self, id: u32) -> Result<()>;
+    /// Start a container/VM
+    async fn start_container(&self, id: u32, kind: WorkloadKind) -> Result<()>;
     
-    /// Stop a container
-    async fn stop_container(&self, id: u32) -> Result<()>;
+    /// Stop a container/VM
+    async fn stop_container(&self, id: u32, kind: WorkloadKind) -> Result<()>;
     
-    /// Delete a container
-    async fn delete_container(&self, id: u32) -> Result<()>;
+    /// Delete a container/VM
+    async fn delete_container(&self, id: u32, kind: WorkloadKind) -> Result<()>;
+
+    /// IDs and kinds of the Paygress-managed workloads that currently exist
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>>;
     
     /// Get node resource usage
     async fn get_node_status(&self) -> Result<NodeStatus>;
     
-    /// Get public IP of the container/VM
-    async fn get_container_ip(&self, id: u32) -> Result<Option<String>>;
+    /// Get the IPv4 address of the container/VM, if it has one yet
+    async fn get_container_ip(&self, id: u32, kind: WorkloadKind) -> Result<Option<String>>;
+
+    /// Address users SSH to when it isn't the provider's `public_ip`, e.g. a Kubernetes
+    /// load balancer. `None` (the default) means the public IP.
+    async fn get_external_host(&self, _id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Push back a workload's expiry after a top-up. Only backends that enforce
+    /// `duration_secs` themselves need to do anything here.
+    async fn extend_container(&self, _id: u32, _kind: WorkloadKind, _additional_secs: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Resource usage of the machine the provider runs on, for backends that
+/// manage workloads on the same host (LXD via `lxc`, Docker)
+pub fn local_node_status() -> Result<NodeStatus> {
+    // Use `free -b` for memory
+    let mem_output = Command::new("free").arg("-b").output()?;
+    let mem_str = String::from_utf8_lossy(&mem_output.stdout);
+    
+    // Simple parsing of `free` output
+    //               total        used        free      shared  buff/cache   available
+    // Mem:    16723824640  1038573568 1234567890 ...
+    let mut memory_total = 0;
+    let mut memory_used = 0;
+    
+    for line in mem_str.lines() {
+        if line.starts_with("Mem:") {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() >= 3 {
+                memory_total = parts[1].parse().unwrap_or(0);
+                memory_used = parts[2].parse().unwrap_or(0);
+            }
+        }
+    }
+
+    // Use `df -B1 /` for disk
+    let disk_output = Command::new("df").args(["-B1", "/"]).output()?;
+    let disk_str = String::from_utf8_lossy(&disk_output.stdout);
+    
+    let mut disk_total = 0;
+    let mut disk_used = 0;
+    
+    for line in disk_str.lines().skip(1) { // Skip header
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 3 {
+            disk_total = parts[1].parse().unwrap_or(0);
+            disk_used = parts[2].parse().unwrap_or(0);
+            break;
+        }
+    }
+    
+    // Use /proc/loadavg for CPU
+    let loadavg = std::fs::read_to_string("/proc/loadavg").unwrap_or_default();
+    let load_1min: f64 = loadavg.split_whitespace().next().unwrap_or("0").parse().unwrap_or(0.0);
+    let cpu_cores = num_cpus::get() as f64;
+    let cpu_usage = (load_1min / cpu_cores).min(1.0);
+
+    Ok(NodeStatus {
+        cpu_usage,
+        cpu_total: num_cpus::get() as u64 * 1000,
+        memory_used,
+        memory_total,
+        disk_used,
+        disk_total,
+    })
 }
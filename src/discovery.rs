@@ -3,14 +3,11 @@
 // Used by end users to discover available providers on Nostr
 // and interact with them for spawning workloads.
 
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::Result;
 use tracing::{info, warn};
 
-use crate::nostr::{
-    NostrRelaySubscriber, RelayConfig, ProviderOfferContent, ProviderInfo, 
-    ProviderFilter, PodSpec,
-};
+use crate::nostr::{NostrRelaySubscriber, RelayConfig, ProviderInfo, ProviderFilter};
+use crate::uptime::{UptimeStats, DAY_SECS};
 
 /// Discovery client for finding providers
 pub struct DiscoveryClient {
@@ -55,7 +52,20 @@ impl DiscoveryClient {
         
         // Optimisation: Fetch all heartbeats in parallel (batch query)
         let provider_npubs: Vec<String> = offers.iter().map(|o| o.provider_npub.clone()).collect();
-        let heartbeats = self.nostr.get_latest_heartbeats_multi(provider_npubs).await?;
+        let heartbeats = self.nostr.get_latest_heartbeats_multi(provider_npubs.clone()).await?;
+
+        // Score uptime ourselves from heartbeat history rather than trusting the offer
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let history = match self.nostr.query_heartbeats_multi(provider_npubs, now.saturating_sub(30 * DAY_SECS)).await {
+            Ok(history) => history,
+            Err(e) => {
+                warn!("Failed to fetch heartbeat history, uptime will show as 0: {}", e);
+                Default::default()
+            }
+        };
         
         for offer in offers {
             // Check if provider is online (has recent heartbeat)
@@ -73,6 +83,13 @@ impl DiscoveryClient {
 
 
 
+            let uptime = history.get(&offer.provider_npub)
+                .map(|hbs| UptimeStats::from_heartbeats(hbs, now))
+                .unwrap_or_default();
+            let available_slots = heartbeats.get(&offer.provider_npub)
+                .map(|hb| hb.available_slots.clone())
+                .unwrap_or_default();
+
             let provider = ProviderInfo {
                 npub: offer.provider_npub.clone(),
                 hostname: offer.hostname,
@@ -80,10 +97,13 @@ impl DiscoveryClient {
                 capabilities: offer.capabilities,
                 specs: offer.specs,
                 whitelisted_mints: offer.whitelisted_mints,
-                uptime_percent: offer.uptime_percent,
+                images: offer.images,
+                uptime_percent: uptime.last_7d,
+                uptime,
                 total_jobs_completed: offer.total_jobs_completed,
                 last_seen,
                 is_online,
+                available_slots,
             };
 
             // Apply filters
@@ -108,6 +128,17 @@ impl DiscoveryClient {
                         continue;
                     }
                 }
+                if let Some(min_storage) = f.min_storage_gb {
+                    if !provider.specs.iter().any(|s| s.storage_gb >= min_storage) {
+                        continue;
+                    }
+                }
+                // Providers that don't report slots are left out
+                if let Some(min_slots) = f.min_available_slots {
+                    if !provider.available_slots.values().any(|&slots| slots >= min_slots) {
+                        continue;
+                    }
+                }
             }
 
             providers.push(provider);
@@ -182,8 +213,10 @@ impl DiscoveryClient {
                 });
             }
             "uptime" => {
+                // 7 day score first; longer then shorter history breaks ties
                 providers.sort_by(|a, b| {
-                    b.uptime_percent.partial_cmp(&a.uptime_percent).unwrap_or(std::cmp::Ordering::Equal)
+                    let key = |p: &ProviderInfo| (p.uptime.last_7d, p.uptime.last_30d, p.uptime.last_24h);
+                    key(b).partial_cmp(&key(a)).unwrap_or(std::cmp::Ordering::Equal)
                 });
             }
             "capacity" => {
@@ -194,7 +227,7 @@ impl DiscoveryClient {
                 });
             }
             "jobs" => {
-                providers.sort_by(|a, b| b.total_jobs_completed.cmp(&a.total_jobs_completed));
+                providers.sort_by_key(|p| std::cmp::Reverse(p.total_jobs_completed));
             }
             _ => {} // No sorting
         }
@@ -206,10 +239,10 @@ impl DiscoveryClient {
         
         let mut output = String::new();
         
-        writeln!(&mut output, "┌──────────────────────────────────────────────────────────────────────────────────────────────────────┐").unwrap();
-        writeln!(&mut output, "│ {:^16} │ {:^18} │ {:^10} │ {:^8} │ {:^8} │ {:^10} │ {:^6} │", 
-            "ID", "PROVIDER", "LOCATION", "UPTIME", "CHEAPEST", "LXC/VM", "ONLINE").unwrap();
-        writeln!(&mut output, "├──────────────────────────────────────────────────────────────────────────────────────────────────────┤").unwrap();
+        writeln!(&mut output, "┌────────────────────────────────────────────────────────────────────────────────────────────────────────────────┐").unwrap();
+        writeln!(&mut output, "│ {:^16} │ {:^18} │ {:^10} │ {:^8} │ {:^8} │ {:^7} │ {:^10} │ {:^6} │", 
+            "ID", "PROVIDER", "LOCATION", "UPTIME", "CHEAPEST", "DISK", "LXC/VM", "ONLINE").unwrap();
+        writeln!(&mut output, "├────────────────────────────────────────────────────────────────────────────────────────────────────────────────┤").unwrap();
         
         for p in providers {
             let id = truncate_str(&p.npub, 16);
@@ -219,21 +252,27 @@ impl DiscoveryClient {
                 .min()
                 .map(|r| format!("{}m/s", r))
                 .unwrap_or_else(|| "-".to_string());
+            let disk = match (p.specs.iter().map(|s| s.storage_gb).min(), p.specs.iter().map(|s| s.storage_gb).max()) {
+                (Some(min), Some(max)) if min == max => format!("{}G", min),
+                (Some(min), Some(max)) => format!("{}-{}G", min, max),
+                _ => "-".to_string(),
+            };
             let capabilities = p.capabilities.join("/");
             let online = if p.is_online { "✓" } else { "✗" };
             
-            writeln!(&mut output, "│ {:16} │ {:18} │ {:^10} │ {:>6.1}% │ {:>8} │ {:^10} │ {:^6} │",
+            writeln!(&mut output, "│ {:16} │ {:18} │ {:^10} │ {:>6.1}% │ {:>8} │ {:>7} │ {:^10} │ {:^6} │",
                 id,
                 truncate_str(&p.hostname, 18),
                 truncate_str(location, 10),
                 p.uptime_percent,
                 cheapest,
+                disk,
                 capabilities,
                 online
             ).unwrap();
         }
         
-        writeln!(&mut output, "└──────────────────────────────────────────────────────────────────────────────────────────────────────┘").unwrap();
+        writeln!(&mut output, "└────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘").unwrap();
         
         output
     }
@@ -249,7 +288,8 @@ impl DiscoveryClient {
         writeln!(&mut output, "├────────────────────────────────────────────────────────────┤").unwrap();
         writeln!(&mut output, "│ NPUB:       {}", truncate_str(&provider.npub, 45)).unwrap();
         writeln!(&mut output, "│ Location:   {}", provider.location.as_deref().unwrap_or("Unknown")).unwrap();
-        writeln!(&mut output, "│ Uptime:     {:.1}%", provider.uptime_percent).unwrap();
+        writeln!(&mut output, "│ Uptime:     {:.1}% (24h)  {:.1}% (7d)  {:.1}% (30d)",
+            provider.uptime.last_24h, provider.uptime.last_7d, provider.uptime.last_30d).unwrap();
         writeln!(&mut output, "│ Jobs Done:  {}", provider.total_jobs_completed).unwrap();
         writeln!(&mut output, "│ Status:     {}", if provider.is_online { "🟢 Online" } else { "🔴 Offline" }).unwrap();
         writeln!(&mut output, "│ Supports:   {}", provider.capabilities.join(", ")).unwrap();
@@ -259,10 +299,21 @@ impl DiscoveryClient {
         for spec in &provider.specs {
             writeln!(&mut output, "│   • {} ({}) - {} msat/sec",
                 spec.name, spec.id, spec.rate_msats_per_sec).unwrap();
-            writeln!(&mut output, "│     {} vCPU, {} MB RAM",
-                spec.cpu_millicores / 1000, spec.memory_mb).unwrap();
+            writeln!(&mut output, "│     {} vCPU, {} MB RAM, {} GB disk ({})",
+                spec.cpu_millicores / 1000, spec.memory_mb, spec.storage_gb, spec.kind.as_str()).unwrap();
+            if let Some(iops) = spec.disk_iops {
+                writeln!(&mut output, "│     Disk limited to {} IOPS", iops).unwrap();
+            }
+            if let Some(slots) = provider.available_slots.get(&spec.id) {
+                writeln!(&mut output, "│     {} × {} available", slots, spec.id).unwrap();
+            }
         }
         
+        if !provider.images.is_empty() {
+            writeln!(&mut output, "├────────────────────────────────────────────────────────────┤").unwrap();
+            writeln!(&mut output, "│ Images:     {}", provider.images.join(", ")).unwrap();
+        }
+
         writeln!(&mut output, "├────────────────────────────────────────────────────────────┤").unwrap();
         writeln!(&mut output, "│ Accepted Mints:").unwrap();
         for mint in &provider.whitelisted_mints {
@@ -286,6 +337,7 @@ fn truncate_str(s: &str, max_len: usize) -> &str {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nostr::PodSpec;
 
     #[test]
     fn test_format_provider_table() {
@@ -301,18 +353,29 @@ mod tests {
                     description: "Test".to_string(),
                     cpu_millicores: 1000,
                     memory_mb: 1024,
+                    storage_gb: 10,
+                    disk_iops: None,
                     rate_msats_per_sec: 50,
+                    kind: Default::default(),
+                    network: Default::default(),
                 }],
                 whitelisted_mints: vec![],
+                images: vec!["ubuntu-22.04".to_string()],
                 uptime_percent: 99.5,
+                uptime: UptimeStats { last_24h: 100.0, last_7d: 99.5, last_30d: 98.0 },
                 total_jobs_completed: 10,
                 last_seen: 0,
                 is_online: true,
+                available_slots: [("basic".to_string(), 3)].into(),
             }
         ];
 
         let table = DiscoveryClient::format_provider_table(&providers);
         assert!(table.contains("Test Provider"));
         assert!(table.contains("99.5%"));
+        assert!(table.contains("10G"));
+
+        let details = DiscoveryClient::format_provider_details(&providers[0]);
+        assert!(details.contains("3 × basic available"));
     }
 }
@@ -0,0 +1,307 @@
+// Docker Backend
+//
+// Implements ComputeBackend with the Docker Engine API on its unix socket, for
+// single-host providers that only run Docker. Podman's Docker-compatible socket
+// works too. Containers run the same SSH bootstrap script as K8s pods, with
+// their SSH port published on the allocated host port.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hyper::Method;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::compute::{local_node_status, ComputeBackend, ContainerConfig, NodeStatus, WorkloadKind};
+use crate::images::{find_image, ImageEntry};
+use crate::nostr::PodSpec;
+use crate::provisioning::{ssh_bootstrap_env, SSH_BOOTSTRAP_SCRIPT};
+use crate::unix_http;
+
+pub const DEFAULT_SOCKET_PATH: &str = "/var/run/docker.sock";
+
+// Oldest API version with everything used here (Docker 20.10, Podman 3)
+const API_VERSION: &str = "v1.41";
+
+pub struct DockerBackend {
+    socket_path: PathBuf,
+    images: Vec<ImageEntry>,
+}
+
+impl DockerBackend {
+    pub fn new(socket_path: &str, images: Vec<ImageEntry>) -> Self {
+        Self {
+            socket_path: PathBuf::from(socket_path),
+            images,
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<(u16, Vec<u8>)> {
+        let path = format!("/{}{}", API_VERSION, path);
+        let (status, bytes) = unix_http::request(&self.socket_path, method, &path, body).await?;
+
+        if status >= 400 {
+            // Errors come back as {"message": "..."}
+            let message = serde_json::from_slice::<Value>(&bytes).ok()
+                .and_then(|v| v["message"].as_str().map(str::to_string))
+                .unwrap_or_else(|| String::from_utf8_lossy(&bytes).to_string());
+            return Err(anyhow::anyhow!("Docker API error {}: {}", status, message));
+        }
+
+        Ok((status, bytes))
+    }
+
+    /// Send a request and decode its JSON body (Null for empty bodies)
+    async fn call(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value> {
+        let (_, bytes) = self.request(method, path, body).await?;
+        if bytes.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_slice(&bytes).context("Failed to parse Docker API response")
+    }
+
+    /// Pull an image. Progress is streamed as JSON lines and failures only show up
+    /// as an "error" line, so the status code alone isn't enough.
+    async fn pull_image(&self, image: &str) -> Result<()> {
+        let query = serde_urlencoded::to_string([("fromImage", image)])?;
+        let (_, bytes) = self.request(Method::POST, &format!("/images/create?{}", query), None).await?;
+
+        let progress = String::from_utf8_lossy(&bytes);
+        for line in progress.lines() {
+            if let Some(error) = serde_json::from_str::<Value>(line).ok().and_then(|v| v["error"].as_str().map(str::to_string)) {
+                return Err(anyhow::anyhow!("Failed to pull {}: {}", image, error));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the daemon's storage driver can cap a container's disk
+    async fn storage_quota(&self) -> Result<bool> {
+        let info = self.call(Method::GET, "/info", None).await?;
+        Ok(storage_quota_supported(&info))
+    }
+
+    /// Warn about tier settings this daemon won't enforce, so they aren't sold unknowingly
+    pub async fn check_specs(&self, specs: &[PodSpec]) -> Result<()> {
+        let info = self.call(Method::GET, "/info", None).await
+            .context("Failed to query the Docker daemon")?;
+        let driver = info["Driver"].as_str().unwrap_or("unknown");
+        let storage_quota = storage_quota_supported(&info);
+
+        for spec in specs {
+            if !storage_quota {
+                warn!(
+                    "Tier {} offers {} GB of disk, but Docker's {} storage driver can't cap it (needs overlay2 on xfs, btrfs or zfs)",
+                    spec.id, spec.storage_gb, driver
+                );
+            }
+            if spec.disk_iops.is_some() {
+                warn!("Tier {} sets disk_iops, which the Docker backend doesn't enforce", spec.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of all containers, running or not, without Docker's leading "/"
+    async fn container_names(&self) -> Result<Vec<String>> {
+        let containers = self.call(Method::GET, "/containers/json?all=true", None).await?;
+
+        Ok(containers.as_array()
+            .unwrap_or(&vec![])
+            .iter()
+            .filter_map(|c| c["Names"].as_array())
+            .flatten()
+            .filter_map(|name| name.as_str())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .collect())
+    }
+}
+
+/// Whether `StorageOpt size` works with the storage driver in a GET /info reply.
+/// overlay2 also needs xfs mounted with pquota, which Docker checks at create.
+fn storage_quota_supported(info: &Value) -> bool {
+    match info["Driver"].as_str() {
+        Some("btrfs" | "zfs" | "devicemapper") => true,
+        Some("overlay2") => info["DriverStatus"].as_array().is_some_and(|status| {
+            status.iter().any(|entry| entry[0] == "Backing Filesystem" && entry[1] == "xfs")
+        }),
+        _ => false,
+    }
+}
+
+/// Body of POST /containers/create for a workload. The disk is only capped when
+/// `storage_quota` says the storage driver can; disk_iops would need the host's block device.
+fn container_spec(config: &ContainerConfig, image: &str, storage_quota: bool) -> Value {
+    let env: Vec<String> = ssh_bootstrap_env(22, "root", &config.password, &config.ssh_public_keys, config.disable_password_auth)
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+
+    let mut host_config = json!({
+        "NanoCpus": config.cpu_cores as u64 * 1_000_000_000,
+        "Memory": config.memory_mb as u64 * 1024 * 1024,
+        "RestartPolicy": { "Name": "unless-stopped" },
+    });
+    if storage_quota {
+        host_config["StorageOpt"] = json!({ "size": format!("{}G", config.storage_gb) });
+    }
+    if let Some(port) = config.host_port {
+        host_config["PortBindings"] = json!({
+            "22/tcp": [{ "HostIp": "0.0.0.0", "HostPort": port.to_string() }],
+        });
+    }
+
+    json!({
+        "Image": image,
+        "Hostname": config.name,
+        "Cmd": ["/bin/sh", "-c", SSH_BOOTSTRAP_SCRIPT],
+        "Env": env,
+        "Labels": { "managed-by": "paygress" },
+        "ExposedPorts": { "22/tcp": {} },
+        "HostConfig": host_config,
+    })
+}
+
+#[async_trait]
+impl ComputeBackend for DockerBackend {
+    fn supported_kinds(&self) -> Vec<WorkloadKind> {
+        vec![WorkloadKind::Container]
+    }
+
+    async fn find_available_id(&self, range_start: u32, range_end: u32) -> Result<u32> {
+        let existing_ids: Vec<u32> = self.container_names().await?
+            .iter()
+            .filter_map(|name| name.strip_prefix("paygress-"))
+            .filter_map(|id| id.parse::<u32>().ok())
+            .collect();
+
+        for id in range_start..=range_end {
+            if !existing_ids.contains(&id) {
+                return Ok(id);
+            }
+        }
+
+        Err(anyhow::anyhow!("No available IDs in range {}-{}", range_start, range_end))
+    }
+
+    async fn create_container(&self, config: &ContainerConfig) -> Result<String> {
+        if config.kind == WorkloadKind::Vm {
+            return Err(anyhow::anyhow!("The Docker backend can't provision VMs"));
+        }
+
+        let name = format!("paygress-{}", config.id);
+        let image = find_image(&self.images, &config.image)
+            .and_then(|image| image.docker_image.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Image '{}' has no Docker image in the catalog", config.image))?;
+
+        info!("Creating Docker container {} with image {}", name, image);
+        self.pull_image(image).await?;
+        let storage_quota = self.storage_quota().await?;
+
+        let query = serde_urlencoded::to_string([("name", name.as_str())])?;
+        self.call(Method::POST, &format!("/containers/create?{}", query), Some(&container_spec(config, image, storage_quota))).await
+            .context("Failed to create Docker container")?;
+
+        self.call(Method::POST, &format!("/containers/{}/start", name), None).await
+            .context("Failed to start Docker container")?;
+
+        if let Some(port) = config.host_port {
+            info!("Published host port {} -> {}:22", port, name);
+        }
+
+        Ok(name)
+    }
+
+    async fn start_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.call(Method::POST, &format!("/containers/paygress-{}/start", id), None).await?;
+        Ok(())
+    }
+
+    async fn stop_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.call(Method::POST, &format!("/containers/paygress-{}/stop", id), None).await?;
+        Ok(())
+    }
+
+    async fn delete_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.call(Method::DELETE, &format!("/containers/paygress-{}?force=true", id), None).await?;
+        Ok(())
+    }
+
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>> {
+        Ok(self.container_names().await?
+            .iter()
+            .filter_map(|name| name.strip_prefix("paygress-"))
+            .filter_map(|id| id.parse::<u32>().ok())
+            .map(|id| (id, WorkloadKind::Container))
+            .collect())
+    }
+
+    async fn get_node_status(&self) -> Result<NodeStatus> {
+        local_node_status()
+    }
+
+    async fn get_container_ip(&self, id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        let container = self.call(Method::GET, &format!("/containers/paygress-{}/json", id), None).await?;
+
+        Ok(container["NetworkSettings"]["Networks"].as_object()
+            .and_then(|networks| {
+                networks.values()
+                    .filter_map(|network| network["IPAddress"].as_str())
+                    .find(|ip| !ip.is_empty())
+            })
+            .map(str::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_spec_maps_limits_and_port() {
+        let config = ContainerConfig {
+            id: 1000,
+            kind: WorkloadKind::Container,
+            name: "paygress-1000".to_string(),
+            image: "debian-12".to_string(),
+            cpu_cores: 2,
+            memory_mb: 512,
+            storage_gb: 10,
+            disk_iops: None,
+            password: "secret".to_string(),
+            ssh_public_keys: vec!["ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIExample user@host".to_string()],
+            disable_password_auth: true,
+            host_port: Some(30001),
+            duration_secs: 3600,
+            network: Default::default(),
+        };
+
+        let spec = container_spec(&config, "debian:12", true);
+        assert_eq!(spec["Image"], "debian:12");
+        assert_eq!(spec["HostConfig"]["StorageOpt"]["size"], "10G");
+        assert!(container_spec(&config, "debian:12", false)["HostConfig"].get("StorageOpt").is_none());
+        assert_eq!(spec["HostConfig"]["NanoCpus"], 2_000_000_000u64);
+        assert_eq!(spec["HostConfig"]["Memory"], 512u64 * 1024 * 1024);
+        assert_eq!(spec["HostConfig"]["PortBindings"]["22/tcp"][0]["HostPort"], "30001");
+
+        // Credentials travel in the environment, not the command
+        let script = spec["Cmd"][2].as_str().unwrap();
+        assert!(!script.contains("secret"));
+        let env: Vec<&str> = spec["Env"].as_array().unwrap().iter().filter_map(|v| v.as_str()).collect();
+        assert!(env.contains(&"PAYGRESS_SSH_KEY_ONLY=true"));
+        assert!(env.contains(&"PAYGRESS_SSH_PASSWORD=secret"));
+        assert!(env.iter().any(|var| var.starts_with("PAYGRESS_SSH_KEYS=") && var.ends_with("user@host")));
+    }
+
+    #[test]
+    fn test_storage_quota_depends_on_driver() {
+        let overlay_on = |fs: &str| json!({ "Driver": "overlay2", "DriverStatus": [["Backing Filesystem", fs], ["Supports d_type", "true"]] });
+        assert!(storage_quota_supported(&overlay_on("xfs")));
+        assert!(!storage_quota_supported(&overlay_on("extfs")));
+        assert!(storage_quota_supported(&json!({ "Driver": "btrfs" })));
+        assert!(!storage_quota_supported(&json!({ "Driver": "vfs" })));
+        assert!(!storage_quota_supported(&json!({})));
+    }
+}
@@ -0,0 +1,87 @@
+// Image Catalog
+//
+// Maps the friendly image names users request (ubuntu-22.04, debian-12, alpine-3.19)
+// to what each backend actually launches: a Proxmox vztmpl volume or cloud-init VM
+// template, an LXD image alias (used for both containers and VMs), or a Docker image
+// (which Kubernetes pods run too).
+
+use serde::{Deserialize, Serialize};
+
+use crate::nostr::WorkloadKind;
+use crate::provider::BackendType;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageEntry {
+    pub name: String, // e.g. "debian-12"
+    #[serde(default)]
+    pub proxmox_template: Option<String>, // e.g. "local:vztmpl/debian-12-standard_12.7-1_amd64.tar.zst"
+    #[serde(default)]
+    pub proxmox_vm_template: Option<u32>, // VMID of a cloud-init VM template to clone
+    #[serde(default)]
+    pub lxd_alias: Option<String>, // e.g. "images:debian/12"
+    #[serde(default)]
+    pub docker_image: Option<String>, // e.g. "debian:12" (also used for Kubernetes pods)
+}
+
+impl ImageEntry {
+    /// Whether `backend` can launch this image as a `kind` workload
+    pub fn available_on(&self, backend: &BackendType, kind: WorkloadKind) -> bool {
+        match (backend, kind) {
+            (BackendType::Proxmox, WorkloadKind::Container) => self.proxmox_template.is_some(),
+            (BackendType::Proxmox, WorkloadKind::Vm) => self.proxmox_vm_template.is_some(),
+            (BackendType::LXD | BackendType::LxdApi, _) => self.lxd_alias.is_some(),
+            (BackendType::Docker | BackendType::Kubernetes, WorkloadKind::Container) => self.docker_image.is_some(),
+            (BackendType::Docker | BackendType::Kubernetes, WorkloadKind::Vm) => false,
+        }
+    }
+}
+
+/// Catalog used when the provider config doesn't define one.
+/// Template file names differ between Proxmox hosts, so only the configured
+/// `proxmox_template` is offered on Proxmox; LXD and Docker pull the rest on demand.
+pub fn default_catalog(proxmox_template: &str) -> Vec<ImageEntry> {
+    let proxmox_template = Some(proxmox_template.to_string()).filter(|t| !t.is_empty());
+
+    vec![
+        ImageEntry {
+            name: "ubuntu-22.04".to_string(),
+            proxmox_template,
+            proxmox_vm_template: None,
+            lxd_alias: Some("ubuntu:22.04".to_string()),
+            docker_image: Some("ubuntu:22.04".to_string()),
+        },
+        ImageEntry {
+            name: "debian-12".to_string(),
+            proxmox_template: None,
+            proxmox_vm_template: None,
+            lxd_alias: Some("images:debian/12".to_string()),
+            docker_image: Some("debian:12".to_string()),
+        },
+        ImageEntry {
+            name: "alpine-3.19".to_string(),
+            proxmox_template: None,
+            proxmox_vm_template: None,
+            lxd_alias: Some("images:alpine/3.19".to_string()),
+            docker_image: Some("alpine:3.19".to_string()),
+        },
+    ]
+}
+
+/// Canonical form of an image name, so "ubuntu:22.04" and "Ubuntu-22.04" both match "ubuntu-22.04"
+pub fn normalize_image_name(name: &str) -> String {
+    name.trim().to_lowercase().replace([':', '/'], "-")
+}
+
+/// Look up a requested image in the catalog
+pub fn find_image<'a>(catalog: &'a [ImageEntry], requested: &str) -> Option<&'a ImageEntry> {
+    let requested = normalize_image_name(requested);
+    catalog.iter().find(|image| normalize_image_name(&image.name) == requested)
+}
+
+/// Names of the catalog images `backend` can launch as any of `kinds`
+pub fn available_images(catalog: &[ImageEntry], backend: &BackendType, kinds: &[WorkloadKind]) -> Vec<String> {
+    catalog.iter()
+        .filter(|image| kinds.iter().any(|kind| image.available_on(backend, *kind)))
+        .map(|image| image.name.clone())
+        .collect()
+}
@@ -17,7 +17,7 @@
 
 use anyhow::Result;
 use std::sync::Arc;
-use tracing::{info, error, warn};
+use tracing::{info, error};
 use axum::{
     extract::State,
     http::{StatusCode, HeaderMap},
@@ -25,9 +25,12 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
-use crate::pod_provisioning::PodProvisioningService;
+use crate::pod_provisioning::{PodProvisioningService, SpawnPodResponse, TopUpPodResponse};
+use crate::nostr::ErrorResponseContent;
+use crate::provisioning::InvalidRequest;
+use crate::admission::CapacityExceeded;
 
 /// L402 payment information extracted from headers
 #[derive(Debug, Clone)]
@@ -40,7 +43,9 @@ pub struct L402Payment {
 /// 
 /// Supports format from ngx_l402:
 /// - Authorization: Cashu <token>
-async fn extract_l402_payment(headers: &HeaderMap) -> Result<Option<L402Payment>, String> {
+///
+/// Tokens from mints outside `accepted_mints` are rejected with `mint_not_accepted`.
+async fn extract_l402_payment(headers: &HeaderMap, accepted_mints: &[String]) -> Result<Option<L402Payment>, ErrorResponseContent> {
     use crate::sidecar_service::extract_token_value;
     
     let mut cashu_token: Option<String> = None;
@@ -67,6 +72,8 @@ async fn extract_l402_payment(headers: &HeaderMap) -> Result<Option<L402Payment>
     
     // If we found a token, decode it to get the amount
     if let Some(token) = cashu_token {
+        crate::cashu::check_token_mint(&token, accepted_mints)?;
+
         match extract_token_value(&token).await {
             Ok(amount_msats) => {
                 info!("✅ Decoded Cashu token: {} msats", amount_msats);
@@ -77,7 +84,13 @@ async fn extract_l402_payment(headers: &HeaderMap) -> Result<Option<L402Payment>
             }
             Err(e) => {
                 error!("❌ Failed to decode Cashu token: {}", e);
-                return Err(format!("Invalid Cashu token: {}", e));
+                return Err(ErrorResponseContent {
+                    error_type: "invalid_token".to_string(),
+                    message: format!("Invalid Cashu token: {}", e),
+                    details: None,
+                    accepted_mints: vec![],
+                    refund_token: None,
+                });
             }
         }
     }
@@ -85,6 +98,40 @@ async fn extract_l402_payment(headers: &HeaderMap) -> Result<Option<L402Payment>
     Ok(None)
 }
 
+/// Build a 400 response for a rejected payment token
+fn payment_rejected_response(error: ErrorResponseContent) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": "Invalid Payment Token",
+            "error_type": error.error_type,
+            "message": error.message,
+            "details": error.details,
+            "accepted_mints": error.accepted_mints
+        }))
+    ).into_response()
+}
+
+/// Build the response for a request refused before anything was provisioned.
+/// Only payment errors are reported as an invalid payment token.
+fn rejected_response(error: ErrorResponseContent) -> Response {
+    let (status, label) = match error.error_type.as_str() {
+        InvalidRequest::ERROR_TYPE => (StatusCode::BAD_REQUEST, "Invalid Request"),
+        CapacityExceeded::ERROR_TYPE => (StatusCode::SERVICE_UNAVAILABLE, "Insufficient Capacity"),
+        _ => return payment_rejected_response(error),
+    };
+
+    (
+        status,
+        Json(serde_json::json!({
+            "error": label,
+            "error_type": error.error_type,
+            "message": error.message,
+            "details": error.details
+        }))
+    ).into_response()
+}
+
 /// Run the HTTP interface with L402 support
 pub async fn run_http_l402_interface(service: Arc<PodProvisioningService>) -> Result<()> {
     info!("🌐 Starting HTTP interface with L402 support...");
@@ -101,6 +148,7 @@ pub async fn run_http_l402_interface(service: Arc<PodProvisioningService>) -> Re
         .route("/pods/status", post(get_pod_status))
         .route("/pods/spawn", post(spawn_pod_l402))
         .route("/pods/topup", post(topup_pod_l402))
+        .route("/pods/terminate", post(terminate_pod))
         .with_state(service);
 
     // Start the HTTP server
@@ -193,7 +241,7 @@ async fn spawn_pod_l402(
     info!("📨 Received spawn pod request via HTTP+L402");
 
     // Extract payment from Authorization: Cashu <token> header (from ngx_l402 or MCP client)
-    let extracted_payment = extract_l402_payment(&headers).await;
+    let extracted_payment = extract_l402_payment(&headers, &service.get_config().whitelisted_mints).await;
 
     match extracted_payment {
         Ok(Some(l402_payment)) => {
@@ -216,14 +264,8 @@ async fn spawn_pod_l402(
              }
         }
         Err(e) => {
-             error!("❌ Invalid payment token in header: {}", e);
-             return (
-                 StatusCode::BAD_REQUEST,
-                 Json(serde_json::json!({
-                     "error": "Invalid Payment Token",
-                     "message": e
-                 }))
-             ).into_response();
+             error!("❌ Invalid payment token in header: {}", e.message);
+             return payment_rejected_response(e);
         }
     }
 
@@ -235,9 +277,15 @@ async fn spawn_pod_l402(
         ssh_username: request.ssh_username,
         ssh_password: request.ssh_password,
         user_pubkey: request.user_pubkey,
+        ssh_public_keys: request.ssh_public_keys,
+        disable_password_auth: request.disable_password_auth,
     };
 
     match service.spawn_pod(spawn_tool).await {
+        Ok(SpawnPodResponse { error: Some(rejection), .. }) => {
+            // Refused before any pod was created
+            rejected_response(rejection)
+        }
         Ok(response) => {
             info!("✅ Pod spawned successfully: {}", response.pod_npub.as_deref().unwrap_or("unknown"));
             let response_json = serde_json::json!({
@@ -252,7 +300,8 @@ async fn spawn_pod_l402(
                 "pod_spec_name": response.pod_spec_name,
                 "cpu_millicores": response.cpu_millicores,
                 "memory_mb": response.memory_mb,
-                "instructions": response.instructions
+                "instructions": response.instructions,
+                "refund_token": response.refund_token
             });
             (StatusCode::OK, Json(response_json)).into_response()
         }
@@ -280,7 +329,7 @@ async fn topup_pod_l402(
     info!("📨 Received topup pod request via HTTP+L402");
 
     // Extract payment from Authorization: Cashu <token> header (from ngx_l402 or MCP client)
-    let extracted_payment = extract_l402_payment(&headers).await;
+    let extracted_payment = extract_l402_payment(&headers, &service.get_config().whitelisted_mints).await;
     
     match extracted_payment {
         Ok(Some(l402_payment)) => {
@@ -302,14 +351,8 @@ async fn topup_pod_l402(
              }
         }
         Err(e) => {
-            error!("❌ Invalid payment token in header: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({
-                    "error": "Invalid Payment Token",
-                    "message": e
-                }))
-            ).into_response();
+            error!("❌ Invalid payment token in header: {}", e.message);
+            return payment_rejected_response(e);
         }
     }
 
@@ -320,6 +363,9 @@ async fn topup_pod_l402(
     };
 
     match service.topup_pod(topup_tool).await {
+        Ok(TopUpPodResponse { error: Some(rejection), .. }) => {
+            rejected_response(rejection)
+        }
         Ok(response) => {
             info!("✅ Pod topped up successfully: {}", response.pod_npub);
             let response_json = serde_json::json!({
@@ -327,7 +373,8 @@ async fn topup_pod_l402(
                 "message": response.message,
                 "pod_npub": response.pod_npub,
                 "extended_duration_seconds": response.extended_duration_seconds,
-                "new_expires_at": response.new_expires_at
+                "new_expires_at": response.new_expires_at,
+                "refund_token": response.refund_token
             });
             (StatusCode::OK, Json(response_json)).into_response()
         }
@@ -344,6 +391,43 @@ async fn topup_pod_l402(
     }
 }
 
+/// Terminate a pod early and refund its unused time (no payment required)
+async fn terminate_pod(
+    State(service): State<Arc<PodProvisioningService>>,
+    Json(request): Json<TerminatePodHttpRequest>,
+) -> Response {
+    info!("📨 Received terminate pod request via HTTP");
+
+    let terminate_tool = crate::pod_provisioning::TerminatePodTool {
+        pod_npub: request.pod_npub,
+        ssh_password: request.ssh_password,
+    };
+
+    match service.terminate_pod(terminate_tool).await {
+        Ok(response) => {
+            let response_json = serde_json::json!({
+                "success": response.success,
+                "message": response.message,
+                "pod_npub": response.pod_npub,
+                "unused_seconds": response.unused_seconds,
+                "refund_msats": response.refund_msats,
+                "refund_token": response.refund_token
+            });
+            (StatusCode::OK, Json(response_json)).into_response()
+        }
+        Err(e) => {
+            error!("❌ Failed to terminate pod: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": "Failed to terminate pod",
+                    "message": e.to_string()
+                }))
+            ).into_response()
+        }
+    }
+}
+
 /// HTTP request structures
 #[derive(Debug, Deserialize)]
 struct SpawnPodHttpRequest {
@@ -354,6 +438,10 @@ struct SpawnPodHttpRequest {
     pub ssh_username: String,
     pub ssh_password: String,
     pub user_pubkey: Option<String>,
+    #[serde(default)]
+    pub ssh_public_keys: Vec<String>,
+    #[serde(default)]
+    pub disable_password_auth: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -363,6 +451,12 @@ struct TopUpPodHttpRequest {
     pub cashu_token: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct TerminatePodHttpRequest {
+    pub pod_npub: String,
+    pub ssh_password: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct GetPodStatusHttpRequest {
     pub pod_npub: String,
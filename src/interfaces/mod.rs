@@ -42,17 +42,12 @@ pub async fn run_all_interfaces(service: Arc<PodProvisioningService>) -> Result<
     info!("   Architecture: MCP → HTTP (with L402 paywall)");
 
     // Wait for all interfaces to complete (they should run forever)
-    tokio::select! {
-        result = async {
-            for task in tasks {
-                if let Err(e) = task.await {
-                    error!("❌ Interface task failed: {}", e);
-                }
-            }
-        } => {
-            info!("🛑 All interfaces stopped");
+    for task in tasks {
+        if let Err(e) = task.await {
+            error!("❌ Interface task failed: {}", e);
         }
     }
+    info!("🛑 All interfaces stopped");
 
     Ok(())
 }
@@ -64,25 +59,3 @@ fn is_interface_enabled(interface: &str) -> bool {
         .unwrap_or_else(|_| "true".to_string()) // Default to enabled
         .to_lowercase() == "true"
 }
-
-/// Get interface-specific configuration
-pub fn get_interface_config() -> InterfaceConfig {
-    InterfaceConfig {
-        mcp_enabled: is_interface_enabled("MCP"),
-        http_enabled: is_interface_enabled("HTTP"),
-        http_port: std::env::var("HTTP_PORT")
-            .unwrap_or_else(|_| "8080".to_string())
-            .parse()
-            .unwrap_or(8080),
-        http_bind_addr: std::env::var("HTTP_BIND_ADDR")
-            .unwrap_or_else(|_| "0.0.0.0:8080".to_string()),
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct InterfaceConfig {
-    pub mcp_enabled: bool,
-    pub http_enabled: bool,
-    pub http_port: u16,
-    pub http_bind_addr: String,
-}
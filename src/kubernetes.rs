@@ -0,0 +1,195 @@
+// Kubernetes Backend
+//
+// Implements ComputeBackend on top of the sidecar's PodManager, so a cluster
+// can sell workloads through the Nostr ProviderService as well as the HTTP/L402
+// service. The workload's host port becomes the node port (or load balancer
+// port) of the pod's SSH service, or with host networking sshd's port on the node.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::api::ListParams;
+use kube::Api;
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::compute::{ComputeBackend, ContainerConfig, NodeStatus, WorkloadKind};
+use crate::images::{find_image, ImageEntry};
+use crate::sidecar_service::{parse_quantity, PodManager, PodNetworking};
+
+pub const DEFAULT_NAMESPACE: &str = "paygress-workloads";
+
+// Label create_ssh_pod puts on every pod
+const POD_SELECTOR: &str = "app=paygress-ssh-pod";
+
+// The provider deletes pods when they expire; activeDeadlineSeconds is only a
+// backstop in case it is down, so it runs a little longer than the paid time.
+const DEADLINE_GRACE_SECS: u64 = 300;
+
+pub struct KubernetesBackend {
+    pods: PodManager,
+    namespace: String,
+    images: Vec<ImageEntry>,
+}
+
+impl KubernetesBackend {
+    /// Connect with the local kubeconfig, or the service account when running in a cluster
+    pub async fn new(namespace: &str, networking: PodNetworking, images: Vec<ImageEntry>) -> Result<Self> {
+        let pods = PodManager::new(networking).await.map_err(|e| anyhow::anyhow!(e))?;
+        Ok(Self {
+            pods,
+            namespace: namespace.to_string(),
+            images,
+        })
+    }
+
+    fn pods_api(&self) -> Api<Pod> {
+        Api::namespaced(self.pods.client.clone(), &self.namespace)
+    }
+
+    /// IDs of the paygress-<id> pods in the namespace
+    async fn pod_ids(&self) -> Result<Vec<u32>> {
+        let pods = self.pods_api().list(&ListParams::default().labels(POD_SELECTOR)).await?;
+
+        Ok(pods.items.iter()
+            .filter_map(|pod| pod.metadata.name.as_deref())
+            .filter_map(|name| name.strip_prefix("paygress-"))
+            .filter_map(|id| id.parse::<u32>().ok())
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ComputeBackend for KubernetesBackend {
+    fn supported_kinds(&self) -> Vec<WorkloadKind> {
+        vec![WorkloadKind::Container]
+    }
+
+    async fn find_available_id(&self, range_start: u32, range_end: u32) -> Result<u32> {
+        let existing_ids = self.pod_ids().await?;
+
+        for id in range_start..=range_end {
+            if !existing_ids.contains(&id) {
+                return Ok(id);
+            }
+        }
+
+        Err(anyhow::anyhow!("No available IDs in range {}-{}", range_start, range_end))
+    }
+
+    async fn create_container(&self, config: &ContainerConfig) -> Result<String> {
+        if config.kind == WorkloadKind::Vm {
+            return Err(anyhow::anyhow!("The Kubernetes backend can't provision VMs"));
+        }
+
+        let ssh_port = config.host_port
+            .ok_or_else(|| anyhow::anyhow!("Kubernetes pods need a host port for SSH"))?;
+        let image = find_image(&self.images, &config.image)
+            .and_then(|image| image.docker_image.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Image '{}' has no container image in the catalog", config.image))?;
+
+        info!("Creating pod {} with image {} in namespace {}", config.name, image, self.namespace);
+        // Nostr workloads have no keys of their own; they are addressed as container-<id>
+        self.pods.create_ssh_pod(
+            &self.namespace,
+            &config.name,
+            &format!("container-{}", config.id),
+            "",
+            image,
+            ssh_port,
+            "root",
+            &config.password,
+            config.duration_secs + DEADLINE_GRACE_SECS,
+            config.memory_mb as u64,
+            config.cpu_cores as u64 * 1000,
+            config.storage_gb as u64,
+            "",
+            &config.ssh_public_keys,
+            config.disable_password_auth,
+            &config.network,
+            BTreeMap::new(),
+        ).await.map_err(|e| anyhow::anyhow!(e))?;
+
+        Ok(config.name.clone())
+    }
+
+    async fn start_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        Err(anyhow::anyhow!("Pod paygress-{} can't be restarted; Kubernetes pods are recreated, not started", id))
+    }
+
+    async fn stop_container(&self, _id: u32, _kind: WorkloadKind) -> Result<()> {
+        // Pods can't be stopped without deleting them, which delete_container does
+        Ok(())
+    }
+
+    async fn delete_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.pods.delete_pod(&self.namespace, &format!("paygress-{}", id)).await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>> {
+        Ok(self.pod_ids().await?
+            .into_iter()
+            .map(|id| (id, WorkloadKind::Container))
+            .collect())
+    }
+
+    /// Cluster-wide allocatable resources against what running pods request
+    async fn get_node_status(&self) -> Result<NodeStatus> {
+        let nodes: Api<Node> = Api::all(self.pods.client.clone());
+        let mut cpu_total = 0.0;
+        let mut memory_total = 0.0;
+        let mut disk_total = 0.0;
+        for node in nodes.list(&ListParams::default()).await?.items {
+            let allocatable = node.status.and_then(|s| s.allocatable).unwrap_or_default();
+            let amount = |key: &str| allocatable.get(key).and_then(|q| parse_quantity(&q.0)).unwrap_or(0.0);
+            cpu_total += amount("cpu");
+            memory_total += amount("memory");
+            disk_total += amount("ephemeral-storage");
+        }
+
+        let pods: Api<Pod> = Api::all(self.pods.client.clone());
+        let active = ListParams::default().fields("status.phase!=Succeeded,status.phase!=Failed");
+        let mut cpu_requested = 0.0;
+        let mut memory_requested = 0.0;
+        let mut disk_requested = 0.0;
+        for pod in pods.list(&active).await?.items {
+            let containers = pod.spec.map(|s| s.containers).unwrap_or_default();
+            for requests in containers.into_iter().filter_map(|c| c.resources.and_then(|r| r.requests)) {
+                let amount = |key: &str| requests.get(key).and_then(|q| parse_quantity(&q.0)).unwrap_or(0.0);
+                cpu_requested += amount("cpu");
+                memory_requested += amount("memory");
+                disk_requested += amount("ephemeral-storage");
+            }
+        }
+
+        Ok(NodeStatus {
+            cpu_usage: if cpu_total > 0.0 { (cpu_requested / cpu_total).min(1.0) } else { 0.0 },
+            cpu_total: (cpu_total * 1000.0) as u64,
+            memory_used: memory_requested as u64,
+            memory_total: memory_total as u64,
+            disk_used: disk_requested as u64,
+            disk_total: disk_total as u64,
+        })
+    }
+
+    /// The load balancer's address with LoadBalancer networking, otherwise the pod IP
+    async fn get_container_ip(&self, id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        let name = format!("paygress-{}", id);
+        if let Some(host) = self.pods.external_host(&self.namespace, &name).await {
+            return Ok(Some(host));
+        }
+        let pod = self.pods_api().get(&name).await?;
+        Ok(pod.status.and_then(|s| s.pod_ip))
+    }
+
+    /// The load balancer's address with LoadBalancer networking
+    async fn get_external_host(&self, id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        Ok(self.pods.external_host(&self.namespace, &format!("paygress-{}", id)).await)
+    }
+
+    async fn extend_container(&self, id: u32, _kind: WorkloadKind, additional_secs: u64) -> Result<()> {
+        self.pods.extend_pod_deadline(&self.namespace, &format!("paygress-{}", id), additional_secs).await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+}
@@ -0,0 +1,148 @@
+// Workload Ledger
+//
+// Durable record of the provider's workloads, payments and stats (redb).
+// ProviderService keeps its in-memory map for fast lookups and writes every
+// change through to the ledger, so a restart can reload and reconcile it.
+
+use anyhow::{Context, Result};
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::provider::WorkloadInfo;
+
+// vmid -> JSON-encoded WorkloadInfo
+const WORKLOADS: TableDefinition<u32, &[u8]> = TableDefinition::new("workloads");
+// sequence number -> JSON-encoded PaymentRecord
+const PAYMENTS: TableDefinition<u64, &[u8]> = TableDefinition::new("payments");
+// stat name -> value
+const STATS: TableDefinition<&str, u64> = TableDefinition::new("stats");
+
+pub const STAT_JOBS_COMPLETED: &str = "total_jobs_completed";
+
+/// A payment received for, or refunded from, a workload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRecord {
+    pub vmid: u32,
+    pub kind: String, // "spawn", "topup" or "refund"
+    pub amount_msats: u64,
+    pub mint_url: String,
+    pub timestamp: u64,
+}
+
+pub struct WorkloadLedger {
+    db: Database,
+}
+
+impl WorkloadLedger {
+    /// Open (or create) the ledger at `path`
+    pub fn open(path: &str) -> Result<Self> {
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create ledger directory for {}", path))?;
+            }
+        }
+
+        let db = Database::create(path)
+            .with_context(|| format!("Failed to open workload ledger {}", path))?;
+
+        // Create the tables up front so readers never see a missing table
+        let txn = db.begin_write()?;
+        txn.open_table(WORKLOADS)?;
+        txn.open_table(PAYMENTS)?;
+        txn.open_table(STATS)?;
+        txn.commit()?;
+
+        Ok(Self { db })
+    }
+
+    /// All recorded workloads, keyed by vmid
+    pub fn load_workloads(&self) -> Result<HashMap<u32, WorkloadInfo>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(WORKLOADS)?;
+
+        let mut workloads = HashMap::new();
+        for entry in table.iter()? {
+            let (vmid, data) = entry?;
+            match serde_json::from_slice::<WorkloadInfo>(data.value()) {
+                Ok(workload) => {
+                    workloads.insert(vmid.value(), workload);
+                }
+                Err(e) => tracing::warn!("Skipping unreadable ledger entry {}: {}", vmid.value(), e),
+            }
+        }
+
+        Ok(workloads)
+    }
+
+    /// Insert or update a workload
+    pub fn save_workload(&self, workload: &WorkloadInfo) -> Result<()> {
+        let data = serde_json::to_vec(workload)?;
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(WORKLOADS)?;
+            table.insert(workload.vmid, data.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Forget a workload
+    pub fn remove_workload(&self, vmid: u32) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(WORKLOADS)?;
+            table.remove(vmid)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Append a payment record
+    pub fn record_payment(&self, payment: &PaymentRecord) -> Result<()> {
+        let data = serde_json::to_vec(payment)?;
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(PAYMENTS)?;
+            let next = table.last()?.map(|(seq, _)| seq.value() + 1).unwrap_or(0);
+            table.insert(next, data.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// All payment records, oldest first
+    pub fn payments(&self) -> Result<Vec<PaymentRecord>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(PAYMENTS)?;
+
+        let mut payments = Vec::new();
+        for entry in table.iter()? {
+            let (_, data) = entry?;
+            payments.push(serde_json::from_slice(data.value())?);
+        }
+
+        Ok(payments)
+    }
+
+    /// Read a counter (0 if never set)
+    pub fn get_stat(&self, name: &str) -> Result<u64> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(STATS)?;
+        let value = table.get(name)?.map(|v| v.value()).unwrap_or(0);
+        Ok(value)
+    }
+
+    /// Overwrite a counter
+    pub fn set_stat(&self, name: &str, value: u64) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(STATS)?;
+            table.insert(name, value)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
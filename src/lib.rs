@@ -6,8 +6,11 @@
 // Core modules
 pub mod cashu;
 pub mod nostr;
+pub mod network_policy;
 pub mod sidecar_service;
 pub mod pod_provisioning;
+pub mod provisioning;
+pub mod admission;
 
 // Proxmox integration modules
 pub mod proxmox;
@@ -15,6 +18,18 @@ pub mod provider;
 pub mod discovery;
 pub mod compute;
 pub mod lxd;
+pub mod lxd_api;
+pub mod docker;
+pub mod kubernetes;
+pub mod unix_http;
+pub mod wallet;
+pub mod ledger;
+pub mod uptime;
+pub mod images;
+pub mod port_forward;
+pub mod transport;
+#[cfg(feature = "mock")]
+pub mod mock;
 
 // Re-export public types and functions
 pub use nostr::{NostrRelaySubscriber, RelayConfig, default_relay_config, custom_relay_config};
@@ -22,10 +37,15 @@ pub use nostr::{ProviderOfferContent, HeartbeatContent, CapacityInfo, ProviderIn
 pub use cashu::initialize_cashu;
 pub use proxmox::ProxmoxClient;
 pub use provider::{ProviderConfig, ProviderService};
+pub use transport::ProviderTransport;
 pub use discovery::DiscoveryClient;
 pub use compute::{ComputeBackend, ContainerConfig, NodeStatus};
 pub use lxd::LxdBackend;
+pub use lxd_api::{LxdApiBackend, LxdClient};
+pub use docker::DockerBackend;
+pub use kubernetes::KubernetesBackend;
+pub use wallet::CashuWallet;
 
 // Architecture notes:
 // - K8s mode: nginx + ngx_l402 → PodProvisioningService
-// - Proxmox mode: Nostr NIP-17 → ProviderService → ProxmoxClient
+// - Nostr mode: NIP-17 → ProviderService → ComputeBackend (Proxmox, LXD, Docker or Kubernetes)
@@ -3,22 +3,28 @@
 // Implements ComputeBackend using the 'lxc' command line tool.
 // This is suitable for single-node setups like a VPS.
 
-use std::process::Command;
+use std::io::Write;
+use std::process::{Command, Stdio};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use tracing::{info, warn};
-use crate::compute::{ComputeBackend, ContainerConfig, NodeStatus};
+use crate::compute::{local_node_status, ComputeBackend, ContainerConfig, NetworkPolicy, NodeStatus, WorkloadKind};
+use crate::images::{find_image, ImageEntry};
+use crate::provisioning::{authorized_keys, AUTHORIZED_KEYS_SCRIPT, ENV_SSH_KEYS, KEY_ONLY_SSH_SCRIPT, SSH_SETUP_SCRIPT};
 
 pub struct LxdBackend {
     storage_pool: String,
+    #[allow(dead_code)] // Containers get their NIC from the default profile
     network_device: String,
+    images: Vec<ImageEntry>,
 }
 
 impl LxdBackend {
-    pub fn new(storage_pool: &str, network_device: &str) -> Self {
+    pub fn new(storage_pool: &str, network_device: &str, images: Vec<ImageEntry>) -> Self {
         Self {
             storage_pool: storage_pool.to_string(),
             network_device: network_device.to_string(),
+            images,
         }
     }
 
@@ -36,6 +42,28 @@ impl LxdBackend {
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
 
+    /// Run lxc with `input` on stdin, so secrets stay off the command line
+    fn run_lxc_with_input(&self, args: &[&str], input: &str) -> Result<()> {
+        let mut child = Command::new("lxc")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute lxc command")?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes()).context("Failed to write to lxc stdin")?;
+        }
+
+        let output = child.wait_with_output().context("Failed to execute lxc command")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("lxc command failed: {}", stderr));
+        }
+        Ok(())
+    }
+
     /// Parse lxc list JSON output, treating empty stdout as an empty array.
     /// `lxc list --format json` returns empty stdout (not `[]`) when no containers exist.
     fn parse_lxc_json(raw: &str) -> Result<serde_json::Value> {
@@ -67,10 +95,80 @@ impl LxdBackend {
                 "No LXD storage pools found. Run `lxc storage create default dir` on the provider."
             ))
     }
+
+    /// First IPv4 address of an instance (eth0 in containers, enp5s0 or similar in VMs)
+    fn instance_ipv4(&self, name: &str) -> Result<Option<String>> {
+        let raw = self.run_lxc(&["list", name, "--format", "json"])?;
+        let instances = Self::parse_lxc_json(&raw)?;
+
+        Ok(instances.as_array()
+            .and_then(|a| a.first())
+            .and_then(|i| i.get("state"))
+            .and_then(|s| s.get("network"))
+            .and_then(first_ipv4))
+    }
+
+    /// Wait until `lxc exec` works, which for VMs means the LXD agent has booted
+    async fn wait_for_exec(&self, name: &str, timeout_secs: u64) -> Result<()> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout_secs);
+        loop {
+            match self.run_lxc(&["exec", name, "--", "true"]) {
+                Ok(_) => return Ok(()),
+                Err(e) if std::time::Instant::now() >= deadline => {
+                    return Err(e.context(format!("{} did not become ready within {}s", name, timeout_secs)));
+                }
+                Err(_) => tokio::time::sleep(std::time::Duration::from_secs(2)).await,
+            }
+        }
+    }
+
+    /// Create the workload's network ACL and attach it, with any bandwidth cap, to eth0
+    fn apply_network_policy(&self, name: &str, id: u32, policy: &NetworkPolicy) -> Result<()> {
+        let acl = acl_name(id);
+        // Remove any ACL left behind by an earlier workload with this ID
+        let _ = self.run_lxc(&["network", "acl", "delete", &acl]);
+        self.run_lxc(&["network", "acl", "create", &acl])
+            .context("Failed to create network ACL")?;
+
+        let rules = acl_egress_rules(policy).into_iter().map(|rule| ("egress", rule))
+            .chain(acl_ingress_rules().into_iter().map(|rule| ("ingress", rule)));
+        for (direction, rule) in rules {
+            let properties: Vec<String> = rule.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            let mut args = vec!["network", "acl", "rule", "add", &acl, direction];
+            args.extend(properties.iter().map(String::as_str));
+            self.run_lxc(&args).context("Failed to add network ACL rule")?;
+        }
+
+        let settings: Vec<String> = nic_network_config(&acl, policy).iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        let mut args = vec!["config", "device", "override", name, "eth0"];
+        args.extend(settings.iter().map(String::as_str));
+        self.run_lxc(&args).context("Failed to attach network policy to eth0")?;
+        Ok(())
+    }
+
+    /// Wait for a VM to get an IPv4 address from the bridge
+    async fn wait_for_ipv4(&self, name: &str, timeout_secs: u64) -> Result<String> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout_secs);
+        loop {
+            if let Some(ip) = self.instance_ipv4(name)? {
+                return Ok(ip);
+            }
+            if std::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("{} got no IPv4 address within {}s", name, timeout_secs));
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        }
+    }
 }
 
 #[async_trait]
 impl ComputeBackend for LxdBackend {
+    fn supported_kinds(&self) -> Vec<WorkloadKind> {
+        vec![WorkloadKind::Container, WorkloadKind::Vm]
+    }
+
     async fn find_available_id(&self, range_start: u32, range_end: u32) -> Result<u32> {
         let raw = self.run_lxc(&["list", "--format", "json"])?;
         let containers = Self::parse_lxc_json(&raw)?;
@@ -101,37 +199,58 @@ impl ComputeBackend for LxdBackend {
         let name = format!("paygress-{}", config.id);
 
         // 1. Launch container
-        // Resolve generic names to specific images
-        let image = match config.image.as_str() {
-            "alpine" => "images:alpine/3.19",
-            "ubuntu" => "ubuntu:22.04", // Default LTS
-            other => other,
-        };
+        // Resolve the catalog name to an LXD image alias
+        let image = find_image(&self.images, &config.image)
+            .and_then(|image| image.lxd_alias.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Image '{}' has no LXD alias in the catalog", config.image))?;
         
-        info!("Creating LXD container {} with image {}", name, image);
+        let is_vm = config.kind == WorkloadKind::Vm;
+        info!("Creating LXD {} {} with image {}", if is_vm { "VM" } else { "container" }, name, image);
         
         // Limits
         let cpu_limit = format!("limits.cpu={}", config.cpu_cores);
         let mem_limit = format!("limits.memory={}MB", config.memory_mb);
+        let root_disk: Vec<String> = root_disk_config(config).into_iter()
+            .map(|(key, value)| format!("root,{}={}", key, value))
+            .collect();
         
         let pool = self.resolve_storage_pool()?;
         info!("Using storage pool: {}", pool);
 
-        self.run_lxc(&[
-            "launch", image, &name,
+        let mut launch_args = vec![
+            "init", image, &name,
             "-s", &pool,
             "-c", &cpu_limit,
             "-c", &mem_limit,
-            "-c", "security.nesting=true",
-        ])?;
+        ];
+        for setting in &root_disk {
+            launch_args.extend(["-d", setting.as_str()]);
+        }
+        if is_vm {
+            launch_args.push("--vm");
+        } else {
+            // Nesting is a container-only setting; VMs have their own kernel
+            launch_args.extend(["-c", "security.nesting=true"]);
+        }
+        self.run_lxc(&launch_args)?;
+
+        // Attach the network policy before first boot, so there's no unfiltered window
+        self.apply_network_policy(&name, config.id, &config.network)?;
+        self.run_lxc(&["start", &name])?;
+
+        // VMs boot a full OS before the agent answers `lxc exec`
+        if is_vm {
+            self.wait_for_exec(&name, 180).await?;
+        }
 
         // 2. Set root password
         // We always set root password so user can access regardless of default user
-        let chpasswd_cmd = format!("echo 'root:{}' | chpasswd", config.password);
+        // chpasswd reads the password from stdin rather than a shell command line
+        let chpasswd_input = format!("root:{}\n", config.password);
         
         // Retry a few times as container starts up
         for _ in 0..10 {
-            match self.run_lxc(&["exec", &name, "--", "sh", "-c", &chpasswd_cmd]) {
+            match self.run_lxc_with_input(&["exec", &name, "--", "chpasswd"], &chpasswd_input) {
                 Ok(_) => break,
                 Err(_) => tokio::time::sleep(std::time::Duration::from_secs(1)).await,
             }
@@ -139,144 +258,179 @@ impl ComputeBackend for LxdBackend {
         
         // 3. Generic SSH Setup & Hardening
         // Attempt to install/enable SSH on various distros (Alpine, Debian, etc)
-        let setup_script = r#"
-            # Detect package manager and install SSH if missing
-            if command -v apk >/dev/null; then
-                # Alpine
-                apk add --no-cache openssh
-                rc-update add sshd default
-                service sshd start
-            elif command -v apt-get >/dev/null; then
-                # Debian/Ubuntu
-                # Usually installed, but ensure it runs
-                systemctl enable ssh
-                systemctl start ssh
-            fi
-            
-            # Configure SSH for root access with password
-            # Check if config exists
-            if [ -f /etc/ssh/sshd_config ]; then
-                # Remove cloud-init config that disables password auth
-                rm -f /etc/ssh/sshd_config.d/*-cloudimg-settings.conf
-
-                sed -i 's/#PermitRootLogin.*/PermitRootLogin yes/' /etc/ssh/sshd_config
-                sed -i 's/PermitRootLogin.*/PermitRootLogin yes/' /etc/ssh/sshd_config
-                sed -i 's/PasswordAuthentication no/PasswordAuthentication yes/' /etc/ssh/sshd_config
-                
-                # Restart service
-                service sshd restart || systemctl restart ssh || systemctl restart sshd
-            fi
-        "#;
-
-        let _ = self.run_lxc(&["exec", &name, "--", "sh", "-c", setup_script]);
+        let _ = self.run_lxc(&["exec", &name, "--", "sh", "-c", SSH_SETUP_SCRIPT]);
+
+        // 3b. Public keys, passed to the script through its environment
+        if !config.ssh_public_keys.is_empty() {
+            let keys_env = format!("{}={}", ENV_SSH_KEYS, authorized_keys(&config.ssh_public_keys));
+            self.run_lxc(&["exec", &name, "--env", &keys_env, "--", "sh", "-c", AUTHORIZED_KEYS_SCRIPT])
+                .context("Failed to install SSH public keys")?;
+        }
+
+        if config.disable_password_auth {
+            self.run_lxc(&["exec", &name, "--", "sh", "-c", KEY_ONLY_SSH_SCRIPT])
+                .context("Failed to disable SSH password authentication")?;
+        }
 
         // 4. Setup Port Forwarding
         if let Some(port) = config.host_port {
-            info!("Setting up port forwarding: Host {} -> Container 22", port);
-            // lxc config device add <container> ssh proxy listen=tcp:0.0.0.0:<port> connect=tcp:127.0.0.1:22
-            self.run_lxc(&[
-                "config", "device", "add", &name, "ssh-proxy", "proxy",
-                &format!("listen=tcp:0.0.0.0:{}", port),
-                "connect=tcp:127.0.0.1:22",
-            ])?;
+            if is_vm {
+                // VMs only support NAT proxies, which need the VM's address pinned
+                let ip = self.wait_for_ipv4(&name, 120).await?;
+                info!("Setting up port forwarding: Host {} -> VM {}:22", port, ip);
+                // eth0 was already overridden locally for the network policy
+                self.run_lxc(&[
+                    "config", "device", "set", &name, "eth0",
+                    &format!("ipv4.address={}", ip),
+                ])?;
+                self.run_lxc(&[
+                    "config", "device", "add", &name, "ssh-proxy", "proxy",
+                    &format!("listen=tcp:0.0.0.0:{}", port),
+                    &format!("connect=tcp:{}:22", ip),
+                    "nat=true",
+                ])?;
+            } else {
+                info!("Setting up port forwarding: Host {} -> Container 22", port);
+                // lxc config device add <container> ssh proxy listen=tcp:0.0.0.0:<port> connect=tcp:127.0.0.1:22
+                self.run_lxc(&[
+                    "config", "device", "add", &name, "ssh-proxy", "proxy",
+                    &format!("listen=tcp:0.0.0.0:{}", port),
+                    "connect=tcp:127.0.0.1:22",
+                ])?;
+            }
         }
 
         Ok(name)
     }
 
-    async fn start_container(&self, id: u32) -> Result<()> {
+    async fn start_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
         let name = format!("paygress-{}", id);
         self.run_lxc(&["start", &name])?;
         Ok(())
     }
 
-    async fn stop_container(&self, id: u32) -> Result<()> {
+    async fn stop_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
         let name = format!("paygress-{}", id);
         self.run_lxc(&["stop", &name])?;
         Ok(())
     }
 
-    async fn delete_container(&self, id: u32) -> Result<()> {
+    async fn delete_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
         let name = format!("paygress-{}", id);
         self.run_lxc(&["delete", &name, "--force"])?;
+
+        // Workloads from before network policies have no ACL
+        if let Err(e) = self.run_lxc(&["network", "acl", "delete", &acl_name(id)]) {
+            warn!("Failed to delete network ACL for {}: {}", name, e);
+        }
         Ok(())
     }
 
-    async fn get_node_status(&self) -> Result<NodeStatus> {
-        // Use `free -b` for memory
-        let mem_output = Command::new("free").arg("-b").output()?;
-        let mem_str = String::from_utf8_lossy(&mem_output.stdout);
-        
-        // Simple parsing of `free` output
-        //               total        used        free      shared  buff/cache   available
-        // Mem:    16723824640  1038573568 1234567890 ...
-        let mut memory_total = 0;
-        let mut memory_used = 0;
-        
-        for line in mem_str.lines() {
-            if line.starts_with("Mem:") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 3 {
-                    memory_total = parts[1].parse().unwrap_or(0);
-                    memory_used = parts[2].parse().unwrap_or(0);
-                }
-            }
-        }
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>> {
+        let raw = self.run_lxc(&["list", "--format", "json"])?;
+        let containers = Self::parse_lxc_json(&raw)?;
 
-        // Use `df -B1 /` for disk
-        let disk_output = Command::new("df").args(["-B1", "/"]).output()?;
-        let disk_str = String::from_utf8_lossy(&disk_output.stdout);
-        
-        let mut disk_total = 0;
-        let mut disk_used = 0;
-        
-        for line in disk_str.lines().skip(1) { // Skip header
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 3 {
-                disk_total = parts[1].parse().unwrap_or(0);
-                disk_used = parts[2].parse().unwrap_or(0);
-                break;
-            }
-        }
-        
-        // Use /proc/loadavg for CPU
-        let loadavg = std::fs::read_to_string("/proc/loadavg").unwrap_or_default();
-        let load_1min: f64 = loadavg.split_whitespace().next().unwrap_or("0").parse().unwrap_or(0.0);
-        let cpu_cores = num_cpus::get() as f64;
-        let cpu_usage = (load_1min / cpu_cores).min(1.0);
-
-        Ok(NodeStatus {
-            cpu_usage,
-            memory_used,
-            memory_total,
-            disk_used,
-            disk_total,
-        })
+        Ok(containers.as_array()
+            .unwrap_or(&vec![])
+            .iter()
+            .filter_map(|c| {
+                let name = c.get("name").and_then(|n| n.as_str())?;
+                let id = name.strip_prefix("paygress-").and_then(|id| id.parse::<u32>().ok())?;
+                let kind = match c.get("type").and_then(|t| t.as_str()) {
+                    Some("virtual-machine") => WorkloadKind::Vm,
+                    _ => WorkloadKind::Container,
+                };
+                Some((id, kind))
+            })
+            .collect())
     }
 
-    async fn get_container_ip(&self, id: u32) -> Result<Option<String>> {
+    async fn get_node_status(&self) -> Result<NodeStatus> {
+        local_node_status()
+    }
+
+    async fn get_container_ip(&self, id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
         let name = format!("paygress-{}", id);
-        let raw = self.run_lxc(&["list", &name, "--format", "json"])?;
-        let containers = Self::parse_lxc_json(&raw)?;
-        
-        if let Some(container) = containers.as_array().and_then(|a| a.first()) {
-            // Traverse json to find eth0 ipv4
-            // state -> network -> eth0 -> addresses -> [family=inet] -> address
-            if let Some(networks) = container.get("state").and_then(|s| s.get("network")) {
-                if let Some(eth0) = networks.get("eth0") {
-                     if let Some(addrs) = eth0.get("addresses").and_then(|a| a.as_array()) {
-                         for addr in addrs {
-                             if addr.get("family").and_then(|f| f.as_str()) == Some("inet") {
-                                 if let Some(ip) = addr.get("address").and_then(|a| a.as_str()) {
-                                     return Ok(Some(ip.to_string()));
-                                 }
-                             }
-                         }
-                     }
-                }
-            }
-        }
-        
-        Ok(None)
+        self.instance_ipv4(&name)
+    }
+}
+
+// Shared with the REST API backend (lxd_api)
+
+/// Network ACL holding a workload's rules
+pub(crate) fn acl_name(id: u32) -> String {
+    format!("paygress-{}", id)
+}
+
+/// Egress rules of a workload's ACL, as LXD rule properties. LXD always lets
+/// DHCP and DNS to the bridge through, so blocking private ranges is safe.
+pub(crate) fn acl_egress_rules(policy: &NetworkPolicy) -> Vec<Vec<(&'static str, String)>> {
+    let mut rules = Vec::new();
+
+    let ranges = policy.blocked_ranges();
+    if !ranges.is_empty() {
+        rules.push(vec![("action", "reject".to_string()), ("destination", ranges.join(","))]);
+    }
+    for port in policy.blocked_tcp_ports() {
+        rules.push(vec![
+            ("action", "reject".to_string()),
+            ("protocol", "tcp".to_string()),
+            ("destination_port", port.to_string()),
+        ]);
     }
+    rules
+}
+
+/// Ingress rules of a workload's ACL. Everything but SSH is rejected, so other
+/// workloads on the bridge can't reach it whatever their own egress policy is.
+pub(crate) fn acl_ingress_rules() -> Vec<Vec<(&'static str, String)>> {
+    vec![vec![
+        ("action", "allow".to_string()),
+        ("protocol", "tcp".to_string()),
+        ("destination_port", "22".to_string()),
+    ]]
+}
+
+/// eth0 settings attaching a workload's ACL and bandwidth cap
+pub(crate) fn nic_network_config(acl: &str, policy: &NetworkPolicy) -> Vec<(&'static str, String)> {
+    let egress_action = if policy.allows_egress() { "allow" } else { "reject" };
+    let mut settings = vec![
+        ("security.acls", acl.to_string()),
+        ("security.acls.default.egress.action", egress_action.to_string()),
+        ("security.acls.default.ingress.action", "reject".to_string()),
+    ];
+    if let Some(mbit) = policy.bandwidth_mbit {
+        settings.push(("limits.ingress", format!("{}Mbit", mbit)));
+        settings.push(("limits.egress", format!("{}Mbit", mbit)));
+    }
+    settings
+}
+
+/// Root disk settings for a workload's size and IOPS cap. Sizes aren't enforced
+/// for containers on `dir` pools; btrfs, zfs and lvm pools apply them.
+pub(crate) fn root_disk_config(config: &ContainerConfig) -> Vec<(&'static str, String)> {
+    let mut settings = vec![("size", format!("{}GiB", config.storage_gb))];
+    if let Some(iops) = config.disk_iops {
+        settings.push(("limits.read", format!("{}iops", iops)));
+        settings.push(("limits.write", format!("{}iops", iops)));
+    }
+    settings
+}
+
+/// First IPv4 address in an instance's state.network map (eth0 in containers, enp5s0 or similar in VMs)
+pub(crate) fn first_ipv4(networks: &serde_json::Value) -> Option<String> {
+    let networks = networks.as_object()?;
+
+    // <iface> -> addresses -> [family=inet] -> address
+    let mut interfaces: Vec<(&String, &serde_json::Value)> = networks.iter()
+        .filter(|(iface, _)| iface.as_str() != "lo")
+        .collect();
+    interfaces.sort_by_key(|(iface, _)| iface.as_str() != "eth0");
+
+    interfaces.into_iter()
+        .filter_map(|(_, iface)| iface.get("addresses").and_then(|a| a.as_array()))
+        .flatten()
+        .find(|addr| addr.get("family").and_then(|f| f.as_str()) == Some("inet"))
+        .and_then(|addr| addr.get("address").and_then(|a| a.as_str()))
+        .map(str::to_string)
 }
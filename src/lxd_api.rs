@@ -0,0 +1,887 @@
+// LXD REST API Backend
+//
+// Implements ComputeBackend against the LXD REST API instead of the `lxc` tool.
+// Talks to the local daemon over its unix socket, or to a remote LXD server or
+// cluster over HTTPS with a trusted client certificate. Long-running calls return
+// background operations, which are awaited through /1.0/operations/<id>/wait.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hyper::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::compute::{ComputeBackend, ContainerConfig, NetworkPolicy, NodeStatus, WorkloadKind};
+use crate::images::{find_image, ImageEntry};
+use crate::lxd::{acl_egress_rules, acl_ingress_rules, acl_name, first_ipv4, nic_network_config, root_disk_config};
+use crate::provisioning::{
+    authorized_keys, AUTHORIZED_KEYS_SCRIPT, ENV_SSH_KEYS, ENV_SSH_PASSWORD, KEY_ONLY_SSH_SCRIPT, ROOT_PASSWORD_SCRIPT,
+    SSH_SETUP_SCRIPT,
+};
+use crate::unix_http;
+
+/// Socket used by the snap package, which is how bootstrap installs LXD
+const SNAP_SOCKET_PATH: &str = "/var/snap/lxd/common/lxd/unix.socket";
+const DEFAULT_SOCKET_PATH: &str = "/var/lib/lxd/unix.socket";
+
+// Image downloads happen inside the create operation, so allow it plenty of time
+const OPERATION_TIMEOUT_SECS: u64 = 600;
+// Each /wait call blocks server-side for at most this long before we poll again
+const OPERATION_POLL_SECS: u64 = 30;
+
+/// Image servers behind the remote prefixes used in `lxd_alias` (e.g. "ubuntu:22.04")
+const IMAGE_REMOTES: &[(&str, &str)] = &[
+    ("ubuntu", "https://cloud-images.ubuntu.com/releases"),
+    ("ubuntu-daily", "https://cloud-images.ubuntu.com/daily"),
+    ("images", "https://images.lxd.canonical.com"),
+];
+
+/// Local LXD socket: the snap's if present, otherwise the classic path
+pub fn default_socket_path() -> String {
+    if Path::new(SNAP_SOCKET_PATH).exists() {
+        SNAP_SOCKET_PATH.to_string()
+    } else {
+        DEFAULT_SOCKET_PATH.to_string()
+    }
+}
+
+/// Failure talking to LXD
+#[derive(Debug)]
+pub enum LxdError {
+    /// The socket or HTTPS endpoint couldn't be reached
+    Transport(String),
+    /// LXD rejected the request (`status` is LXD's error_code, an HTTP status)
+    Api { status: u16, message: String },
+    /// A background operation failed or didn't finish in time
+    Operation { id: String, message: String },
+    /// A command run inside an instance exited non-zero
+    Exec { status: i64 },
+    /// The response didn't match the documented API
+    Decode(String),
+}
+
+impl LxdError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, LxdError::Api { status: 404, .. })
+    }
+}
+
+impl fmt::Display for LxdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LxdError::Transport(e) => write!(f, "LXD unreachable: {}", e),
+            LxdError::Api { status, message } => write!(f, "LXD API error {}: {}", status, message),
+            LxdError::Operation { id, message } => write!(f, "LXD operation {} failed: {}", id, message),
+            LxdError::Exec { status } => write!(f, "command exited with status {}", status),
+            LxdError::Decode(e) => write!(f, "Unexpected LXD response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LxdError {}
+
+/// Standard LXD response envelope
+#[derive(Debug, Deserialize)]
+struct LxdResponse {
+    #[serde(rename = "type")]
+    response_type: String, // "sync", "async" or "error"
+    #[serde(default)]
+    error_code: u16,
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    operation: String, // "/1.0/operations/<id>" for async responses
+    #[serde(default)]
+    metadata: Value,
+}
+
+/// Background operation, as returned by /1.0/operations/<id>/wait
+#[derive(Debug, Deserialize)]
+struct LxdOperation {
+    id: String,
+    status_code: u16, // 200 success, 400 failure, 401 cancelled, 103 still running
+    #[serde(default)]
+    err: String,
+    #[serde(default)]
+    metadata: Value,
+}
+
+enum Transport {
+    Unix(PathBuf),
+    Https { base_url: String, client: reqwest::Client },
+}
+
+pub struct LxdClient {
+    transport: Transport,
+}
+
+impl LxdClient {
+    /// Connect to the local daemon through its unix socket
+    pub fn unix(socket_path: &str) -> Self {
+        Self { transport: Transport::Unix(PathBuf::from(socket_path)) }
+    }
+
+    /// Connect to a remote server (e.g. https://10.0.0.5:8443) with a client certificate
+    /// it trusts. The key must be PKCS#8 PEM. `server_cert` pins the server's
+    /// self-signed certificate, which LXD doesn't issue for its IP addresses.
+    pub fn https(url: &str, client_cert: &str, client_key: &str, server_cert: Option<&str>) -> Result<Self> {
+        let cert = std::fs::read(client_cert)
+            .with_context(|| format!("Failed to read LXD client certificate {}", client_cert))?;
+        let key = std::fs::read(client_key)
+            .with_context(|| format!("Failed to read LXD client key {}", client_key))?;
+        let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key)
+            .context("Invalid LXD client certificate or key (the key must be PKCS#8)")?;
+
+        let mut builder = reqwest::Client::builder().identity(identity);
+        if let Some(path) = server_cert {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read LXD server certificate {}", path))?;
+            builder = builder
+                .add_root_certificate(reqwest::Certificate::from_pem(&pem)?)
+                .danger_accept_invalid_hostnames(true);
+        }
+
+        Ok(Self {
+            transport: Transport::Https {
+                base_url: url.trim_end_matches('/').to_string(),
+                client: builder.build()?,
+            },
+        })
+    }
+
+    /// Send one request and decode the response envelope
+    async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<LxdResponse, LxdError> {
+        debug!("LXD {} {}", method, path);
+
+        let (status, bytes) = match &self.transport {
+            Transport::Unix(socket) => unix_http::request(socket, method, path, body).await
+                .map_err(|e| LxdError::Transport(format!("{:#}", e)))?,
+            Transport::Https { base_url, client } => {
+                let mut request = client.request(method, format!("{}{}", base_url, path));
+                if let Some(body) = body {
+                    request = request.json(body);
+                }
+                let response = request.send().await
+                    .map_err(|e| LxdError::Transport(e.to_string()))?;
+                let status = response.status().as_u16();
+                let bytes = response.bytes().await
+                    .map_err(|e| LxdError::Transport(e.to_string()))?;
+                (status, bytes.to_vec())
+            }
+        };
+
+        let response: LxdResponse = serde_json::from_slice(&bytes)
+            .map_err(|e| LxdError::Decode(format!("HTTP {} from {}: {}", status, path, e)))?;
+
+        if response.response_type == "error" {
+            let status = if response.error_code != 0 { response.error_code } else { status };
+            return Err(LxdError::Api { status, message: response.error });
+        }
+
+        Ok(response)
+    }
+
+    /// Send a request, waiting for its background operation if it started one.
+    /// Returns the sync metadata, or the finished operation's metadata.
+    async fn call(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value, LxdError> {
+        let response = self.request(method, path, body).await?;
+
+        if response.response_type == "async" {
+            let operation = self.wait_operation(&response.operation).await?;
+            return Ok(operation.metadata);
+        }
+
+        Ok(response.metadata)
+    }
+
+    /// Block until the operation at `operation_path` finishes
+    async fn wait_operation(&self, operation_path: &str) -> Result<LxdOperation, LxdError> {
+        let deadline = Instant::now() + Duration::from_secs(OPERATION_TIMEOUT_SECS);
+        let wait_path = format!("{}/wait?timeout={}", operation_path, OPERATION_POLL_SECS);
+
+        loop {
+            let response = self.request(Method::GET, &wait_path, None).await?;
+            let operation: LxdOperation = serde_json::from_value(response.metadata)
+                .map_err(|e| LxdError::Decode(format!("operation {}: {}", operation_path, e)))?;
+
+            match operation.status_code {
+                200 => return Ok(operation),
+                100 | 103 | 105 if Instant::now() < deadline => continue, // created / running / pending
+                100 | 103 | 105 => {
+                    return Err(LxdError::Operation {
+                        id: operation.id,
+                        message: format!("still running after {}s", OPERATION_TIMEOUT_SECS),
+                    });
+                }
+                _ => return Err(LxdError::Operation { id: operation.id, message: operation.err }),
+            }
+        }
+    }
+
+    /// Names of all instances (every member's, on a cluster)
+    pub async fn list_instance_names(&self) -> Result<Vec<String>, LxdError> {
+        let urls = self.call(Method::GET, "/1.0/instances", None).await?;
+        Ok(names_from_urls(&urls))
+    }
+
+    /// Full instance objects (name, type, config, ...)
+    pub async fn list_instances(&self) -> Result<Vec<Value>, LxdError> {
+        let instances = self.call(Method::GET, "/1.0/instances?recursion=1", None).await?;
+        Ok(instances.as_array().cloned().unwrap_or_default())
+    }
+
+    pub async fn get_instance(&self, name: &str) -> Result<Value, LxdError> {
+        self.call(Method::GET, &format!("/1.0/instances/{}", name), None).await
+    }
+
+    /// Runtime state (status, network, ...)
+    pub async fn instance_state(&self, name: &str) -> Result<Value, LxdError> {
+        self.call(Method::GET, &format!("/1.0/instances/{}/state", name), None).await
+    }
+
+    pub async fn create_instance(&self, body: &Value) -> Result<(), LxdError> {
+        self.call(Method::POST, "/1.0/instances", Some(body)).await?;
+        Ok(())
+    }
+
+    /// Apply a partial update; LXD merges `config` and `devices` with the existing ones
+    pub async fn patch_instance(&self, name: &str, body: &Value) -> Result<(), LxdError> {
+        self.call(Method::PATCH, &format!("/1.0/instances/{}", name), Some(body)).await?;
+        Ok(())
+    }
+
+    /// Change power state: `action` is "start", "stop" or "restart"
+    pub async fn set_state(&self, name: &str, action: &str, force: bool) -> Result<(), LxdError> {
+        let body = json!({ "action": action, "timeout": 30, "force": force });
+        self.call(Method::PUT, &format!("/1.0/instances/{}/state", name), Some(&body)).await?;
+        Ok(())
+    }
+
+    pub async fn delete_instance(&self, name: &str) -> Result<(), LxdError> {
+        self.call(Method::DELETE, &format!("/1.0/instances/{}", name), None).await?;
+        Ok(())
+    }
+
+    /// Run a command in an instance and wait for it to exit successfully
+    pub async fn exec(&self, name: &str, command: &[&str], environment: &[(&str, String)]) -> Result<(), LxdError> {
+        let environment: serde_json::Map<String, Value> = environment.iter()
+            .map(|(key, value)| (key.to_string(), json!(value)))
+            .collect();
+        let body = json!({
+            "command": command,
+            "environment": environment,
+            "interactive": false,
+            "wait-for-websocket": false,
+            "record-output": false,
+        });
+        let result = self.call(Method::POST, &format!("/1.0/instances/{}/exec", name), Some(&body)).await?;
+
+        match result.get("return").and_then(|r| r.as_i64()) {
+            Some(0) => Ok(()),
+            Some(status) => Err(LxdError::Exec { status }),
+            None => Err(LxdError::Decode(format!("exec in {} returned no exit status", name))),
+        }
+    }
+
+    /// Create a network ACL from `{ "name", "egress", "ingress" }`
+    pub async fn create_network_acl(&self, body: &Value) -> Result<(), LxdError> {
+        self.call(Method::POST, "/1.0/network-acls", Some(body)).await?;
+        Ok(())
+    }
+
+    pub async fn delete_network_acl(&self, name: &str) -> Result<(), LxdError> {
+        self.call(Method::DELETE, &format!("/1.0/network-acls/{}", name), None).await?;
+        Ok(())
+    }
+
+    pub async fn list_storage_pools(&self) -> Result<Vec<String>, LxdError> {
+        let urls = self.call(Method::GET, "/1.0/storage-pools", None).await?;
+        Ok(names_from_urls(&urls))
+    }
+
+    /// Space used and total on a storage pool, in bytes
+    pub async fn storage_pool_space(&self, pool: &str) -> Result<(u64, u64), LxdError> {
+        let resources = self.call(Method::GET, &format!("/1.0/storage-pools/{}/resources", pool), None).await?;
+        let space = &resources["space"];
+        Ok((space["used"].as_u64().unwrap_or(0), space["total"].as_u64().unwrap_or(0)))
+    }
+
+    /// Hardware of the server (or cluster member) we're talking to
+    pub async fn server_resources(&self) -> Result<Value, LxdError> {
+        self.call(Method::GET, "/1.0/resources", None).await
+    }
+}
+
+/// "/1.0/instances/paygress-1000" -> "paygress-1000"
+fn names_from_urls(urls: &Value) -> Vec<String> {
+    urls.as_array()
+        .map(|urls| {
+            urls.iter()
+                .filter_map(|url| url.as_str())
+                .filter_map(|url| url.rsplit('/').next())
+                .map(|name| name.split('?').next().unwrap_or(name).to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Instance source for an `lxd_alias`: "remote:alias" pulls from a known image server,
+/// a bare alias uses an image already on the server
+fn image_source(alias: &str) -> Result<Value> {
+    let Some((remote, alias)) = alias.split_once(':') else {
+        return Ok(json!({ "type": "image", "alias": alias }));
+    };
+
+    let server = IMAGE_REMOTES.iter()
+        .find(|(name, _)| *name == remote)
+        .map(|(_, server)| *server)
+        .ok_or_else(|| anyhow::anyhow!("Unknown LXD image remote '{}'", remote))?;
+
+    Ok(json!({
+        "type": "image",
+        "mode": "pull",
+        "protocol": "simplestreams",
+        "server": server,
+        "alias": alias,
+    }))
+}
+
+pub struct LxdApiBackend {
+    client: LxdClient,
+    storage_pool: String,
+    images: Vec<ImageEntry>,
+}
+
+impl LxdApiBackend {
+    pub fn new(client: LxdClient, storage_pool: &str, images: Vec<ImageEntry>) -> Self {
+        Self {
+            client,
+            storage_pool: storage_pool.to_string(),
+            images,
+        }
+    }
+
+    /// Return the storage pool to use: the configured one if it exists,
+    /// otherwise the first pool on the server
+    async fn resolve_storage_pool(&self) -> Result<String> {
+        let names = self.client.list_storage_pools().await?;
+
+        if names.contains(&self.storage_pool) {
+            return Ok(self.storage_pool.clone());
+        }
+
+        names.into_iter().next()
+            .ok_or_else(|| anyhow::anyhow!(
+                "No LXD storage pools found. Run `lxc storage create default dir` on the provider."
+            ))
+    }
+
+    async fn instance_ipv4(&self, name: &str) -> Result<Option<String>> {
+        let state = self.client.instance_state(name).await?;
+        Ok(first_ipv4(&state["network"]))
+    }
+
+    /// Wait until exec works, which for VMs means the LXD agent has booted
+    async fn wait_for_exec(&self, name: &str, timeout_secs: u64) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
+            match self.client.exec(name, &["true"], &[]).await {
+                Ok(()) => return Ok(()),
+                Err(e) if Instant::now() >= deadline => {
+                    return Err(anyhow::Error::new(e)
+                        .context(format!("{} did not become ready within {}s", name, timeout_secs)));
+                }
+                Err(_) => tokio::time::sleep(Duration::from_secs(2)).await,
+            }
+        }
+    }
+
+    /// Create the workload's network ACL and attach it, with any bandwidth cap, to eth0
+    async fn apply_network_policy(&self, name: &str, id: u32, policy: &NetworkPolicy) -> Result<()> {
+        let acl = acl_name(id);
+        // Remove any ACL left behind by an earlier workload with this ID
+        let _ = self.client.delete_network_acl(&acl).await;
+
+        let to_json = |rules: Vec<Vec<(&'static str, String)>>| -> Vec<Value> {
+            rules.into_iter()
+                .map(|rule| {
+                    let mut rule: serde_json::Map<String, Value> = rule.into_iter()
+                        .map(|(key, value)| (key.to_string(), json!(value)))
+                        .collect();
+                    rule.insert("state".to_string(), json!("enabled"));
+                    Value::Object(rule)
+                })
+                .collect()
+        };
+        let egress = to_json(acl_egress_rules(policy));
+        let ingress = to_json(acl_ingress_rules());
+        self.client.create_network_acl(&json!({ "name": acl, "egress": egress, "ingress": ingress })).await
+            .context("Failed to create network ACL")?;
+
+        // Override the profile's NIC locally with the ACL settings
+        let instance = self.client.get_instance(name).await?;
+        let mut nic = instance["expanded_devices"]["eth0"].clone();
+        if !nic.is_object() {
+            return Err(anyhow::anyhow!("{} has no eth0 device to attach a network policy to", name));
+        }
+        for (key, value) in nic_network_config(&acl, policy) {
+            nic[key] = json!(value);
+        }
+        self.client.patch_instance(name, &json!({ "devices": { "eth0": nic } })).await
+            .context("Failed to attach network policy to eth0")?;
+        Ok(())
+    }
+
+    /// Wait for a VM to get an IPv4 address from the bridge
+    async fn wait_for_ipv4(&self, name: &str, timeout_secs: u64) -> Result<String> {
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
+            if let Some(ip) = self.instance_ipv4(name).await? {
+                return Ok(ip);
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("{} got no IPv4 address within {}s", name, timeout_secs));
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
+    /// Run one of the provisioning scripts, which read their inputs from `environment`
+    async fn shell(&self, name: &str, script: &str, environment: &[(&str, String)]) -> Result<(), LxdError> {
+        self.client.exec(name, &["sh", "-c", script], environment).await
+    }
+}
+
+#[async_trait]
+impl ComputeBackend for LxdApiBackend {
+    fn supported_kinds(&self) -> Vec<WorkloadKind> {
+        vec![WorkloadKind::Container, WorkloadKind::Vm]
+    }
+
+    async fn find_available_id(&self, range_start: u32, range_end: u32) -> Result<u32> {
+        let existing_ids: Vec<u32> = self.client.list_instance_names().await?
+            .iter()
+            .filter_map(|name| name.strip_prefix("paygress-"))
+            .filter_map(|id| id.parse::<u32>().ok())
+            .collect();
+
+        (range_start..=range_end)
+            .find(|id| !existing_ids.contains(id))
+            .ok_or_else(|| anyhow::anyhow!("No available IDs in range {}-{}", range_start, range_end))
+    }
+
+    async fn create_container(&self, config: &ContainerConfig) -> Result<String> {
+        let name = format!("paygress-{}", config.id);
+
+        // 1. Create and start the instance
+        let alias = find_image(&self.images, &config.image)
+            .and_then(|image| image.lxd_alias.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Image '{}' has no LXD alias in the catalog", config.image))?;
+
+        let is_vm = config.kind == WorkloadKind::Vm;
+        let pool = self.resolve_storage_pool().await?;
+        info!("Creating LXD {} {} with image {} on pool {}", if is_vm { "VM" } else { "container" }, name, alias, pool);
+
+        let mut instance_config = json!({
+            "limits.cpu": config.cpu_cores.to_string(),
+            "limits.memory": format!("{}MB", config.memory_mb),
+        });
+        if !is_vm {
+            // Nesting is a container-only setting; VMs have their own kernel
+            instance_config["security.nesting"] = json!("true");
+        }
+
+        let mut root_disk = json!({ "type": "disk", "path": "/", "pool": pool });
+        for (key, value) in root_disk_config(config) {
+            root_disk[key] = json!(value);
+        }
+
+        self.client.create_instance(&json!({
+            "name": name,
+            "type": if is_vm { "virtual-machine" } else { "container" },
+            "source": image_source(alias)?,
+            "config": instance_config,
+            "devices": {
+                "root": root_disk,
+            },
+        })).await.context("Failed to create LXD instance")?;
+
+        // Attach the network policy before first boot, so there's no unfiltered window
+        self.apply_network_policy(&name, config.id, &config.network).await?;
+
+        self.client.set_state(&name, "start", false).await
+            .context("Failed to start LXD instance")?;
+
+        // VMs boot a full OS before the agent accepts exec
+        if is_vm {
+            self.wait_for_exec(&name, 180).await?;
+        }
+
+        // 2. Set root password, retrying while the container starts up
+        let password_env = [(ENV_SSH_PASSWORD, config.password.clone())];
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.shell(&name, ROOT_PASSWORD_SCRIPT, &password_env).await {
+                Ok(()) => break,
+                Err(e) if attempts >= 10 => return Err(anyhow::Error::new(e).context("Failed to set root password")),
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+
+        // 3. SSH setup & hardening
+        self.shell(&name, SSH_SETUP_SCRIPT, &[]).await
+            .context("Failed to set up SSH")?;
+
+        if !config.ssh_public_keys.is_empty() {
+            let keys_env = [(ENV_SSH_KEYS, authorized_keys(&config.ssh_public_keys))];
+            self.shell(&name, AUTHORIZED_KEYS_SCRIPT, &keys_env).await
+                .context("Failed to install SSH public keys")?;
+        }
+
+        if config.disable_password_auth {
+            self.shell(&name, KEY_ONLY_SSH_SCRIPT, &[]).await
+                .context("Failed to disable SSH password authentication")?;
+        }
+
+        // 4. Port forwarding through a proxy device on the LXD host
+        if let Some(port) = config.host_port {
+            let devices = if is_vm {
+                // VMs only support NAT proxies, which need the VM's address pinned
+                let ip = self.wait_for_ipv4(&name, 120).await?;
+                info!("Setting up port forwarding: Host {} -> VM {}:22", port, ip);
+
+                // Override the profile's NIC locally with the pinned address
+                let instance = self.client.get_instance(&name).await?;
+                let mut nic = instance["expanded_devices"]["eth0"].clone();
+                if !nic.is_object() {
+                    return Err(anyhow::anyhow!("{} has no eth0 device to pin an address on", name));
+                }
+                nic["ipv4.address"] = json!(ip);
+
+                json!({
+                    "eth0": nic,
+                    "ssh-proxy": {
+                        "type": "proxy",
+                        "listen": format!("tcp:0.0.0.0:{}", port),
+                        "connect": format!("tcp:{}:22", ip),
+                        "nat": "true",
+                    },
+                })
+            } else {
+                info!("Setting up port forwarding: Host {} -> Container 22", port);
+                json!({
+                    "ssh-proxy": {
+                        "type": "proxy",
+                        "listen": format!("tcp:0.0.0.0:{}", port),
+                        "connect": "tcp:127.0.0.1:22",
+                    },
+                })
+            };
+
+            self.client.patch_instance(&name, &json!({ "devices": devices })).await
+                .context("Failed to add SSH proxy device")?;
+        }
+
+        Ok(name)
+    }
+
+    async fn start_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.client.set_state(&format!("paygress-{}", id), "start", false).await?;
+        Ok(())
+    }
+
+    async fn stop_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.client.set_state(&format!("paygress-{}", id), "stop", false).await?;
+        Ok(())
+    }
+
+    async fn delete_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        let name = format!("paygress-{}", id);
+
+        // Running instances can't be deleted, so force them off first
+        let state = self.client.instance_state(&name).await?;
+        if state["status"].as_str() != Some("Stopped") {
+            self.client.set_state(&name, "stop", true).await?;
+        }
+
+        self.client.delete_instance(&name).await?;
+
+        // Workloads from before network policies have no ACL
+        match self.client.delete_network_acl(&acl_name(id)).await {
+            Err(e) if !e.is_not_found() => warn!("Failed to delete network ACL for {}: {}", name, e),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>> {
+        Ok(self.client.list_instances().await?
+            .iter()
+            .filter_map(|instance| {
+                let name = instance["name"].as_str()?;
+                let id = name.strip_prefix("paygress-").and_then(|id| id.parse::<u32>().ok())?;
+                let kind = match instance["type"].as_str() {
+                    Some("virtual-machine") => WorkloadKind::Vm,
+                    _ => WorkloadKind::Container,
+                };
+                Some((id, kind))
+            })
+            .collect())
+    }
+
+    async fn get_node_status(&self) -> Result<NodeStatus> {
+        let resources = self.client.server_resources().await?;
+        let memory_total = resources["memory"]["total"].as_u64().unwrap_or(0);
+        let memory_used = resources["memory"]["used"].as_u64().unwrap_or(0);
+
+        let pool = self.resolve_storage_pool().await?;
+        let (disk_used, disk_total) = self.client.storage_pool_space(&pool).await?;
+
+        // The API exposes no load average (and the server may be remote), so report
+        // the share of CPU threads already committed to instances
+        let threads = resources["cpu"]["total"].as_u64().unwrap_or(0);
+        let committed: u64 = self.client.list_instances().await?
+            .iter()
+            .filter_map(|instance| instance["config"]["limits.cpu"].as_str())
+            .filter_map(|cpus| cpus.parse::<u64>().ok())
+            .sum();
+        let cpu_usage = if threads > 0 { (committed as f64 / threads as f64).min(1.0) } else { 0.0 };
+
+        Ok(NodeStatus {
+            cpu_usage,
+            cpu_total: threads * 1000,
+            memory_used,
+            memory_total,
+            disk_used,
+            disk_total,
+        })
+    }
+
+    async fn get_container_ip(&self, id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        match self.instance_ipv4(&format!("paygress-{}", id)).await {
+            Err(e) if e.downcast_ref::<LxdError>().is_some_and(LxdError::is_not_found) => Ok(None),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+
+    type Route = fn(&str, &str) -> Value;
+
+    /// Serve canned LXD responses on a temporary socket, recording "METHOD path" of each request
+    async fn mock_lxd(route: Route) -> (String, Arc<Mutex<Vec<String>>>) {
+        let path = std::env::temp_dir()
+            .join(format!("paygress-lxd-{}.sock", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        let listener = UnixListener::bind(&path).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let log = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut raw = Vec::new();
+                let mut buf = [0u8; 4096];
+                // Read the head plus a Content-Length body
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw);
+                    if let Some(head_end) = text.find("\r\n\r\n") {
+                        let length = text[..head_end].lines()
+                            .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap_or(0)))
+                            .unwrap_or(0);
+                        if raw.len() >= head_end + 4 + length {
+                            break;
+                        }
+                    }
+                }
+
+                let text = String::from_utf8_lossy(&raw);
+                let mut request_line = text.lines().next().unwrap().split_whitespace();
+                let (method, path) = (request_line.next().unwrap().to_string(), request_line.next().unwrap().to_string());
+                log.lock().unwrap().push(format!("{} {}", method, path));
+
+                let body = route(&method, &path).to_string();
+                let status = if body.contains("\"type\":\"error\"") { "404 Not Found" } else { "200 OK" };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status, body.len(), body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (path, requests)
+    }
+
+    fn sync(metadata: Value) -> Value {
+        json!({ "type": "sync", "status": "Success", "status_code": 200, "metadata": metadata })
+    }
+
+    fn operation(id: &str) -> Value {
+        json!({ "type": "async", "status": "Operation created", "status_code": 100, "operation": format!("/1.0/operations/{}", id) })
+    }
+
+    fn backend(socket: &str) -> LxdApiBackend {
+        LxdApiBackend::new(LxdClient::unix(socket), "default", crate::images::default_catalog(""))
+    }
+
+    #[tokio::test]
+    async fn test_create_waits_on_operations() {
+        let (socket, requests) = mock_lxd(|method, path| match (method, path) {
+            ("GET", "/1.0/storage-pools") => sync(json!(["/1.0/storage-pools/default"])),
+            ("POST", "/1.0/instances") => operation("create"),
+            ("POST", "/1.0/network-acls") => sync(json!({})),
+            ("GET", "/1.0/instances/paygress-1000") => {
+                sync(json!({ "expanded_devices": { "eth0": { "type": "nic", "network": "lxdbr0", "name": "eth0" } } }))
+            }
+            ("PUT", "/1.0/instances/paygress-1000/state") => operation("start"),
+            ("POST", "/1.0/instances/paygress-1000/exec") => operation("exec"),
+            ("PATCH", "/1.0/instances/paygress-1000") => sync(json!({})),
+            (_, p) if p.starts_with("/1.0/operations/exec/wait") => {
+                sync(json!({ "id": "exec", "status_code": 200, "metadata": { "return": 0 } }))
+            }
+            (_, p) if p.starts_with("/1.0/operations/") => sync(json!({ "id": "op", "status_code": 200 })),
+            _ => json!({ "type": "error", "error": "not found", "error_code": 404 }),
+        }).await;
+
+        let config = ContainerConfig {
+            id: 1000,
+            kind: WorkloadKind::Container,
+            name: "paygress-1000".to_string(),
+            image: "ubuntu-22.04".to_string(),
+            cpu_cores: 1,
+            memory_mb: 1024,
+            storage_gb: 10,
+            disk_iops: None,
+            password: "secret".to_string(),
+            ssh_public_keys: Vec::new(),
+            disable_password_auth: false,
+            host_port: Some(30000),
+            duration_secs: 3600,
+            network: Default::default(),
+        };
+        let name = backend(&socket).create_container(&config).await.unwrap();
+        assert_eq!(name, "paygress-1000");
+
+        let requests = requests.lock().unwrap();
+        let create = requests.iter().position(|r| r == "POST /1.0/instances").unwrap();
+        let start = requests.iter().position(|r| r == "PUT /1.0/instances/paygress-1000/state").unwrap();
+        assert!(requests[create + 1].starts_with("GET /1.0/operations/create/wait"));
+        assert!(requests[start + 1].starts_with("GET /1.0/operations/start/wait"));
+        // The network policy is in place before the instance first boots
+        let acl = requests.iter().position(|r| r == "POST /1.0/network-acls").unwrap();
+        assert!(create < acl && acl < start);
+        assert_eq!(requests.last().unwrap(), "PATCH /1.0/instances/paygress-1000");
+    }
+
+    #[tokio::test]
+    async fn test_failed_ssh_setup_fails_create() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static EXECS: AtomicUsize = AtomicUsize::new(0);
+
+        let (socket, _) = mock_lxd(|method, path| match (method, path) {
+            ("GET", "/1.0/storage-pools") => sync(json!(["/1.0/storage-pools/default"])),
+            ("POST", "/1.0/instances") => operation("create"),
+            ("POST", "/1.0/network-acls") => sync(json!({})),
+            ("GET", "/1.0/instances/paygress-1001") => {
+                sync(json!({ "expanded_devices": { "eth0": { "type": "nic", "network": "lxdbr0", "name": "eth0" } } }))
+            }
+            ("PUT", "/1.0/instances/paygress-1001/state") => operation("start"),
+            ("POST", "/1.0/instances/paygress-1001/exec") => operation("exec"),
+            ("PATCH", "/1.0/instances/paygress-1001") => sync(json!({})),
+            (_, p) if p.starts_with("/1.0/operations/exec/wait") => {
+                // The password is set, then the SSH setup script exits non-zero
+                let status = if EXECS.fetch_add(1, Ordering::SeqCst) == 0 { 0 } else { 1 };
+                sync(json!({ "id": "exec", "status_code": 200, "metadata": { "return": status } }))
+            }
+            (_, p) if p.starts_with("/1.0/operations/") => sync(json!({ "id": "op", "status_code": 200 })),
+            _ => json!({ "type": "error", "error": "not found", "error_code": 404 }),
+        }).await;
+
+        let config = ContainerConfig {
+            id: 1001,
+            kind: WorkloadKind::Container,
+            name: "paygress-1001".to_string(),
+            image: "ubuntu-22.04".to_string(),
+            cpu_cores: 1,
+            memory_mb: 1024,
+            storage_gb: 10,
+            disk_iops: None,
+            password: "secret".to_string(),
+            ssh_public_keys: Vec::new(),
+            disable_password_auth: false,
+            host_port: Some(30001),
+            duration_secs: 3600,
+            network: Default::default(),
+        };
+        let error = backend(&socket).create_container(&config).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<LxdError>(), Some(LxdError::Exec { status: 1 })), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_structured() {
+        let (socket, _) = mock_lxd(|method, path| match (method, path) {
+            ("PUT", "/1.0/instances/paygress-1/state") => operation("start"),
+            (_, p) if p.starts_with("/1.0/operations/start/wait") => {
+                sync(json!({ "id": "start", "status_code": 400, "err": "Failed to start device \"eth0\"" }))
+            }
+            _ => json!({ "type": "error", "error": "Instance not found", "error_code": 404 }),
+        }).await;
+        let client = LxdClient::unix(&socket);
+
+        match client.set_state("paygress-1", "start", false).await {
+            Err(LxdError::Operation { id, message }) => {
+                assert_eq!(id, "start");
+                assert!(message.contains("eth0"));
+            }
+            other => panic!("expected operation error, got {:?}", other.map(|_| ())),
+        }
+
+        let missing = client.instance_state("paygress-2").await.unwrap_err();
+        assert!(missing.is_not_found(), "{}", missing);
+        assert_eq!(backend(&socket).get_container_ip(2, WorkloadKind::Container).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_containers_reads_kinds() {
+        let (socket, _) = mock_lxd(|_, _| sync(json!([
+            { "name": "paygress-1000", "type": "container" },
+            { "name": "paygress-1001", "type": "virtual-machine" },
+            { "name": "unrelated", "type": "container" },
+        ]))).await;
+
+        let mut workloads = backend(&socket).list_containers().await.unwrap();
+        workloads.sort_by_key(|(id, _)| *id);
+        assert_eq!(workloads, vec![(1000, WorkloadKind::Container), (1001, WorkloadKind::Vm)]);
+    }
+
+    #[test]
+    fn test_ingress_only_allows_ssh() {
+        let policy = NetworkPolicy { egress: crate::network_policy::Egress::Unrestricted, ..Default::default() };
+        let settings = nic_network_config("paygress-1000", &policy);
+        assert!(settings.contains(&("security.acls.default.ingress.action", "reject".to_string())));
+        assert!(settings.contains(&("security.acls.default.egress.action", "allow".to_string())));
+        assert_eq!(acl_ingress_rules(), vec![vec![
+            ("action", "allow".to_string()),
+            ("protocol", "tcp".to_string()),
+            ("destination_port", "22".to_string()),
+        ]]);
+    }
+}
@@ -9,11 +9,10 @@ use std::sync::Arc;
 use tracing_subscriber::{self, EnvFilter};
 
 mod interfaces;
-mod pod_provisioning;
 mod mcp;
-mod cashu;
-mod nostr; // Still used for PodSpec type
-mod sidecar_service;
+
+// Shared with the library rather than compiled again here
+use paygress::{admission, cashu, nostr, pod_provisioning, provisioning, sidecar_service};
 
 use crate::pod_provisioning::PodProvisioningService;
 use crate::interfaces::run_all_interfaces;
@@ -121,6 +120,32 @@ fn get_sidecar_config() -> crate::sidecar_service::SidecarConfig {
                 .collect()
         },
         pod_specs: get_pod_specs_from_env(),
+        pod_networking: match std::env::var("POD_NETWORKING") {
+            Ok(value) => crate::sidecar_service::PodNetworking::parse(&value).unwrap_or_else(|| {
+                tracing::error!("❌ Error: POD_NETWORKING must be host, nodeport or loadbalancer (got {})", value);
+                std::process::exit(1);
+            }),
+            Err(_) => crate::sidecar_service::PodNetworking::default(),
+        },
+        overcommit: crate::admission::OvercommitRatios {
+            cpu: overcommit_ratio_from_env("OVERCOMMIT_CPU"),
+            memory: overcommit_ratio_from_env("OVERCOMMIT_MEMORY"),
+            disk: overcommit_ratio_from_env("OVERCOMMIT_DISK"),
+        },
+    }
+}
+
+/// Overcommit ratio from `name`, 1.0 (no overcommit) when unset
+fn overcommit_ratio_from_env(name: &str) -> f64 {
+    match std::env::var(name) {
+        Ok(value) => match value.parse::<f64>() {
+            Ok(ratio) if ratio > 0.0 => ratio,
+            _ => {
+                tracing::error!("❌ Error: {} must be a positive number (got {})", name, value);
+                std::process::exit(1);
+            }
+        },
+        Err(_) => 1.0,
     }
 }
 
@@ -129,5 +129,9 @@ pub struct SpawnPodRequest {
     pub ssh_username: String,
     pub ssh_password: String,
     pub user_pubkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_public_keys: Vec<String>,
+    #[serde(default)]
+    pub disable_password_auth: bool,
 }
 
@@ -51,7 +51,9 @@ pub fn handle_tools_list(request: &Value) -> Value {
                             "pod_image": {"type": "string", "description": "Container image to use for the pod"},
                             "ssh_username": {"type": "string", "description": "SSH username for accessing the pod"},
                             "ssh_password": {"type": "string", "description": "SSH password for accessing the pod"},
-                            "user_pubkey": {"type": "string", "description": "Optional user public key for identification"}
+                            "user_pubkey": {"type": "string", "description": "Optional user public key for identification"},
+                            "ssh_public_keys": {"type": "array", "items": {"type": "string"}, "description": "Optional OpenSSH public keys to install for the user"},
+                            "disable_password_auth": {"type": "boolean", "description": "Only allow key login (requires ssh_public_keys)"}
                         },
                         "required": ["cashu_token", "pod_image", "ssh_username", "ssh_password"]
                     }
@@ -109,6 +111,10 @@ pub async fn handle_tools_call_http(http_client: &PaywalledHttpClient, request:
                 ssh_username: arguments["ssh_username"].as_str().unwrap_or("").to_string(),
                 ssh_password: arguments["ssh_password"].as_str().unwrap_or("").to_string(),
                 user_pubkey: arguments["user_pubkey"].as_str().map(|s| s.to_string()),
+                ssh_public_keys: arguments["ssh_public_keys"].as_array()
+                    .map(|keys| keys.iter().filter_map(|k| k.as_str().map(|s| s.to_string())).collect())
+                    .unwrap_or_default(),
+                disable_password_auth: arguments["disable_password_auth"].as_bool().unwrap_or(false),
             };
 
             match http_client.spawn_pod(spawn_request).await {
@@ -255,7 +261,7 @@ pub async fn handle_tools_call_http(http_client: &PaywalledHttpClient, request:
                     let min_duration = response["minimum_duration_seconds"].as_u64().unwrap_or(0);
                     let whitelisted_mints = response["whitelisted_mints"].as_array()
                         .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
-                        .unwrap_or_else(|| vec![]);
+                        .unwrap_or_default();
                     
                     let empty_vec = vec![];
                     let pod_specs = response["pod_specs"].as_array().unwrap_or(&empty_vec);
@@ -0,0 +1,460 @@
+// Mock Provider Components
+//
+// In-memory stand-ins for the compute backend, the Nostr relays and the Cashu
+// mints, so a ProviderService can be driven end to end in tests without
+// Proxmox, LXD or network access. Enabled by the `mock` cargo feature.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::RngCore;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::cashu::PaymentProcessor;
+use crate::compute::{ComputeBackend, ContainerConfig, NodeStatus, WorkloadKind};
+use crate::nostr::{ErrorResponseContent, HeartbeatContent, NostrEvent, ProviderOfferContent};
+use crate::transport::{ProviderTransport, RequestHandler};
+
+/// Backend operations that can be made to fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockOperation {
+    FindAvailableId,
+    Create,
+    Start,
+    Stop,
+    Delete,
+    List,
+    NodeStatus,
+    ContainerIp,
+    ConfigureAccess, // Fails create_container after the workload exists, like a broken port forward
+}
+
+#[derive(Debug, Clone)]
+struct MockContainer {
+    config: ContainerConfig,
+    running: bool,
+}
+
+/// ComputeBackend that keeps its workloads in memory
+pub struct MockBackend {
+    containers: Mutex<HashMap<u32, MockContainer>>,
+    failures: Mutex<HashSet<MockOperation>>,
+    latency: Duration,
+    node_status: NodeStatus,
+    external_host: Option<String>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self {
+            containers: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashSet::new()),
+            latency: Duration::ZERO,
+            node_status: NodeStatus {
+                cpu_usage: 0.25,
+                cpu_total: 8000,
+                memory_used: 4 * 1024 * 1024 * 1024,
+                memory_total: 16 * 1024 * 1024 * 1024,
+                disk_used: 20 * 1024 * 1024 * 1024,
+                disk_total: 100 * 1024 * 1024 * 1024,
+            },
+            external_host: None,
+        }
+    }
+
+    /// Delay every operation by `latency`
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Report `status` from get_node_status
+    pub fn with_node_status(mut self, status: NodeStatus) -> Self {
+        self.node_status = status;
+        self
+    }
+
+    /// Report `host` as every workload's external address, like a load balancer
+    pub fn with_external_host(mut self, host: &str) -> Self {
+        self.external_host = Some(host.to_string());
+        self
+    }
+
+    /// Make `operation` return an error until `recover` is called
+    pub fn fail(&self, operation: MockOperation) {
+        self.failures.lock().unwrap().insert(operation);
+    }
+
+    pub fn recover(&self, operation: MockOperation) {
+        self.failures.lock().unwrap().remove(&operation);
+    }
+
+    /// IDs of the workloads that currently exist, sorted
+    pub fn container_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.containers.lock().unwrap().keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Config a workload was created with
+    pub fn container(&self, id: u32) -> Option<ContainerConfig> {
+        self.containers.lock().unwrap().get(&id).map(|c| c.config.clone())
+    }
+
+    pub fn is_running(&self, id: u32) -> bool {
+        self.containers.lock().unwrap().get(&id).is_some_and(|c| c.running)
+    }
+
+    /// Add a workload directly, as if it existed before the provider started
+    pub fn insert(&self, config: ContainerConfig) {
+        self.containers.lock().unwrap().insert(config.id, MockContainer { config, running: true });
+    }
+
+    async fn begin(&self, operation: MockOperation) -> Result<()> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        if self.failures.lock().unwrap().contains(&operation) {
+            return Err(anyhow::anyhow!("Mock backend failure: {:?}", operation));
+        }
+        Ok(())
+    }
+
+    fn set_running(&self, id: u32, running: bool) -> Result<()> {
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers.get_mut(&id)
+            .ok_or_else(|| anyhow::anyhow!("Container {} not found", id))?;
+        container.running = running;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ComputeBackend for MockBackend {
+    fn supported_kinds(&self) -> Vec<WorkloadKind> {
+        vec![WorkloadKind::Container, WorkloadKind::Vm]
+    }
+
+    async fn find_available_id(&self, range_start: u32, range_end: u32) -> Result<u32> {
+        self.begin(MockOperation::FindAvailableId).await?;
+        let containers = self.containers.lock().unwrap();
+        (range_start..=range_end)
+            .find(|id| !containers.contains_key(id))
+            .ok_or_else(|| anyhow::anyhow!("No available IDs in range {}-{}", range_start, range_end))
+    }
+
+    async fn create_container(&self, config: &ContainerConfig) -> Result<String> {
+        self.begin(MockOperation::Create).await?;
+        let mut containers = self.containers.lock().unwrap();
+        if containers.contains_key(&config.id) {
+            return Err(anyhow::anyhow!("Container {} already exists", config.id));
+        }
+        containers.insert(config.id, MockContainer { config: config.clone(), running: true });
+        drop(containers);
+
+        if self.failures.lock().unwrap().contains(&MockOperation::ConfigureAccess) {
+            return Err(anyhow::anyhow!("Mock backend failure: {:?}", MockOperation::ConfigureAccess));
+        }
+        Ok(config.name.clone())
+    }
+
+    async fn start_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.begin(MockOperation::Start).await?;
+        self.set_running(id, true)
+    }
+
+    async fn stop_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.begin(MockOperation::Stop).await?;
+        self.set_running(id, false)
+    }
+
+    async fn delete_container(&self, id: u32, _kind: WorkloadKind) -> Result<()> {
+        self.begin(MockOperation::Delete).await?;
+        self.containers.lock().unwrap().remove(&id)
+            .ok_or_else(|| anyhow::anyhow!("Container {} not found", id))?;
+        Ok(())
+    }
+
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>> {
+        self.begin(MockOperation::List).await?;
+        Ok(self.containers.lock().unwrap()
+            .values()
+            .map(|c| (c.config.id, c.config.kind))
+            .collect())
+    }
+
+    async fn get_node_status(&self) -> Result<NodeStatus> {
+        self.begin(MockOperation::NodeStatus).await?;
+        Ok(self.node_status.clone())
+    }
+
+    async fn get_container_ip(&self, id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        self.begin(MockOperation::ContainerIp).await?;
+        Ok(self.containers.lock().unwrap()
+            .get(&id)
+            .filter(|c| c.running)
+            .map(|_| format!("10.0.{}.{}", id / 250, id % 250 + 2)))
+    }
+
+    async fn get_external_host(&self, _id: u32, _kind: WorkloadKind) -> Result<Option<String>> {
+        self.begin(MockOperation::ContainerIp).await?;
+        Ok(self.external_host.clone())
+    }
+}
+
+/// A reply the provider sent through the relay
+#[derive(Debug, Clone)]
+pub struct MockMessage {
+    pub receiver_pubkey: String,
+    pub content: String,
+    pub message_type: String,
+}
+
+/// ProviderTransport that delivers requests and collects replies in memory
+pub struct MockRelay {
+    public_key: String,
+    requests_tx: mpsc::UnboundedSender<NostrEvent>,
+    requests_rx: tokio::sync::Mutex<Option<mpsc::UnboundedReceiver<NostrEvent>>>,
+    replies: Mutex<Vec<MockMessage>>,
+    offers: Mutex<Vec<ProviderOfferContent>>,
+    heartbeats: Mutex<Vec<HeartbeatContent>>,
+}
+
+impl Default for MockRelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockRelay {
+    pub fn new() -> Self {
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        Self {
+            public_key: random_hex_key(),
+            requests_tx,
+            requests_rx: tokio::sync::Mutex::new(Some(requests_rx)),
+            replies: Mutex::new(Vec::new()),
+            offers: Mutex::new(Vec::new()),
+            heartbeats: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Deliver `request` to the provider as a NIP-17 message from `from_pubkey`
+    pub fn send_request(&self, from_pubkey: &str, request: &impl Serialize) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let event = NostrEvent {
+            id: random_hex_key(),
+            pubkey: from_pubkey.to_string(),
+            created_at: now,
+            kind: 14,
+            tags: vec![vec!["p".to_string(), self.public_key.clone()]],
+            content: serde_json::to_string(request)?,
+            sig: String::new(),
+            message_type: "nip17".to_string(),
+        };
+
+        self.requests_tx.send(event)
+            .map_err(|_| anyhow::anyhow!("Provider is no longer listening"))
+    }
+
+    /// Wait up to `timeout` for the next reply to `receiver_pubkey`
+    pub async fn next_reply(&self, receiver_pubkey: &str, timeout: Duration) -> Option<MockMessage> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            {
+                let mut replies = self.replies.lock().unwrap();
+                if let Some(pos) = replies.iter().position(|m| m.receiver_pubkey == receiver_pubkey) {
+                    return Some(replies.remove(pos));
+                }
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    pub fn offers(&self) -> Vec<ProviderOfferContent> {
+        self.offers.lock().unwrap().clone()
+    }
+
+    pub fn heartbeats(&self) -> Vec<HeartbeatContent> {
+        self.heartbeats.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl ProviderTransport for MockRelay {
+    fn public_key_hex(&self) -> String {
+        self.public_key.clone()
+    }
+
+    async fn publish_provider_offer(&self, offer: ProviderOfferContent) -> Result<()> {
+        self.offers.lock().unwrap().push(offer);
+        Ok(())
+    }
+
+    async fn publish_heartbeat(&self, heartbeat: HeartbeatContent) -> Result<()> {
+        self.heartbeats.lock().unwrap().push(heartbeat);
+        Ok(())
+    }
+
+    async fn calculate_uptime(&self, _provider_npub: &str, _days: u32) -> Result<f32> {
+        Ok(100.0)
+    }
+
+    async fn subscribe_to_requests(&self, handler: RequestHandler) -> Result<()> {
+        let mut requests = self.requests_rx.lock().await.take()
+            .ok_or_else(|| anyhow::anyhow!("MockRelay only supports one subscriber"))?;
+
+        while let Some(event) = requests.recv().await {
+            if let Err(e) = handler(event).await {
+                warn!("Request handler failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_private_message(&self, receiver_pubkey: &str, content: String, message_type: &str) -> Result<()> {
+        self.replies.lock().unwrap().push(MockMessage {
+            receiver_pubkey: receiver_pubkey.to_string(),
+            content,
+            message_type: message_type.to_string(),
+        });
+        Ok(())
+    }
+}
+
+const MOCK_TOKEN_PREFIX: &str = "mockcashu";
+
+static TOKEN_NONCE: AtomicU64 = AtomicU64::new(0);
+
+/// PaymentProcessor for tokens made by `MockPayments::token`, which carry their
+/// mint and value in plain text. Each token can only be redeemed once.
+#[derive(Default)]
+pub struct MockPayments {
+    fee_msats: u64,
+    spent: Mutex<HashSet<String>>,
+    refunds: Mutex<Vec<(String, u64)>>,
+}
+
+impl MockPayments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take `fee_msats` off every redeemed token, like a mint's swap fee
+    pub fn with_fee(mut self, fee_msats: u64) -> Self {
+        self.fee_msats = fee_msats;
+        self
+    }
+
+    /// A new token worth `amount_msats` from `mint_url`
+    pub fn token(mint_url: &str, amount_msats: u64) -> String {
+        let nonce = TOKEN_NONCE.fetch_add(1, Ordering::Relaxed);
+        format!("{}:{}:{}:{}", MOCK_TOKEN_PREFIX, nonce, amount_msats, mint_url)
+    }
+
+    pub fn is_spent(&self, token_str: &str) -> bool {
+        self.spent.lock().unwrap().contains(token_str)
+    }
+
+    /// Mint and amount of every refund handed out so far
+    pub fn refunds(&self) -> Vec<(String, u64)> {
+        self.refunds.lock().unwrap().clone()
+    }
+
+    fn refund(&self, mint_url: &str, amount_msats: u64) -> Option<String> {
+        if amount_msats == 0 {
+            return None;
+        }
+        self.refunds.lock().unwrap().push((mint_url.to_string(), amount_msats));
+        Some(Self::token(mint_url, amount_msats))
+    }
+}
+
+/// Value and mint of a mock token
+fn parse_mock_token(token_str: &str) -> Result<(u64, String)> {
+    let mut parts = token_str.splitn(4, ':');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(MOCK_TOKEN_PREFIX), Some(_nonce), Some(amount), Some(mint_url)) => {
+            Ok((amount.parse()?, mint_url.to_string()))
+        }
+        _ => Err(anyhow::anyhow!("Not a mock Cashu token")),
+    }
+}
+
+#[async_trait]
+impl PaymentProcessor for MockPayments {
+    async fn extract_token_value(&self, token_str: &str) -> Result<u64> {
+        Ok(parse_mock_token(token_str)?.0)
+    }
+
+    fn check_token_mint(&self, token_str: &str, accepted_mints: &[String]) -> std::result::Result<(), ErrorResponseContent> {
+        let mint_url = parse_mock_token(token_str).map_err(|e| ErrorResponseContent {
+            error_type: "invalid_token".to_string(),
+            message: format!("Failed to decode Cashu token: {}", e),
+            details: None,
+            accepted_mints: vec![],
+            refund_token: None,
+        })?.1;
+
+        if accepted_mints.iter().any(|m| m.trim_end_matches('/') == mint_url.trim_end_matches('/')) {
+            return Ok(());
+        }
+
+        Err(ErrorResponseContent {
+            error_type: "mint_not_accepted".to_string(),
+            message: format!("Tokens from mint {} are not accepted", mint_url),
+            details: Some(format!("Accepted mints: {}", accepted_mints.join(", "))),
+            accepted_mints: accepted_mints.to_vec(),
+            refund_token: None,
+        })
+    }
+
+    async fn receive_token(&self, token_str: &str) -> Result<u64> {
+        let (amount_msats, _) = parse_mock_token(token_str)?;
+        if !self.spent.lock().unwrap().insert(token_str.to_string()) {
+            return Err(cdk::Error::TokenAlreadySpent.into());
+        }
+        Ok(amount_msats.saturating_sub(self.fee_msats))
+    }
+
+    fn token_source(&self, token_str: &str) -> Result<(String, String)> {
+        Ok((parse_mock_token(token_str)?.1, "sat".to_string()))
+    }
+
+    async fn refund_or_log(&self, paid_token: &str, amount_msats: u64) -> Option<String> {
+        let mint_url = parse_mock_token(paid_token).ok()?.1;
+        self.refund(&mint_url, amount_msats)
+    }
+
+    async fn refund_at_mint_or_log(&self, mint_url: &str, _unit: &str, amount_msats: u64) -> Option<String> {
+        if mint_url.is_empty() {
+            return None;
+        }
+        self.refund(mint_url, amount_msats)
+    }
+}
+
+/// Random 32-byte hex string, shaped like a Nostr public key or event ID
+pub fn random_hex_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,103 @@
+// Workload Network Policy
+//
+// What a spec's workloads may reach. By default they get the internet and
+// nothing else: private, carrier-grade NAT and link-local ranges are blocked,
+// which covers the provider's LAN, cloud metadata endpoints and other tenants
+// on the same bridge or cluster. Each backend translates the policy into its
+// own mechanism (LXD network ACLs, the Proxmox guest firewall, a Kubernetes
+// NetworkPolicy).
+
+use serde::{Deserialize, Serialize};
+
+/// IPv4 ranges blocked unless a spec allows unrestricted egress
+pub const PRIVATE_IPV4_RANGES: [&str; 5] = [
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "100.64.0.0/10",  // Carrier-grade NAT
+    "169.254.0.0/16", // Link-local, including cloud metadata services
+];
+
+/// IPv6 ranges blocked unless a spec allows unrestricted egress
+pub const PRIVATE_IPV6_RANGES: [&str; 2] = [
+    "fc00::/7",  // Unique local
+    "fe80::/10", // Link-local
+];
+
+pub const DNS_PORT: u16 = 53;
+pub const SMTP_PORT: u16 = 25;
+
+/// Network rules for a spec's workloads
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkPolicy {
+    #[serde(default)]
+    pub egress: Egress,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_mbit: Option<u32>, // Cap on traffic in each direction, in Mbit/s
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub block_smtp: bool, // Reject outbound connections to port 25
+}
+
+/// Where a workload may open connections to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Egress {
+    /// Public internet only; private and link-local ranges are blocked
+    #[default]
+    Internet,
+    /// Anywhere, including the provider's LAN; other workloads still only accept SSH
+    Unrestricted,
+    /// Nothing; images must already ship sshd, as nothing can be installed
+    None,
+}
+
+impl NetworkPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether any outbound connections are allowed
+    pub fn allows_egress(&self) -> bool {
+        self.egress != Egress::None
+    }
+
+    /// Destination ranges outbound connections are rejected to
+    pub fn blocked_ranges(&self) -> Vec<&'static str> {
+        match self.egress {
+            Egress::Internet => PRIVATE_IPV4_RANGES.iter().chain(PRIVATE_IPV6_RANGES.iter()).copied().collect(),
+            Egress::Unrestricted | Egress::None => vec![],
+        }
+    }
+
+    /// TCP ports outbound connections are rejected to, wherever they go
+    pub fn blocked_tcp_ports(&self) -> Vec<u16> {
+        if self.block_smtp { vec![SMTP_PORT] } else { vec![] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_blocks_private_ranges() {
+        let policy: NetworkPolicy = serde_json::from_str("{}").unwrap();
+        assert!(policy.is_default());
+        assert!(policy.allows_egress());
+        assert!(policy.blocked_ranges().contains(&"192.168.0.0/16"));
+        assert!(policy.blocked_ranges().contains(&"fc00::/7"));
+        assert!(policy.blocked_tcp_ports().is_empty());
+    }
+
+    #[test]
+    fn test_spec_overrides() {
+        let policy: NetworkPolicy =
+            serde_json::from_str(r#"{"egress": "unrestricted", "bandwidth_mbit": 100, "block_smtp": true}"#).unwrap();
+        assert!(policy.blocked_ranges().is_empty());
+        assert_eq!(policy.blocked_tcp_ports(), vec![SMTP_PORT]);
+        assert_eq!(policy.bandwidth_mbit, Some(100));
+
+        let closed: NetworkPolicy = serde_json::from_str(r#"{"egress": "none"}"#).unwrap();
+        assert!(!closed.allows_egress());
+    }
+}
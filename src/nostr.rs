@@ -4,15 +4,25 @@ use nostr_sdk::{Client, Keys, Filter, Kind, RelayPoolNotification, Url, EventBui
 use nostr_sdk::nips::nip59::UnwrappedGift;
 use nostr_sdk::nips::nip04;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::future::Future;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
 
+use crate::network_policy::NetworkPolicy;
+use crate::uptime::UptimeStats;
+
 // Custom event kinds for Paygress provider discovery
 pub const KIND_PROVIDER_OFFER: u16 = 38383;
-pub const KIND_PROVIDER_HEARTBEAT: u16 = 38384;
+// Heartbeats use a regular (non-replaceable) kind: relays must keep every one of them,
+// since uptime is scored from the whole history, not just the latest
+pub const KIND_PROVIDER_HEARTBEAT: u16 = 8384;
+
+// Relays cap how many events a query returns, so heartbeat history is fetched in pages
+const HEARTBEAT_PAGE_SIZE: usize = 1000;
+const MAX_HEARTBEAT_PAGES: usize = 200;
 #[derive(Clone, Debug)]
 pub struct RelayConfig {
     pub relays: Vec<String>,
@@ -156,7 +166,7 @@ impl NostrRelaySubscriber {
                         // Decrypt the NIP-04 message using NIP-04 module
                         match self.keys.secret_key() {
                             Ok(secret_key) => {
-                                match nip04::decrypt(&secret_key, &event.pubkey, &event.content) {
+                                match nip04::decrypt(secret_key, &event.pubkey, &event.content) {
                                     Ok(decrypted_content) => {
                                         debug!("Decrypted NIP-04 message. Length: {}", decrypted_content.len());
                                         
@@ -246,7 +256,7 @@ impl NostrRelaySubscriber {
             "nip04" => {
                 match self.keys.secret_key() {
                     Ok(secret_key) => {
-                        let encrypted_content = nip04::encrypt(&secret_key, &receiver_pubkey_parsed, &content)?;
+                        let encrypted_content = nip04::encrypt(secret_key, &receiver_pubkey_parsed, &content)?;
                         let receiver_tag = Tag::public_key(receiver_pubkey_parsed);
                         let alt_tag = Tag::parse(&["alt", "Private Message"])?;
                         
@@ -262,7 +272,7 @@ impl NostrRelaySubscriber {
                     }
                 }
             }
-            "nip17" | _ => {
+            _ => {
                 // Default to NIP-17 if not specified or nip17
                 let event_id = self.client.send_private_msg(receiver_pubkey_parsed, content, None).await?;
                 info!("Sent NIP-17 message to {}: {:?}", receiver_pubkey, event_id);
@@ -271,69 +281,6 @@ impl NostrRelaySubscriber {
         }
     }
 
-    // Send access details via private encrypted message
-    pub async fn send_access_details_private_message(
-        &self, 
-        request_pubkey: &str,
-        details: AccessDetailsContent,
-        message_type: &str
-    ) -> Result<String> {
-        let details_json = serde_json::to_string(&details)?;
-        self.send_encrypted_private_message(request_pubkey, details_json, message_type).await
-    }
-
-    // Send status response via private encrypted message
-    pub async fn send_status_response(
-        &self, 
-        request_pubkey: &str,
-        response: StatusResponseContent,
-        message_type: &str
-    ) -> Result<String> {
-        let response_json = serde_json::to_string(&response)?;
-        self.send_encrypted_private_message(request_pubkey, response_json, message_type).await
-    }
-
-    // Convenience helper to send error response with individual fields
-    pub async fn send_error_response(
-        &self,
-        request_pubkey: &str,
-        error_type: &str,
-        message: &str,
-        details: Option<&str>,
-        message_type: &str,
-    ) -> Result<String> {
-        let error = ErrorResponseContent {
-            error_type: error_type.to_string(),
-            message: message.to_string(),
-            details: details.map(|s| s.to_string()),
-        };
-        self.send_error_response_private_message(request_pubkey, error, message_type).await
-    }
-
-    // Send error response via private encrypted message
-    pub async fn send_error_response_private_message(
-        &self, 
-        request_pubkey: &str,
-        error: ErrorResponseContent,
-        message_type: &str
-    ) -> Result<String> {
-        let error_json = serde_json::to_string(&error)?;
-        self.send_encrypted_private_message(request_pubkey, error_json, message_type).await
-    }
-
-    // Send top-up response via private encrypted message
-    pub async fn send_topup_response_private_message(
-        &self, 
-        request_pubkey: &str,
-        response: TopUpResponseContent,
-        message_type: &str
-    ) -> Result<String> {
-        let response_json = serde_json::to_string(&response)?;
-        self.send_encrypted_private_message(request_pubkey, response_json, message_type).await
-    }
-
-
-
     // Get the underlying Nostr client
     pub fn client(&self) -> &Client {
         &self.client
@@ -344,21 +291,6 @@ impl NostrRelaySubscriber {
         self.keys.public_key().to_hex()
     }
 
-    fn convert_event(&self, event: &nostr_sdk::Event) -> NostrEvent {
-        NostrEvent {
-            id: event.id.to_hex(),
-            pubkey: event.pubkey.to_hex(),
-            created_at: event.created_at.as_u64(),
-            kind: event.kind.as_u32(),
-            tags: event.tags.iter().map(|tag| {
-                tag.as_vec().iter().map(|s| s.to_string()).collect()
-            }).collect(),
-            content: event.content.clone(),
-            sig: event.sig.to_string(),
-            message_type: "unknown".to_string(), // Default value since this function doesn't know the context
-        }
-    }
-
     /// Wait for a private decrypted message from a specific sender
     pub async fn wait_for_decrypted_message(&self, sender_pubkey: &str, timeout_secs: u64) -> Result<NostrEvent> {
         let sender_pk = nostr_sdk::PublicKey::parse(sender_pubkey)?;
@@ -382,7 +314,6 @@ impl NostrRelaySubscriber {
             notification_res = client.handle_notifications(|notification| {
                 let tx = tx.clone();
                 let receiver_keys = receiver_keys.clone();
-                let sender_pk = sender_pk.clone();
                 let client = client.clone();
                 
                 async move {
@@ -407,21 +338,19 @@ impl NostrRelaySubscriber {
                                     }
                                 }
                             }
-                            Kind::EncryptedDirectMessage => {
-                                if event.pubkey == sender_pk {
-                                    if let Ok(secret_key) = receiver_keys.secret_key() {
-                                        if let Ok(content) = nip04::decrypt(&secret_key, &event.pubkey, &event.content) {
-                                            event_to_send = Some(NostrEvent {
-                                                id: event.id.to_hex(),
-                                                pubkey: event.pubkey.to_hex(),
-                                                created_at: event.created_at.as_u64(),
-                                                kind: event.kind.as_u32(),
-                                                tags: event.tags.iter().map(|tag| tag.as_vec().iter().map(|s| s.to_string()).collect()).collect(),
-                                                content,
-                                                sig: event.sig.to_string(),
-                                                message_type: "nip04".to_string(),
-                                            });
-                                        }
+                            Kind::EncryptedDirectMessage if event.pubkey == sender_pk => {
+                                if let Ok(secret_key) = receiver_keys.secret_key() {
+                                    if let Ok(content) = nip04::decrypt(secret_key, &event.pubkey, &event.content) {
+                                        event_to_send = Some(NostrEvent {
+                                            id: event.id.to_hex(),
+                                            pubkey: event.pubkey.to_hex(),
+                                            created_at: event.created_at.as_u64(),
+                                            kind: event.kind.as_u32(),
+                                            tags: event.tags.iter().map(|tag| tag.as_vec().iter().map(|s| s.to_string()).collect()).collect(),
+                                            content,
+                                            sig: event.sig.to_string(),
+                                            message_type: "nip04".to_string(),
+                                        });
                                     }
                                 }
                             }
@@ -475,7 +404,40 @@ pub struct PodSpec {
     pub description: String, // Description of the spec
     pub cpu_millicores: u64, // CPU in millicores (1000 millicores = 1 CPU core)
     pub memory_mb: u64, // Memory in MB
+    #[serde(default = "default_storage_gb")]
+    pub storage_gb: u64, // Root disk size in GB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_iops: Option<u64>, // Read and write IOPS cap, on backends that support one
     pub rate_msats_per_sec: u64, // Payment rate for this spec
+    #[serde(default)]
+    pub kind: WorkloadKind, // What this spec provisions (container unless stated)
+    #[serde(default, skip_serializing_if = "NetworkPolicy::is_default")]
+    pub network: NetworkPolicy, // Egress, bandwidth and SMTP rules for the workloads
+}
+
+// What specs from before disk sizing were provisioned with
+fn default_storage_gb() -> u64 {
+    10
+}
+
+/// What a workload runs in: a container sharing the host kernel, or a full VM
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WorkloadKind {
+    #[default]
+    #[serde(rename = "lxc")]
+    Container,
+    #[serde(rename = "vm")]
+    Vm,
+}
+
+impl WorkloadKind {
+    /// Capability string advertised in offers ("lxc" or "vm")
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkloadKind::Container => "lxc",
+            WorkloadKind::Vm => "vm",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -495,6 +457,8 @@ pub struct AccessDetailsContent {
     pub pod_spec_name: String, // Human-readable spec name
     pub pod_spec_description: String, // Spec description
     pub instructions: Vec<String>, // SSH connection instructions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>, // Change for any payment beyond the purchased duration
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -502,6 +466,10 @@ pub struct ErrorResponseContent {
     pub error_type: String, // Type of error (e.g., "insufficient_payment", "invalid_spec", "image_not_found")
     pub message: String, // Human-readable error message
     pub details: Option<String>, // Additional error details
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted_mints: Vec<String>, // Set on "mint_not_accepted" errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>, // Cashu token returning payment that was not used
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -511,6 +479,8 @@ pub struct TopUpResponseContent {
     pub extended_duration_seconds: u64,
     pub new_expires_at: String,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>, // Change for any payment beyond whole seconds of extension
 }
 
 // NEW: Encrypted request structure
@@ -521,6 +491,47 @@ pub struct EncryptedSpawnPodRequest {
     pub pod_image: String, // Required: Container image to use for the pod
     pub ssh_username: String,
     pub ssh_password: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_public_keys: Vec<String>, // OpenSSH public keys to install in authorized_keys
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disable_password_auth: bool, // Key-only login; requires ssh_public_keys
+}
+
+/// Key types accepted in `ssh_public_keys`
+const SSH_KEY_TYPES: [&str; 7] = [
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+/// Check that each key is a single-line OpenSSH public key ("<type> <base64> [comment]").
+/// Keys end up in provisioning shell scripts, so quotes and shell metacharacters are rejected.
+pub fn validate_ssh_public_keys(keys: &[String], disable_password_auth: bool) -> std::result::Result<(), String> {
+    if disable_password_auth && keys.is_empty() {
+        return Err("disable_password_auth requires at least one SSH public key".to_string());
+    }
+
+    for key in keys {
+        let mut parts = key.split_whitespace();
+        let key_type = parts.next().unwrap_or("");
+        let data = parts.next().unwrap_or("");
+
+        let valid = SSH_KEY_TYPES.contains(&key_type)
+            && !data.is_empty()
+            && data.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
+            && !key.contains(['\'', '"', '`', '$', '\\', '\n', '\r']);
+
+        if !valid {
+            let shown: String = key.chars().take(40).collect();
+            return Err(format!("Invalid SSH public key: {}...", shown));
+        }
+    }
+
+    Ok(())
 }
 
 // NEW: Encrypted top-up request structure
@@ -552,6 +563,7 @@ pub async fn send_provisioning_request_private_message(
 pub enum PrivateRequest {
     Spawn(EncryptedSpawnPodRequest),
     TopUp(EncryptedTopUpPodRequest),
+    Terminate(TerminateRequestContent), // Must precede Status, which only needs pod_id
     Status(StatusRequestContent),
 }
 
@@ -575,7 +587,7 @@ pub fn parse_private_message_content(content: &str) -> Result<PrivateRequest> {
 /// Capacity information for a provider
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CapacityInfo {
-    pub cpu_available: u64,      // Available CPU in millicores
+    pub cpu_available: u64,      // Sellable CPU in millicores (cores × 1000 minus committed)
     pub memory_mb_available: u64, // Available memory in MB
     pub storage_gb_available: u64, // Available storage in GB
 }
@@ -590,19 +602,25 @@ pub struct ProviderOfferContent {
     pub capabilities: Vec<String>,  // ["lxc", "vm"]
     pub specs: Vec<PodSpec>,
     pub whitelisted_mints: Vec<String>,
+    #[serde(default)]
+    pub images: Vec<String>, // Image names accepted in spawn requests (e.g. "ubuntu-22.04")
     pub uptime_percent: f32,
     pub total_jobs_completed: u64,
     pub api_endpoint: Option<String>,
 }
 
-/// Heartbeat content published to Nostr (Kind 38384)
-/// Published every 60 seconds to prove liveness
+/// Heartbeat content published to Nostr (Kind 8384)
+/// Published every `interval_secs` seconds to prove liveness
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeartbeatContent {
     pub provider_npub: String,
     pub timestamp: u64,
     pub active_workloads: u32,
     pub available_capacity: CapacityInfo,
+    #[serde(default)]
+    pub interval_secs: u64, // Declared heartbeat interval (0 from older providers = 60s)
+    #[serde(default)]
+    pub available_slots: HashMap<String, u32>, // Workloads of each spec (by PodSpec.id) that still fit
 }
 
 /// Provider info as seen by discovery clients
@@ -614,10 +632,16 @@ pub struct ProviderInfo {
     pub capabilities: Vec<String>,
     pub specs: Vec<PodSpec>,
     pub whitelisted_mints: Vec<String>,
-    pub uptime_percent: f32,
+    #[serde(default)]
+    pub images: Vec<String>,
+    pub uptime_percent: f32, // Computed from heartbeats by the client (7 day window)
+    #[serde(default)]
+    pub uptime: UptimeStats,
     pub total_jobs_completed: u64,
     pub last_seen: u64,  // Timestamp of last heartbeat
     pub is_online: bool,
+    #[serde(default)]
+    pub available_slots: HashMap<String, u32>, // From the last heartbeat; specs without an entry are unknown
 }
 
 /// Filter for querying providers
@@ -627,6 +651,8 @@ pub struct ProviderFilter {
     pub min_uptime: Option<f32>,
     pub min_memory_mb: Option<u64>,
     pub min_cpu: Option<u64>,
+    pub min_storage_gb: Option<u64>,
+    pub min_available_slots: Option<u32>, // Some spec can still fit this many workloads
 }
 
 impl NostrRelaySubscriber {
@@ -658,7 +684,7 @@ impl NostrRelaySubscriber {
         }
     }
 
-    /// Publish a heartbeat event (Kind 38384)
+    /// Publish a heartbeat event (Kind 8384, regular so every heartbeat is kept)
     pub async fn publish_heartbeat(&self, heartbeat: HeartbeatContent) -> Result<String> {
         let content = serde_json::to_string(&heartbeat)?;
         
@@ -714,7 +740,7 @@ impl NostrRelaySubscriber {
             .author(provider_pubkey)
             .since(Timestamp::from(since_secs));
 
-        let events = self.client.get_events_of(vec![filter], Some(std::time::Duration::from_secs(5))).await?;
+        let events = self.get_events_paged(filter, std::time::Duration::from_secs(5)).await?;
         
         let mut heartbeats = Vec::new();
         for event in events {
@@ -729,6 +755,70 @@ impl NostrRelaySubscriber {
         Ok(heartbeats)
     }
 
+    /// Query heartbeats for multiple providers since a given time, keyed by the signing pubkey (hex)
+    pub async fn query_heartbeats_multi(&self, provider_npubs: Vec<String>, since_secs: u64) -> Result<std::collections::HashMap<String, Vec<HeartbeatContent>>> {
+        let mut heartbeats: std::collections::HashMap<String, Vec<HeartbeatContent>> = std::collections::HashMap::new();
+
+        let pubkeys: Vec<nostr_sdk::PublicKey> = provider_npubs.iter()
+            .filter_map(|npub| nostr_sdk::PublicKey::parse(npub).ok())
+            .collect();
+        if pubkeys.is_empty() {
+            return Ok(heartbeats);
+        }
+
+        let filter = Filter::new()
+            .kind(Kind::Custom(KIND_PROVIDER_HEARTBEAT))
+            .authors(pubkeys)
+            .since(Timestamp::from(since_secs));
+
+        let events = self.get_events_paged(filter, std::time::Duration::from_secs(10)).await?;
+
+        // Key by the event author rather than the claimed provider_npub, so one
+        // provider can't publish heartbeats on behalf of another
+        for event in events {
+            if let Ok(hb) = serde_json::from_str::<HeartbeatContent>(&event.content) {
+                heartbeats.entry(event.pubkey.to_hex()).or_default().push(hb);
+            }
+        }
+
+        Ok(heartbeats)
+    }
+
+    /// Fetch every event matching `filter`, paging backwards with `until` past relay result limits
+    async fn get_events_paged(&self, filter: Filter, timeout: std::time::Duration) -> Result<Vec<nostr_sdk::Event>> {
+        let mut events = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut until: Option<Timestamp> = None;
+
+        for _ in 0..MAX_HEARTBEAT_PAGES {
+            let mut page_filter = filter.clone().limit(HEARTBEAT_PAGE_SIZE);
+            if let Some(until) = until {
+                page_filter = page_filter.until(until);
+            }
+
+            let page = self.client.get_events_of(vec![page_filter], Some(timeout)).await?;
+            let full = page.len() >= HEARTBEAT_PAGE_SIZE;
+            let oldest = page.iter().map(|event| event.created_at).min();
+
+            // `until` is inclusive, so the next page repeats the oldest second; skip what we have
+            let mut new_events = 0;
+            for event in page {
+                if seen.insert(event.id) {
+                    events.push(event);
+                    new_events += 1;
+                }
+            }
+
+            match oldest {
+                Some(oldest) if full && new_events > 0 => until = Some(oldest),
+                _ => return Ok(events),
+            }
+        }
+
+        warn!("Stopped paging after {} events; older heartbeats are not counted", events.len());
+        Ok(events)
+    }
+
     /// Get the latest heartbeat for a provider (to check if online)
     pub async fn get_latest_heartbeat(&self, provider_npub: &str) -> Result<Option<HeartbeatContent>> {
         let provider_pubkey = nostr_sdk::PublicKey::parse(provider_npub)?;
@@ -818,11 +908,10 @@ impl NostrRelaySubscriber {
             return Ok(0.0);
         }
         
-        // Expected heartbeats (one per minute)
-        let expected = (days as f32) * 24.0 * 60.0;
-        let actual = heartbeats.len() as f32;
-        
-        Ok((actual / expected * 100.0).min(100.0))
+        // Coverage of the window at the interval the provider declares
+        let interval = crate::uptime::declared_interval(&heartbeats);
+        let timestamps: Vec<u64> = heartbeats.iter().map(|hb| hb.timestamp).collect();
+        Ok(crate::uptime::availability(&timestamps, interval, days as u64 * crate::uptime::DAY_SECS, now))
     }
 }
 
@@ -843,3 +932,22 @@ pub struct StatusResponseContent {
     pub ssh_port: u16,
     pub ssh_username: String,
 }
+
+/// Request to stop a workload early and get the unused time refunded.
+/// `terminate` must be true; it also keeps this distinct from a status request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminateRequestContent {
+    pub pod_id: String, // Container ID ("container-1000" or "1000")
+    pub terminate: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminateResponseContent {
+    pub pod_id: String,
+    pub terminated: bool,
+    pub unused_seconds: u64,
+    pub refund_msats: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>,
+    pub message: String,
+}
@@ -1,10 +1,12 @@
 // Unified Pod Provisioning Service
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use tracing::{info, error};
+use tracing::{info, error, warn};
 
-use crate::sidecar_service::{SidecarState, SidecarConfig, PodInfo, extract_token_value};
-use crate::nostr::{EncryptedSpawnPodRequest, EncryptedTopUpPodRequest, PodSpec};
+use crate::sidecar_service::{SidecarState, SidecarConfig, PodInfo, extract_token_value, pod_name_for_npub, payment_annotations};
+use crate::provisioning::validate_credentials;
+use crate::admission::AdmissionController;
+use crate::nostr::{validate_ssh_public_keys, EncryptedSpawnPodRequest, EncryptedTopUpPodRequest, ErrorResponseContent, PodSpec};
 
 /// Request for spawning a new pod
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +17,10 @@ pub struct SpawnPodTool {
     pub ssh_username: String,
     pub ssh_password: String,
     pub user_pubkey: Option<String>,
+    #[serde(default)]
+    pub ssh_public_keys: Vec<String>,
+    #[serde(default)]
+    pub disable_password_auth: bool,
 }
 
 /// Request for topping up an existing pod
@@ -25,6 +31,13 @@ pub struct TopUpPodTool {
 }
 
 
+/// Request for terminating a pod early (authenticated by its SSH password)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminatePodTool {
+    pub pod_npub: String,
+    pub ssh_password: String,
+}
+
 /// Request for getting available pod specifications/offers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetOffersTool {}
@@ -50,6 +63,10 @@ pub struct SpawnPodResponse {
     pub cpu_millicores: Option<u64>,
     pub memory_mb: Option<u64>,
     pub instructions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponseContent>,
 }
 
 /// Response for pod top-up
@@ -60,9 +77,25 @@ pub struct TopUpPodResponse {
     pub pod_npub: String,
     pub extended_duration_seconds: Option<u64>,
     pub new_expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponseContent>,
 }
 
 
+/// Response for early pod termination
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminatePodResponse {
+    pub success: bool,
+    pub message: String,
+    pub pod_npub: String,
+    pub unused_seconds: Option<u64>,
+    pub refund_msats: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_token: Option<String>,
+}
+
 /// Response for getting offers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetOffersResponse {
@@ -85,19 +118,32 @@ pub struct GetPodStatusResponse {
     pub cpu_millicores: Option<u64>,
     pub memory_mb: Option<u64>,
     pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_host: Option<String>, // External address: ssh_host, or the pod's load balancer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_port: Option<u16>,
 }
 
 /// Unified service handler for pod provisioning
 pub struct PodProvisioningService {
     state: SidecarState,
+    admission: AdmissionController,
 }
 
 impl PodProvisioningService {
     pub async fn new(config: SidecarConfig) -> Result<Self> {
+        let admission = AdmissionController::new(config.overcommit.clone());
         let state = SidecarState::new(config).await
             .map_err(|e| anyhow::anyhow!("Failed to initialize sidecar state: {}", e))?;
+
+        // Pick up pods created before this process started, then follow changes
+        match state.reconcile_from_cluster().await {
+            Ok(count) => info!("Recovered {} active pods from Kubernetes", count),
+            Err(e) => warn!("Failed to recover pods from Kubernetes: {}", e),
+        }
+        tokio::spawn(state.clone().watch_pods());
         
-        Ok(Self { state })
+        Ok(Self { state, admission })
     }
 
     /// Get the service configuration
@@ -105,6 +151,12 @@ impl PodProvisioningService {
         &self.state.config
     }
 
+    /// Host clients SSH to for a pod: its load balancer address, or the configured ssh_host
+    async fn ssh_host_for(&self, pod_name: &str) -> String {
+        self.state.k8s_client.external_host(&self.state.config.pod_namespace, pod_name).await
+            .unwrap_or_else(|| self.state.config.ssh_host.clone())
+    }
+
     /// Handle spawn pod request
     pub async fn spawn_pod(&self, request: SpawnPodTool) -> Result<SpawnPodResponse> {
         info!("Pod spawn request received for image: {}", request.pod_image);
@@ -116,6 +168,8 @@ impl PodProvisioningService {
             pod_image: request.pod_image,
             ssh_username: request.ssh_username,
             ssh_password: request.ssh_password,
+            ssh_public_keys: request.ssh_public_keys,
+            disable_password_auth: request.disable_password_auth,
         };
 
         // Use the existing logic from main.rs handle_spawn_pod_request
@@ -136,6 +190,8 @@ impl PodProvisioningService {
                     cpu_millicores: None,
                     memory_mb: None,
                     instructions: vec![],
+                    refund_token: None,
+                    error: None,
                 })
             }
         }
@@ -162,12 +218,83 @@ impl PodProvisioningService {
                     pod_npub: request.pod_npub,
                     extended_duration_seconds: None,
                     new_expires_at: None,
+                    refund_token: None,
+                    error: None,
                 })
             }
         }
     }
 
 
+    /// Handle terminate pod request: delete the pod and refund its unused seconds
+    pub async fn terminate_pod(&self, request: TerminatePodTool) -> Result<TerminatePodResponse> {
+        use chrono::Utc;
+
+        info!("Pod terminate request received for NPUB: {}", request.pod_npub);
+
+        // Remove from tracking up front so a concurrent terminate can't refund twice
+        let pod_info = {
+            let mut active_pods = self.state.active_pods.write().await;
+            match active_pods.get(&request.pod_npub) {
+                Some(info) if info.ssh_password == request.ssh_password => active_pods.remove(&request.pod_npub),
+                _ => None,
+            }
+        };
+
+        let pod_info = match pod_info {
+            Some(info) => info,
+            None => {
+                return Ok(TerminatePodResponse {
+                    success: false,
+                    message: format!("Pod '{}' not found or credentials do not match", request.pod_npub),
+                    pod_npub: request.pod_npub,
+                    unused_seconds: None,
+                    refund_msats: None,
+                    refund_token: None,
+                });
+            }
+        };
+
+        let pod_name = pod_name_for_npub(&pod_info.pod_npub);
+        if let Err(e) = self.state.k8s_client.delete_pod(&pod_info.namespace, &pod_name).await {
+            error!("Failed to delete pod {}: {}", pod_name, e);
+            // Keep tracking it; activeDeadlineSeconds still ends it at expiry
+            self.state.active_pods.write().await.insert(pod_info.pod_npub.clone(), pod_info);
+            return Ok(TerminatePodResponse {
+                success: false,
+                message: format!("Failed to delete pod: {}", e),
+                pod_npub: request.pod_npub,
+                unused_seconds: None,
+                refund_msats: None,
+                refund_token: None,
+            });
+        }
+
+        // Refund only out of payments this service redeemed and recorded for the pod
+        let unused_seconds = (pod_info.expires_at - Utc::now()).num_seconds().max(0) as u64;
+        let refund_msats = (unused_seconds * pod_info.rate_msats_per_sec).min(pod_info.received_msats);
+        let refund_token = crate::cashu::refund_at_mint_or_log(
+            &pod_info.payment_mint,
+            &pod_info.payment_unit,
+            refund_msats,
+        ).await;
+
+        info!("Pod {} terminated early ({}s unused, refund {} msats)", pod_name, unused_seconds, refund_msats);
+
+        Ok(TerminatePodResponse {
+            success: true,
+            message: if refund_token.is_some() {
+                format!("Pod terminated. Refunded {} unused seconds ({} msats).", unused_seconds, refund_msats)
+            } else {
+                "Pod terminated.".to_string()
+            },
+            pod_npub: request.pod_npub,
+            unused_seconds: Some(unused_seconds),
+            refund_msats: Some(refund_msats),
+            refund_token,
+        })
+    }
+
     /// Handle get offers request
     pub async fn get_offers(&self, _request: GetOffersTool) -> Result<GetOffersResponse> {
         info!("Get offers request received");
@@ -202,6 +329,7 @@ impl PodProvisioningService {
 
             // Get pod spec details
             let pod_spec = self.state.config.pod_specs.first(); // Default spec
+            let ssh_host = self.ssh_host_for(&pod_name_for_npub(&info.pod_npub)).await;
 
             Ok(GetPodStatusResponse {
                 success: true,
@@ -215,6 +343,8 @@ impl PodProvisioningService {
                 cpu_millicores: pod_spec.map(|s| s.cpu_millicores),
                 memory_mb: pod_spec.map(|s| s.memory_mb),
                 status: Some(if time_remaining.unwrap_or(0) > 0 { "running".to_string() } else { "expired".to_string() }),
+                ssh_host: Some(ssh_host),
+                ssh_port: Some(info.allocated_port),
             })
         } else {
             // Pod not found in our tracking, check if it exists in Kubernetes but expired
@@ -235,6 +365,8 @@ impl PodProvisioningService {
                         cpu_millicores: None,
                         memory_mb: None,
                         status: None,
+                        ssh_host: None,
+                        ssh_port: None,
                     });
                 }
             };
@@ -284,6 +416,8 @@ impl PodProvisioningService {
                     cpu_millicores: None,
                     memory_mb: None,
                     status: Some(status),
+                    ssh_host: None,
+                    ssh_port: None,
                 })
             } else {
                 // Pod not found anywhere
@@ -299,6 +433,8 @@ impl PodProvisioningService {
                     cpu_millicores: None,
                     memory_mb: None,
                     status: None,
+                    ssh_host: None,
+                    ssh_port: None,
                 })
             }
         }
@@ -309,6 +445,26 @@ impl PodProvisioningService {
         use chrono::Utc;
         use nostr_sdk::{Keys, ToBech32};
 
+        // Reject credentials that could break out of the provisioning scripts before touching anything
+        if let Err(e) = validate_credentials(&request.ssh_username, &request.ssh_password) {
+            return Ok(SpawnPodResponse {
+                success: false,
+                message: e.to_string(),
+                pod_npub: None,
+                ssh_host: None,
+                ssh_port: None,
+                ssh_username: None,
+                ssh_password: None,
+                expires_at: None,
+                pod_spec_name: None,
+                cpu_millicores: None,
+                memory_mb: None,
+                instructions: vec!["Choose a different SSH username or password".to_string()],
+                refund_token: None,
+                error: Some(e.to_error_response()),
+            });
+        }
+
         // Select pod specification
         let pod_spec = if let Some(spec_id) = &request.pod_spec_id {
             self.state.config.pod_specs.iter().find(|s| s.id == *spec_id)
@@ -332,10 +488,79 @@ impl PodProvisioningService {
                     cpu_millicores: None,
                     memory_mb: None,
                     instructions: vec!["Please check available specifications in the offer".to_string()],
+                    refund_token: None,
+                    error: None,
+                });
+            }
+        };
+
+        // Refuse tiers the cluster can't hold before the token is taken. The reservation
+        // covers the spawn until the pod exists and its requests count as committed.
+        let (allocatable, committed) = match self.state.k8s_client.resource_usage(&self.state.config.pod_namespace).await {
+            Ok(usage) => usage,
+            Err(e) => {
+                error!("Failed to check cluster capacity: {}", e);
+                return Ok(SpawnPodResponse {
+                    success: false,
+                    message: "Failed to check cluster capacity".to_string(),
+                    pod_npub: None,
+                    ssh_host: None,
+                    ssh_port: None,
+                    ssh_username: None,
+                    ssh_password: None,
+                    expires_at: None,
+                    pod_spec_name: Some(pod_spec.name.clone()),
+                    cpu_millicores: Some(pod_spec.cpu_millicores),
+                    memory_mb: Some(pod_spec.memory_mb),
+                    instructions: vec![format!("Capacity check error: {}", e)],
+                    refund_token: None,
+                    error: None,
+                });
+            }
+        };
+        let _reservation = match self.admission.admit(pod_spec, allocatable, committed) {
+            Ok(reservation) => reservation,
+            Err(e) => {
+                warn!("Rejecting spawn request: {}", e);
+                return Ok(SpawnPodResponse {
+                    success: false,
+                    message: e.to_string(),
+                    pod_npub: None,
+                    ssh_host: None,
+                    ssh_port: None,
+                    ssh_username: None,
+                    ssh_password: None,
+                    expires_at: None,
+                    pod_spec_name: Some(pod_spec.name.clone()),
+                    cpu_millicores: Some(pod_spec.cpu_millicores),
+                    memory_mb: Some(pod_spec.memory_mb),
+                    instructions: vec!["Try a smaller tier or try again later".to_string()],
+                    refund_token: None,
+                    error: Some(e.to_error_response()),
                 });
             }
         };
 
+        // Reject tokens from mints we don't accept
+        if let Err(rejection) = crate::cashu::check_token_mint(&request.cashu_token, &self.state.config.whitelisted_mints) {
+            return Ok(SpawnPodResponse {
+                success: false,
+                message: rejection.message.clone(),
+                pod_npub: None,
+                ssh_host: None,
+                ssh_port: None,
+                ssh_username: None,
+                ssh_password: None,
+                expires_at: None,
+                pod_spec_name: None,
+                cpu_millicores: None,
+                memory_mb: None,
+                instructions: rejection.details.clone().into_iter().collect(),
+                refund_token: None,
+                error: Some(rejection),
+            });
+        }
+
         // Decode token to get amount and duration
         let payment_amount_msats = match extract_token_value(&request.cashu_token).await {
             Ok(msats) => msats,
@@ -353,9 +578,31 @@ impl PodProvisioningService {
                     cpu_millicores: None,
                     memory_mb: None,
                     instructions: vec![format!("Token decode error: {}", e)],
+                    refund_token: None,
+                    error: None,
                 });
             }
         };
+
+        // Reject malformed SSH keys before the token is redeemed
+        if let Err(e) = validate_ssh_public_keys(&request.ssh_public_keys, request.disable_password_auth) {
+            return Ok(SpawnPodResponse {
+                success: false,
+                message: format!("Invalid SSH public key: {}", e),
+                pod_npub: None,
+                ssh_host: None,
+                ssh_port: None,
+                ssh_username: None,
+                ssh_password: None,
+                expires_at: None,
+                pod_spec_name: None,
+                cpu_millicores: None,
+                memory_mb: None,
+                instructions: vec!["Provide OpenSSH public keys, e.g. the contents of ~/.ssh/id_ed25519.pub".to_string()],
+                refund_token: None,
+                error: None,
+            });
+        }
         
         // Check if payment is sufficient for minimum duration with selected spec
         let minimum_payment = self.state.config.minimum_pod_duration_seconds * pod_spec.rate_msats_per_sec;
@@ -379,13 +626,23 @@ impl PodProvisioningService {
                         pod_spec.name,
                         pod_spec.rate_msats_per_sec)
                 ],
+                refund_token: None,
+                error: None,
             });
         }
 
         // Calculate duration based on payment and selected spec rate
         let duration_seconds = payment_amount_msats / pod_spec.rate_msats_per_sec;
 
-        // Token verification handled by ngx_l402 at nginx layer
+        // Token verification handled by ngx_l402 at nginx layer. Redeem it here as well when
+        // we can: refunds may only come out of what this service actually received.
+        let received_msats = match crate::cashu::receive_token(&request.cashu_token).await {
+            Ok(msats) => Some(msats),
+            Err(e) => {
+                warn!("Token not redeemed by the service, no refunds will be made for it: {}", e);
+                None
+            }
+        };
         info!("✅ Using payment: {} msats for {} seconds (verified by ngx_l402)", payment_amount_msats, duration_seconds);
 
         // Generate NPUB first and use it as pod name
@@ -394,13 +651,14 @@ impl PodProvisioningService {
         let pod_nsec = pod_keys.secret_key().unwrap().to_secret_hex();
         
         // Create Kubernetes-safe pod name from NPUB (take first 8 chars after npub1 prefix)
-        let pod_name = format!("pod-{}", pod_npub.replace("npub1", "").chars().take(8).collect::<String>());
+        let pod_name = pod_name_for_npub(&pod_npub);
         let username = request.ssh_username;
         let password = request.ssh_password;
         let image = request.pod_image;
         let ssh_port = match self.state.generate_ssh_port().await {
             Ok(port) => port,
             Err(e) => {
+                let refund_token = crate::cashu::refund_received_or_log(&request.cashu_token, received_msats, payment_amount_msats).await;
                 return Ok(SpawnPodResponse {
                     success: false,
                     message: "Failed to allocate SSH port".to_string(),
@@ -414,6 +672,8 @@ impl PodProvisioningService {
                     cpu_millicores: Some(pod_spec.cpu_millicores),
                     memory_mb: Some(pod_spec.memory_mb),
                     instructions: vec![format!("Port allocation error: {}", e)],
+                    refund_token,
+                    error: None,
                 });
             }
         };
@@ -421,8 +681,18 @@ impl PodProvisioningService {
         let now = Utc::now();
         let expires_at = now + chrono::Duration::seconds(duration_seconds as i64);
 
+        // Only what was redeemed here and pays for time can be refunded on terminate
+        let received_for_time_msats = received_msats
+            .map(|msats| msats.min(duration_seconds * pod_spec.rate_msats_per_sec))
+            .unwrap_or(0);
+
+        // Token already decoded above, so its mint and unit are known
+        let (payment_mint, payment_unit) = match crate::cashu::token_source(&request.cashu_token) {
+            Ok((mint, unit)) => (mint, unit.to_string()),
+            Err(_) => (String::new(), String::new()),
+        };
+
         match self.state.k8s_client.create_ssh_pod(
-            &self.state.config,
             &self.state.config.pod_namespace,
             &pod_name,
             &pod_npub,
@@ -434,7 +704,12 @@ impl PodProvisioningService {
             duration_seconds,
             pod_spec.memory_mb,
             pod_spec.cpu_millicores,
+            pod_spec.storage_gb,
             user_pubkey,
+            &request.ssh_public_keys,
+            request.disable_password_auth,
+            &pod_spec.network,
+            payment_annotations(payment_amount_msats, pod_spec.rate_msats_per_sec, &payment_mint, &payment_unit, received_for_time_msats),
         ).await {
             Ok(node_port) => {
                 let pod_info = PodInfo {
@@ -447,17 +722,27 @@ impl PodProvisioningService {
                     ssh_password: password.clone(),
                     payment_amount_msats,
                     duration_seconds,
+                    rate_msats_per_sec: pod_spec.rate_msats_per_sec,
+                    payment_mint,
+                    payment_unit,
+                    received_msats: received_for_time_msats,
                     node_port: Some(node_port),
                     nostr_public_key: pod_npub.clone(),
                     nostr_private_key: pod_nsec,
                 };
                 self.state.active_pods.write().await.insert(pod_npub.clone(), pod_info.clone());
 
+                let ssh_host = self.ssh_host_for(&pod_name).await;
+                let ssh_command = if request.ssh_public_keys.is_empty() {
+                    format!("   ssh -o PreferredAuthentications=password -o PubkeyAuthentication=no {}@{} -p {}", username, ssh_host, node_port)
+                } else {
+                    format!("   ssh {}@{} -p {}", username, ssh_host, node_port)
+                };
                 let instructions = vec![
                     "🚀 SSH access available:".to_string(),
                     "".to_string(),
                     "Direct access (no kubectl needed):".to_string(),
-                    format!("   ssh -o PreferredAuthentications=password -o PubkeyAuthentication=no {}@{} -p {}", username, self.state.config.ssh_host, node_port),
+                    ssh_command,
                     "".to_string(),
                     "⚠️  Pod expires at:".to_string(),
                     format!("   {}", expires_at.format("%Y-%m-%d %H:%M:%S UTC")),
@@ -472,11 +757,15 @@ impl PodProvisioningService {
 
                 info!("Pod with NPUB {} created successfully", pod_npub);
 
+                // Return whatever of the received amount doesn't buy a whole second at this spec's rate
+                let change_msats = received_msats.unwrap_or(0).saturating_sub(duration_seconds * pod_spec.rate_msats_per_sec);
+                let refund_token = crate::cashu::refund_received_or_log(&request.cashu_token, received_msats, change_msats).await;
+
                 Ok(SpawnPodResponse {
                     success: true,
                     message: format!("Pod created successfully. SSH access available for {} seconds", duration_seconds),
                     pod_npub: Some(pod_npub),
-                    ssh_host: Some(self.state.config.ssh_host.clone()),
+                    ssh_host: Some(ssh_host),
                     ssh_port: Some(node_port),
                     ssh_username: Some(username),
                     ssh_password: Some(password),
@@ -485,9 +774,13 @@ impl PodProvisioningService {
                     cpu_millicores: Some(pod_spec.cpu_millicores),
                     memory_mb: Some(pod_spec.memory_mb),
                     instructions,
+                    refund_token,
+                    error: None,
                 })
             }
             Err(e) => {
+                // Nothing was delivered, so hand back everything received
+                let refund_token = crate::cashu::refund_received_or_log(&request.cashu_token, received_msats, payment_amount_msats).await;
                 Ok(SpawnPodResponse {
                     success: false,
                     message: "Failed to create pod".to_string(),
@@ -501,6 +794,8 @@ impl PodProvisioningService {
                     cpu_millicores: Some(pod_spec.cpu_millicores),
                     memory_mb: Some(pod_spec.memory_mb),
                     instructions: vec![format!("Pod creation error: {}", e)],
+                    refund_token,
+                    error: None,
                 })
             }
         }
@@ -526,6 +821,8 @@ impl PodProvisioningService {
                     pod_npub: request.pod_npub,
                     extended_duration_seconds: None,
                     new_expires_at: None,
+                    refund_token: None,
+                    error: None,
                 });
             }
         };
@@ -564,6 +861,8 @@ impl PodProvisioningService {
                     pod_npub: request.pod_npub,
                     extended_duration_seconds: None,
                     new_expires_at: None,
+                    refund_token: None,
+                    error: None,
                 });
             }
         };
@@ -578,10 +877,25 @@ impl PodProvisioningService {
                     pod_npub: request.pod_npub,
                     extended_duration_seconds: None,
                     new_expires_at: None,
+                    refund_token: None,
+                    error: None,
                 });
             }
         };
 
+        // Reject tokens from mints we don't accept
+        if let Err(rejection) = crate::cashu::check_token_mint(&request.cashu_token, &self.state.config.whitelisted_mints) {
+            return Ok(TopUpPodResponse {
+                success: false,
+                message: rejection.message.clone(),
+                pod_npub: request.pod_npub,
+                extended_duration_seconds: None,
+                new_expires_at: None,
+                refund_token: None,
+                error: Some(rejection),
+            });
+        }
+
         // Extract payment amount from token
         let payment_amount_msats = match extract_token_value(&request.cashu_token).await {
             Ok(msats) => msats,
@@ -592,25 +906,38 @@ impl PodProvisioningService {
                     pod_npub: request.pod_npub,
                     extended_duration_seconds: None,
                     new_expires_at: None,
+                    refund_token: None,
+                    error: None,
                 });
             }
         };
 
-        // Calculate additional duration from payment
-        let additional_duration_seconds = self.state.calculate_duration_from_payment(payment_amount_msats);
+        // Calculate additional duration from payment at the rate the pod was sold at
+        let rate_msats_per_sec = self.state.pod_rate_msats_per_sec(&request.pod_npub).await;
+        let additional_duration_seconds = payment_amount_msats / rate_msats_per_sec;
         
         if additional_duration_seconds == 0 {
             return Ok(TopUpPodResponse {
                 success: false,
                 message: format!("Insufficient payment: {} msats. Minimum required: {} msats for 1 second extension", 
-                    payment_amount_msats, self.state.config.pod_specs.first().map(|s| s.rate_msats_per_sec).unwrap_or(100)),
+                    payment_amount_msats, rate_msats_per_sec),
                 pod_npub: request.pod_npub,
                 extended_duration_seconds: None,
                 new_expires_at: None,
+                refund_token: None,
+                error: None,
             });
         }
 
-        // Token verification handled by ngx_l402 at nginx layer
+        // Token verification handled by ngx_l402 at nginx layer; redeem it here too so
+        // refunds only ever come out of what this service actually received
+        let received_msats = match crate::cashu::receive_token(&request.cashu_token).await {
+            Ok(msats) => Some(msats),
+            Err(e) => {
+                warn!("Token not redeemed by the service, no refunds will be made for it: {}", e);
+                None
+            }
+        };
         info!("✅ Top-up payment: {} msats for {} additional seconds (verified by ngx_l402)", payment_amount_msats, additional_duration_seconds);
 
         // Get current pod configuration before restarting
@@ -620,12 +947,15 @@ impl PodProvisioningService {
             Ok(pod) => pod,
             Err(e) => {
                 error!("Failed to get current pod configuration: {}", e);
+                let refund_token = crate::cashu::refund_received_or_log(&request.cashu_token, received_msats, payment_amount_msats).await;
                 return Ok(TopUpPodResponse {
                     success: false,
                     message: format!("Failed to get pod configuration: {}", e),
                     pod_npub: request.pod_npub,
                     extended_duration_seconds: None,
                     new_expires_at: None,
+                    refund_token,
+                    error: None,
                 });
             }
         };
@@ -633,12 +963,15 @@ impl PodProvisioningService {
         // Use the proper deadline extension method instead of recreating the pod
         if let Err(e) = self.state.k8s_client.extend_pod_deadline(&self.state.config.pod_namespace, &pod_name, additional_duration_seconds).await {
             error!("Failed to extend pod deadline: {}", e);
+            let refund_token = crate::cashu::refund_received_or_log(&request.cashu_token, received_msats, payment_amount_msats).await;
             return Ok(TopUpPodResponse {
                 success: false,
                 message: format!("Failed to extend pod deadline: {}", e),
                 pod_npub: request.pod_npub,
                 extended_duration_seconds: None,
                 new_expires_at: None,
+                refund_token,
+                error: None,
             });
         }
 
@@ -659,14 +992,25 @@ impl PodProvisioningService {
             None => Utc::now() + chrono::Duration::seconds(additional_duration_seconds as i64), // Fallback
         };
         
-        // Update the pod info in our tracking with the new deadline
+        // Update the pod info in our tracking with the new deadline and the redeemed payment
+        let received_for_time_msats = received_msats
+            .map(|msats| msats.min(additional_duration_seconds * rate_msats_per_sec))
+            .unwrap_or(0);
         let mut active_pods = self.state.active_pods.write().await;
-        if let Some(pod_info) = active_pods.get_mut(&request.pod_npub) {
+        let total_received_msats = active_pods.get_mut(&request.pod_npub).map(|pod_info| {
             pod_info.expires_at = new_expires_at;
             pod_info.duration_seconds = current_deadline_seconds as u64 + additional_duration_seconds;
-        }
+            pod_info.received_msats += received_for_time_msats;
+            pod_info.received_msats
+        });
         drop(active_pods);
 
+        if let Some(total) = total_received_msats.filter(|_| received_for_time_msats > 0) {
+            if let Err(e) = self.state.k8s_client.record_received_msats(&self.state.config.pod_namespace, &pod_name, total).await {
+                warn!("Failed to record received payment on pod {}: {}", pod_name, e);
+            }
+        }
+
         info!(
             "🔄 Pod '{}' (NPUB: {}) extended by {} seconds (new deadline: {} seconds)",
             pod_name,
@@ -675,6 +1019,10 @@ impl PodProvisioningService {
             current_deadline_seconds + additional_duration_seconds as i64
         );
 
+        // Return whatever of the received amount doesn't buy a whole second at the pod's rate
+        let change_msats = received_msats.unwrap_or(0).saturating_sub(additional_duration_seconds * rate_msats_per_sec);
+        let refund_token = crate::cashu::refund_received_or_log(&request.cashu_token, received_msats, change_msats).await;
+
         Ok(TopUpPodResponse {
             success: true,
             message: format!(
@@ -687,6 +1035,8 @@ impl PodProvisioningService {
             pod_npub: request.pod_npub,
             extended_duration_seconds: Some(additional_duration_seconds),
             new_expires_at: Some(new_expires_at.to_rfc3339()),
+            refund_token,
+            error: None,
         })
     }
 }
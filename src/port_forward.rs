@@ -0,0 +1,125 @@
+// Host Port Forwarding
+//
+// Maintains iptables DNAT rules that expose a workload's SSH port on a host port.
+// Needed on Proxmox, where (unlike LXD proxy devices) nothing forwards ports from
+// the host to bridged containers and VMs. Rules live in dedicated chains and are
+// tagged with the workload ID so they can be replaced or removed individually.
+
+use std::process::Command;
+use anyhow::{Context, Result};
+use tracing::{debug, info};
+
+const NAT_CHAIN: &str = "PAYGRESS";
+const FORWARD_CHAIN: &str = "PAYGRESS-FORWARD";
+
+pub struct PortForwarder;
+
+impl PortForwarder {
+    /// Create the Paygress chains (if missing) and enable IPv4 forwarding
+    pub fn new() -> Result<Self> {
+        let forwarder = Self;
+        forwarder.ensure_chains()?;
+
+        std::fs::write("/proc/sys/net/ipv4/ip_forward", "1")
+            .context("Failed to enable IPv4 forwarding")?;
+
+        Ok(forwarder)
+    }
+
+    fn run_iptables(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new("iptables")
+            .args(args)
+            .output()
+            .context("Failed to execute iptables")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("iptables {} failed: {}", args.join(" "), stderr.trim()));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn ensure_chains(&self) -> Result<()> {
+        // -N fails if the chain already exists, which is fine
+        let _ = self.run_iptables(&["-t", "nat", "-N", NAT_CHAIN]);
+        let _ = self.run_iptables(&["-t", "filter", "-N", FORWARD_CHAIN]);
+
+        // Only traffic addressed to the host itself is forwarded, so the host's own
+        // outbound connections and routed traffic on the same ports are untouched
+        let jumps: [(&str, &str, &[&str]); 2] = [
+            ("nat", "PREROUTING", &["-m", "addrtype", "--dst-type", "LOCAL", "-j", NAT_CHAIN]),
+            ("filter", "FORWARD", &["-j", FORWARD_CHAIN]),
+        ];
+
+        for (table, parent, rule) in jumps {
+            let mut check = vec!["-t", table, "-C", parent];
+            check.extend_from_slice(rule);
+            if self.run_iptables(&check).is_ok() {
+                continue;
+            }
+
+            let mut insert = vec!["-t", table, "-I", parent, "1"];
+            insert.extend_from_slice(rule);
+            self.run_iptables(&insert)?;
+        }
+
+        Ok(())
+    }
+
+    /// Forward `host_port` on the host to `target_ip:target_port`, replacing any
+    /// rules previously installed for workload `id`
+    pub fn forward(&self, id: u32, host_port: u16, target_ip: &str, target_port: u16) -> Result<()> {
+        self.remove(id)?;
+
+        let tag = rule_tag(id);
+        let host_port = host_port.to_string();
+        let target_port = target_port.to_string();
+        let destination = format!("{}:{}", target_ip, target_port);
+
+        self.run_iptables(&[
+            "-t", "nat", "-A", NAT_CHAIN,
+            "-p", "tcp", "--dport", &host_port,
+            "-m", "comment", "--comment", &tag,
+            "-j", "DNAT", "--to-destination", &destination,
+        ])?;
+        self.run_iptables(&[
+            "-t", "filter", "-A", FORWARD_CHAIN,
+            "-p", "tcp", "-d", target_ip, "--dport", &target_port,
+            "-m", "comment", "--comment", &tag,
+            "-j", "ACCEPT",
+        ])?;
+
+        info!("Forwarding host port {} to {} for workload {}", host_port, destination, id);
+        Ok(())
+    }
+
+    /// Remove every rule installed for workload `id`
+    pub fn remove(&self, id: u32) -> Result<()> {
+        let tag = rule_tag(id);
+
+        for (table, chain) in [("nat", NAT_CHAIN), ("filter", FORWARD_CHAIN)] {
+            let rules = self.run_iptables(&["-t", table, "-S", chain])?;
+
+            for rule in rules.lines() {
+                let args: Vec<&str> = rule.split_whitespace().collect();
+                let tagged = args.windows(2).any(|w| w[0] == "--comment" && w[1] == tag);
+                if !tagged || args.first() != Some(&"-A") {
+                    continue;
+                }
+
+                // `-S` prints rules as "-A <chain> ..."; the same args with -D delete it
+                let mut delete = vec!["-t", table, "-D"];
+                delete.extend_from_slice(&args[1..]);
+                self.run_iptables(&delete)?;
+                debug!("Removed {} rule for workload {}: {}", table, id, rule);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn rule_tag(id: u32) -> String {
+    format!("paygress-{}", id)
+}
@@ -7,31 +7,42 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
 
 use crate::nostr::{
     NostrRelaySubscriber, RelayConfig, ProviderOfferContent, HeartbeatContent, 
-    CapacityInfo, PodSpec, EncryptedSpawnPodRequest, AccessDetailsContent, 
-    ErrorResponseContent, parse_private_message_content, PrivateRequest,
-    StatusRequestContent, StatusResponseContent,
+    CapacityInfo, PodSpec, WorkloadKind, EncryptedSpawnPodRequest, EncryptedTopUpPodRequest, AccessDetailsContent, 
+    ErrorResponseContent, TopUpResponseContent, parse_private_message_content, PrivateRequest,
+    StatusRequestContent, StatusResponseContent, TerminateRequestContent, TerminateResponseContent,
+    validate_ssh_public_keys,
 };
 use crate::proxmox::{ProxmoxClient, ProxmoxBackend};
-use crate::compute::{ComputeBackend, ContainerConfig};
+use crate::admission::{AdmissionController, OvercommitRatios, Resources};
+use crate::compute::{ComputeBackend, ContainerConfig, NodeStatus};
 use crate::lxd::LxdBackend;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::lxd_api::{default_socket_path, LxdApiBackend, LxdClient};
+use crate::docker::DockerBackend;
+use crate::kubernetes::KubernetesBackend;
+use crate::sidecar_service::PodNetworking;
+use crate::ledger::{PaymentRecord, WorkloadLedger, STAT_JOBS_COMPLETED};
+use crate::images::{available_images, default_catalog, find_image, ImageEntry};
+use crate::port_forward::PortForwarder;
+use crate::provisioning::validate_credentials;
+use crate::transport::{request_handler, ProviderTransport};
+use crate::cashu::{CashuPayments, PaymentProcessor};
+use crate::wallet::{admin_socket_path, serve_admin_socket, CashuWallet};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub enum BackendType {
+    #[default]
     Proxmox,
-    LXD,
-}
-
-impl Default for BackendType {
-    fn default() -> Self {
-        Self::Proxmox
-    }
+    LXD,     // `lxc` command line tool
+    LxdApi,  // LXD REST API (local socket or remote HTTPS)
+    Docker,  // Docker Engine API (or Podman's compatible socket), containers only
+    Kubernetes, // Pods through the sidecar's PodManager, containers only
 }
 
 
@@ -50,8 +61,35 @@ pub struct ProviderConfig {
     pub proxmox_storage: String,
     pub proxmox_template: String,
     pub proxmox_bridge: String,
+    // Install iptables DNAT rules for SSH ports (needs the provider to run on the Proxmox node)
+    #[serde(default = "default_proxmox_port_forwarding")]
+    pub proxmox_port_forwarding: bool,
     pub vmid_range_start: u32,
     pub vmid_range_end: u32,
+
+    // LXD REST API settings (backend_type "LxdApi"). Without lxd_url the local
+    // unix socket is used; with it, the client cert/key must be trusted by the server.
+    #[serde(default)]
+    pub lxd_socket_path: Option<String>,
+    #[serde(default)]
+    pub lxd_url: Option<String>,
+    #[serde(default)]
+    pub lxd_client_cert: Option<String>,
+    #[serde(default)]
+    pub lxd_client_key: Option<String>,
+    #[serde(default)]
+    pub lxd_server_cert: Option<String>,
+
+    // Docker settings (backend_type "Docker"; default /var/run/docker.sock)
+    #[serde(default)]
+    pub docker_socket_path: Option<String>,
+
+    // Kubernetes settings (backend_type "Kubernetes"; default namespace paygress-workloads).
+    // The cluster comes from the local kubeconfig or the pod's service account.
+    #[serde(default)]
+    pub kubernetes_namespace: Option<String>,
+    #[serde(default)]
+    pub kubernetes_networking: PodNetworking, // NodePort unless set
     
     // Nostr settings
     pub nostr_private_key: String,
@@ -71,6 +109,10 @@ pub struct ProviderConfig {
     pub heartbeat_interval_secs: u64,
     pub minimum_duration_seconds: u64,
 
+    // How far CPU, memory and disk may be oversold (1.0 each, i.e. not at all, unless set)
+    #[serde(default)]
+    pub overcommit: OvercommitRatios,
+
     // Tunnel settings (for providers behind NAT)
     #[serde(default)]
     pub tunnel_enabled: bool,
@@ -80,6 +122,30 @@ pub struct ProviderConfig {
     pub ssh_port_start: Option<u16>,
     #[serde(default)]
     pub ssh_port_end: Option<u16>,
+
+    // Cashu wallet (tokens are redeemed here before provisioning)
+    #[serde(default = "default_cashu_db_path")]
+    pub cashu_db_path: String,
+
+    // Workload ledger (survives provider restarts)
+    #[serde(default = "default_ledger_path")]
+    pub ledger_path: String,
+
+    // Images users can request (empty = built-in catalog, see images::default_catalog)
+    #[serde(default)]
+    pub images: Vec<ImageEntry>,
+}
+
+fn default_cashu_db_path() -> String {
+    "/var/lib/paygress/cashu.db".to_string()
+}
+
+fn default_ledger_path() -> String {
+    "/var/lib/paygress/ledger.redb".to_string()
+}
+
+fn default_proxmox_port_forwarding() -> bool {
+    true
 }
 
 impl Default for ProviderConfig {
@@ -93,8 +159,17 @@ impl Default for ProviderConfig {
             proxmox_storage: "local-lvm".to_string(),
             proxmox_template: "local:vztmpl/ubuntu-22.04-standard.tar.zst".to_string(),
             proxmox_bridge: "vmbr0".to_string(),
+            proxmox_port_forwarding: default_proxmox_port_forwarding(),
             vmid_range_start: 1000,
             vmid_range_end: 1999,
+            lxd_socket_path: None,
+            lxd_url: None,
+            lxd_client_cert: None,
+            lxd_client_key: None,
+            lxd_server_cert: None,
+            docker_socket_path: None,
+            kubernetes_namespace: None,
+            kubernetes_networking: PodNetworking::default(),
             nostr_private_key: String::new(),
             nostr_relays: vec![
                 "wss://relay.damus.io".to_string(),
@@ -111,49 +186,145 @@ impl Default for ProviderConfig {
                     description: "1 vCPU, 1GB RAM".to_string(),
                     cpu_millicores: 1000,
                     memory_mb: 1024,
+                    storage_gb: 10,
+                    disk_iops: None,
                     rate_msats_per_sec: 50,
+                    kind: WorkloadKind::Container,
+                    network: Default::default(),
                 },
             ],
             whitelisted_mints: vec!["https://mint.minibits.cash".to_string()],
             heartbeat_interval_secs: 60,
             minimum_duration_seconds: 60,
+            overcommit: OvercommitRatios::default(),
             tunnel_enabled: false,
             tunnel_interface: None,
             ssh_port_start: None,
             ssh_port_end: None,
+            cashu_db_path: default_cashu_db_path(),
+            ledger_path: default_ledger_path(),
+            images: Vec::new(),
         }
     }
 }
 
 /// Active workload tracking
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkloadInfo {
     pub vmid: u32,
-    pub workload_type: String,  // "lxc" or "vm"
+    pub workload_type: WorkloadKind,  // Serialized as "lxc" or "vm"
     pub spec_id: String,
     pub created_at: u64,
     pub expires_at: u64,
     pub owner_npub: String,
+    pub payment_mint: String, // Mint the payment was redeemed at (refunds are paid from here)
+    pub payment_unit: String,
+    #[serde(default)]
+    pub host_port: u16, // SSH port on the host (0 in ledgers written before ports were recorded)
+    #[serde(default)]
+    pub rate_msats_per_sec: u64, // Rate the workload was sold at (0 in older ledgers)
+    #[serde(default)]
+    pub received_msats: u64, // Redeemed and paying for time; terminate refunds never exceed it
 }
 
 /// Provider service that manages the node
 pub struct ProviderService {
     config: ProviderConfig,
     backend: Arc<dyn ComputeBackend>,
-    nostr: NostrRelaySubscriber,
+    nostr: Arc<dyn ProviderTransport>,
+    payments: Arc<dyn PaymentProcessor>,
     active_workloads: Arc<Mutex<HashMap<u32, WorkloadInfo>>>,
     stats: Arc<Mutex<ProviderStats>>,
+    ledger: Arc<WorkloadLedger>,
+    ports: Arc<Mutex<HostPortAllocator>>,
+    admission: Arc<AdmissionController>,
 }
 
 #[derive(Debug, Clone, Default)]
 struct ProviderStats {
     total_jobs_completed: u64,
-    uptime_start: u64,
+}
+
+/// Node totals in the units specs are sold in
+fn node_resources(status: &NodeStatus) -> Resources {
+    Resources {
+        cpu_millicores: status.cpu_total,
+        memory_mb: status.memory_total / (1024 * 1024),
+        storage_gb: status.disk_total / (1024 * 1024 * 1024),
+    }
+}
+
+/// Resources claimed by active workloads, going by their tier
+fn committed_resources(workloads: &HashMap<u32, WorkloadInfo>, specs: &[PodSpec]) -> Resources {
+    workloads.values()
+        .filter_map(|workload| specs.iter().find(|spec| spec.id == workload.spec_id))
+        .map(Resources::of_spec)
+        .sum()
+}
+
+/// Host ports handed out to workloads for SSH access.
+///
+/// Like `sidecar_service::PortPool`, but durable: each workload's port is stored on its
+/// `WorkloadInfo` in the ledger, and the allocator is rebuilt from those on startup.
+/// Ports something else on the host is already listening on are skipped.
+pub struct HostPortAllocator {
+    range_start: u16,
+    range_end: u16,
+    allocated: HashSet<u16>,
+}
+
+impl HostPortAllocator {
+    pub fn new(range_start: u16, range_end: u16) -> Self {
+        Self {
+            range_start,
+            range_end,
+            allocated: HashSet::new(),
+        }
+    }
+
+    /// Range from `ssh_port_start`/`ssh_port_end`, or one port per VMID from 30000
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        let start = config.ssh_port_start.unwrap_or(30000);
+        let slots = config.vmid_range_end.saturating_sub(config.vmid_range_start);
+        let end = config.ssh_port_end
+            .unwrap_or_else(|| u16::try_from(start as u32 + slots).unwrap_or(u16::MAX));
+        Self::new(start, end.max(start))
+    }
+
+    /// Record a port that is already in use by a workload
+    pub fn mark_allocated(&mut self, port: u16) {
+        self.allocated.insert(port);
+    }
+
+    /// Hand out the lowest free port in the range
+    pub fn allocate(&mut self) -> Option<u16> {
+        let port = (self.range_start..=self.range_end)
+            .filter(|port| !self.allocated.contains(port))
+            .find(|port| std::net::TcpListener::bind(("0.0.0.0", *port)).is_ok())?;
+        self.allocated.insert(port);
+        Some(port)
+    }
+
+    /// Return a port once its workload is gone
+    pub fn release(&mut self, port: u16) {
+        self.allocated.remove(&port);
+    }
+
+    pub fn available_count(&self) -> usize {
+        let in_use = self.allocated.iter()
+            .filter(|port| (self.range_start..=self.range_end).contains(*port))
+            .count();
+        (self.range_end - self.range_start) as usize + 1 - in_use
+    }
 }
 
 impl ProviderService {
     /// Create a new provider service
-    pub async fn new(config: ProviderConfig) -> Result<Self> {
+    pub async fn new(mut config: ProviderConfig) -> Result<Self> {
+        if config.images.is_empty() {
+            config.images = default_catalog(&config.proxmox_template);
+        }
+
         let backend: Arc<dyn ComputeBackend> = match config.backend_type {
             BackendType::Proxmox => {
                 let client = ProxmoxClient::new(
@@ -162,21 +333,82 @@ impl ProviderService {
                     &config.proxmox_token_secret,
                     &config.proxmox_node,
                 )?;
-                Arc::new(ProxmoxBackend::new(
+                let backend = ProxmoxBackend::new(
                     client,
                     &config.proxmox_storage,
                     &config.proxmox_bridge,
                     &config.proxmox_template,
-                ))
+                    config.images.clone(),
+                );
+                if config.proxmox_port_forwarding {
+                    Arc::new(backend.with_port_forwarding(PortForwarder::new()?))
+                } else {
+                    Arc::new(backend)
+                }
             }
             BackendType::LXD => {
                 Arc::new(LxdBackend::new(
                     &config.proxmox_storage, // Reuse storage field for pool name
                     &config.proxmox_bridge,  // Reuse bridge for network
+                    config.images.clone(),
+                ))
+            }
+            BackendType::LxdApi => {
+                let client = match &config.lxd_url {
+                    Some(url) => {
+                        let cert = config.lxd_client_cert.as_deref()
+                            .ok_or_else(|| anyhow::anyhow!("lxd_client_cert is required with lxd_url"))?;
+                        let key = config.lxd_client_key.as_deref()
+                            .ok_or_else(|| anyhow::anyhow!("lxd_client_key is required with lxd_url"))?;
+                        LxdClient::https(url, cert, key, config.lxd_server_cert.as_deref())?
+                    }
+                    None => LxdClient::unix(&config.lxd_socket_path.clone().unwrap_or_else(default_socket_path)),
+                };
+                Arc::new(LxdApiBackend::new(
+                    client,
+                    &config.proxmox_storage, // Reuse storage field for pool name
+                    config.images.clone(),
                 ))
             }
+            BackendType::Docker => {
+                let backend = DockerBackend::new(
+                    config.docker_socket_path.as_deref().unwrap_or(crate::docker::DEFAULT_SOCKET_PATH),
+                    config.images.clone(),
+                );
+                backend.check_specs(&config.specs).await?;
+                Arc::new(backend)
+            }
+            BackendType::Kubernetes => {
+                // Host ports become node ports, which the API server only accepts inside its range.
+                // The default range (one port per VMID) is trimmed to fit; a configured one must.
+                let ports = HostPortAllocator::from_config(&config);
+                if config.kubernetes_networking == PodNetworking::NodePort && config.ssh_port_end.is_none() {
+                    config.ssh_port_end = Some(ports.range_end.min(PodNetworking::NODE_PORT_RANGE.1));
+                }
+                let ports = HostPortAllocator::from_config(&config);
+                config.kubernetes_networking.check_port_range(ports.range_start, ports.range_end)
+                    .map_err(|e| anyhow::anyhow!("{} (ssh_port_start/ssh_port_end)", e))?;
+                Arc::new(KubernetesBackend::new(
+                    config.kubernetes_namespace.as_deref().unwrap_or(crate::kubernetes::DEFAULT_NAMESPACE),
+                    config.kubernetes_networking,
+                    config.images.clone(),
+                ).await?)
+            }
         };
 
+        // Initialize the Cashu wallet used to redeem incoming tokens
+        crate::cashu::initialize_cashu(&config.cashu_db_path).await
+            .map_err(|e| anyhow::anyhow!("Cashu init failed: {}", e))?;
+
+        // This process now holds the wallet's lock, so `provider wallet` goes through here
+        let wallet = CashuWallet::open(&config.cashu_db_path).await?;
+        let wallet_socket = admin_socket_path(&config.cashu_db_path);
+        tokio::spawn(async move {
+            if let Err(e) = serve_admin_socket(wallet, &wallet_socket).await {
+                warn!("Wallet socket stopped: {}", e);
+            }
+        });
+
         // Initialize Nostr client
         let relay_config = RelayConfig {
             relays: config.nostr_relays.clone(),
@@ -184,25 +416,49 @@ impl ProviderService {
         };
         let nostr = NostrRelaySubscriber::new(relay_config).await?;
 
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs();
+        Self::with_components(config, backend, Arc::new(nostr), Arc::new(CashuPayments)).await
+    }
+
+    /// Create a provider service from already-built parts, e.g. `mock::MockBackend`,
+    /// `mock::MockRelay` and `mock::MockPayments` in tests
+    pub async fn with_components(
+        mut config: ProviderConfig,
+        backend: Arc<dyn ComputeBackend>,
+        nostr: Arc<dyn ProviderTransport>,
+        payments: Arc<dyn PaymentProcessor>,
+    ) -> Result<Self> {
+        if config.images.is_empty() {
+            config.images = default_catalog(&config.proxmox_template);
+        }
+
+        // Reload workloads and stats from before any restart
+        let ledger = WorkloadLedger::open(&config.ledger_path)?;
+        let mut workloads = ledger.load_workloads()?;
+        let ports = restore_host_ports(&config, &ledger, &mut workloads);
+        let total_jobs_completed = ledger.get_stat(STAT_JOBS_COMPLETED)?;
+        info!("Loaded {} workloads from ledger {}", workloads.len(), config.ledger_path);
+
+        let supported = backend.supported_kinds();
+        for spec in config.specs.iter().filter(|s| !supported.contains(&s.kind)) {
+            warn!("Tier {} provisions a {} but this backend doesn't support it", spec.id, spec.kind.as_str());
+        }
 
         Ok(Self {
+            admission: Arc::new(AdmissionController::new(config.overcommit.clone())),
             config,
             backend,
             nostr,
-            active_workloads: Arc::new(Mutex::new(HashMap::new())),
-            stats: Arc::new(Mutex::new(ProviderStats {
-                total_jobs_completed: 0,
-                uptime_start: now,
-            })),
+            payments,
+            active_workloads: Arc::new(Mutex::new(workloads)),
+            stats: Arc::new(Mutex::new(ProviderStats { total_jobs_completed })),
+            ledger: Arc::new(ledger),
+            ports: Arc::new(Mutex::new(ports)),
         })
     }
 
     /// Get the provider's public key (npub)
     pub fn get_npub(&self) -> String {
-        self.nostr.get_service_public_key()
+        self.nostr.public_key_hex()
     }
 
     /// Start the provider service (runs forever)
@@ -211,6 +467,11 @@ impl ProviderService {
         info!("Provider: {}", self.config.provider_name);
         info!("NPUB: {}", self.get_npub());
 
+        // Make the ledger match reality before accepting new requests
+        if let Err(e) = self.reconcile_workloads().await {
+            warn!("Failed to reconcile ledger with backend: {}", e);
+        }
+
         // Publish initial offer
         self.publish_offer().await?;
 
@@ -231,18 +492,92 @@ impl ProviderService {
         }
     }
 
+    /// Reconcile the ledger with the containers that actually exist on the backend.
+    ///
+    /// Workloads whose container is gone are dropped. Paygress containers with no
+    /// ledger entry (e.g. created just before a crash) are reaped, since nobody is
+    /// paying for them. Everything else is adopted and expires through the cleanup loop.
+    async fn reconcile_workloads(&self) -> Result<()> {
+        let range = self.config.vmid_range_start..=self.config.vmid_range_end;
+        let existing: HashMap<u32, WorkloadKind> = self.backend.list_containers().await?
+            .into_iter()
+            .filter(|(id, _)| range.contains(id))
+            .collect();
+
+        let mut workloads = self.active_workloads.lock().await;
+
+        let missing: Vec<u32> = workloads.keys()
+            .filter(|vmid| !existing.contains_key(vmid))
+            .copied()
+            .collect();
+        for vmid in missing {
+            warn!("Workload {} is in the ledger but its container no longer exists", vmid);
+            if let Some(workload) = workloads.remove(&vmid) {
+                self.ports.lock().await.release(workload.host_port);
+            }
+            if let Err(e) = self.ledger.remove_workload(vmid) {
+                warn!("Failed to remove workload {} from ledger: {}", vmid, e);
+            }
+        }
+
+        for (vmid, kind) in existing {
+            if workloads.contains_key(&vmid) {
+                continue;
+            }
+            warn!("Reaping orphaned {} {} (no ledger entry)", kind.as_str(), vmid);
+            if let Err(e) = self.backend.stop_container(vmid, kind).await {
+                debug!("Stop before delete failed for {}: {}", vmid, e);
+            }
+            if let Err(e) = self.backend.delete_container(vmid, kind).await {
+                error!("Failed to reap orphaned container {}: {}", vmid, e);
+            }
+        }
+
+        info!("Adopted {} workloads from the ledger", workloads.len());
+        Ok(())
+    }
+
+    /// Workload kinds offered by at least one spec
+    fn spec_kinds(&self) -> Vec<WorkloadKind> {
+        let mut kinds: Vec<WorkloadKind> = self.config.specs.iter().map(|s| s.kind).collect();
+        kinds.sort_by_key(|k| k.as_str());
+        kinds.dedup();
+        kinds
+    }
+
+    /// Configured capabilities plus the kind of every offered spec
+    fn capabilities(&self) -> Vec<String> {
+        let mut capabilities = self.config.capabilities.clone();
+        for kind in self.spec_kinds() {
+            if !capabilities.iter().any(|c| c == kind.as_str()) {
+                capabilities.push(kind.as_str().to_string());
+            }
+        }
+        capabilities
+    }
+
     /// Publish provider offer to Nostr
     async fn publish_offer(&self) -> Result<()> {
+        // Advertise our own heartbeat-derived uptime; clients recompute it anyway
+        let uptime_percent = match self.nostr.calculate_uptime(&self.get_npub(), 7).await {
+            Ok(uptime) => uptime,
+            Err(e) => {
+                warn!("Failed to calculate uptime: {}", e);
+                0.0
+            }
+        };
+
         let stats = self.stats.lock().await;
         
         let offer = ProviderOfferContent {
             provider_npub: self.get_npub(),
             hostname: self.config.provider_name.clone(),
             location: self.config.provider_location.clone(),
-            capabilities: self.config.capabilities.clone(),
+            capabilities: self.capabilities(),
             specs: self.config.specs.clone(),
             whitelisted_mints: self.config.whitelisted_mints.clone(),
-            uptime_percent: 100.0, // Will be calculated from heartbeat history
+            images: available_images(&self.config.images, &self.config.backend_type, &self.spec_kinds()),
+            uptime_percent,
             total_jobs_completed: stats.total_jobs_completed,
             api_endpoint: None, // TODO: Add if supporting direct API
         };
@@ -267,20 +602,28 @@ impl ProviderService {
     async fn send_heartbeat(&self) -> Result<()> {
         let workloads = self.active_workloads.lock().await;
         
-        // Get node status for capacity info
-        let capacity = match self.backend.get_node_status().await {
-            Ok(status) => CapacityInfo {
-                cpu_available: ((1.0 - status.cpu_usage) * 100000.0) as u64, // Convert to millicores
-                memory_mb_available: status.memory_total.saturating_sub(status.memory_used) / (1024 * 1024),
-                storage_gb_available: status.disk_total.saturating_sub(status.disk_used) / (1024 * 1024 * 1024), 
-            },
+        // Advertise what can still be sold, not what the host happens to have free
+        let (capacity, available_slots) = match self.backend.get_node_status().await {
+            Ok(status) => {
+                let total = node_resources(&status);
+                let committed = committed_resources(&workloads, &self.config.specs);
+                let sellable = self.admission.sellable(total, committed);
+                let slots = self.config.specs.iter()
+                    .filter_map(|spec| Some((spec.id.clone(), self.admission.slots(spec, total, committed)?)))
+                    .collect();
+                (CapacityInfo {
+                    cpu_available: sellable.cpu_millicores,
+                    memory_mb_available: sellable.memory_mb,
+                    storage_gb_available: sellable.storage_gb,
+                }, slots)
+            }
             Err(e) => {
                 warn!("Failed to get node status: {}", e);
-                CapacityInfo {
+                (CapacityInfo {
                     cpu_available: 0,
                     memory_mb_available: 0,
                     storage_gb_available: 0,
-                }
+                }, HashMap::new())
             }
         };
 
@@ -293,6 +636,8 @@ impl ProviderService {
             timestamp: now,
             active_workloads: workloads.len() as u32,
             available_capacity: capacity,
+            interval_secs: self.config.heartbeat_interval_secs,
+            available_slots,
         };
 
         self.nostr.publish_heartbeat(heartbeat).await?;
@@ -307,18 +652,26 @@ impl ProviderService {
         let backend = self.backend.clone();
         let config = self.config.clone();
         let nostr = self.nostr.clone();
+        let payments = self.payments.clone();
         let workloads = self.active_workloads.clone();
         let stats = self.stats.clone();
+        let ledger = self.ledger.clone();
+        let ports = self.ports.clone();
+        let admission = self.admission.clone();
 
-        self.nostr.subscribe_to_pod_events(move |event| {
+        self.nostr.subscribe_to_requests(request_handler(move |event| {
             let backend = backend.clone();
             let config = config.clone();
             let nostr = nostr.clone();
+            let payments = payments.clone();
             let workloads = workloads.clone();
             let stats = stats.clone();
+            let ledger = ledger.clone();
+            let ports = ports.clone();
+            let admission = admission.clone();
             
             Box::pin(async move {
-                let my_pubkey = nostr.public_key().to_hex();
+                let my_pubkey = nostr.public_key_hex();
                 if event.pubkey == my_pubkey {
                     return Ok(());
                 }
@@ -334,6 +687,8 @@ impl ProviderService {
                             error_type: "invalid_request".to_string(),
                             message: "Failed to parse request".to_string(),
                             details: Some(e.to_string()),
+                            accepted_mints: vec![],
+                            refund_token: None,
                         };
                         let _ = nostr.send_error_response_private_message(
                             &event.pubkey,
@@ -352,9 +707,13 @@ impl ProviderService {
                         if let Err(e) = handle_spawn_request(
                             backend.as_ref(),
                             &config,
-                            &nostr,
+                            nostr.as_ref(),
+                            payments.as_ref(),
                             &workloads,
                             &stats,
+                            &ledger,
+                            &ports,
+                            &admission,
                             &event.pubkey,
                             &event.message_type,
                             spawn_req,
@@ -366,7 +725,7 @@ impl ProviderService {
                         if let Err(e) = handle_status_request(
                             backend.as_ref(),
                             &config,
-                            &nostr,
+                            nostr.as_ref(),
                             &workloads,
                             &event.pubkey,
                             &event.message_type,
@@ -375,21 +734,41 @@ impl ProviderService {
                             error!("Failed to handle status request: {}", e);
                         }
                     }
-                    PrivateRequest::TopUp(_) => {
-                        warn!("TopUp request received but not yet fully implemented");
-                        let _ = nostr.send_error_response(
+                    PrivateRequest::Terminate(terminate_req) => {
+                        if let Err(e) = handle_terminate_request(
+                            backend.as_ref(),
+                            nostr.as_ref(),
+                            payments.as_ref(),
+                            &workloads,
+                            &ledger,
+                            &ports,
                             &event.pubkey,
-                            "not_implemented",
-                            "TopUp is not yet implemented on this provider",
-                            None,
                             &event.message_type,
-                        ).await;
+                            terminate_req,
+                        ).await {
+                            error!("Failed to handle terminate request: {}", e);
+                        }
+                    }
+                    PrivateRequest::TopUp(topup_req) => {
+                        if let Err(e) = handle_topup_request(
+                            backend.as_ref(),
+                            &config,
+                            nostr.as_ref(),
+                            payments.as_ref(),
+                            &workloads,
+                            &ledger,
+                            &event.pubkey,
+                            &event.message_type,
+                            topup_req,
+                        ).await {
+                            error!("Failed to handle topup request: {}", e);
+                        }
                     }
                 }
 
                 Ok(())
             })
-        }).await?;
+        })).await?;
 
         Ok(())
     }
@@ -400,59 +779,83 @@ impl ProviderService {
         
         loop {
             tokio::time::sleep(interval).await;
+            self.cleanup_expired().await?;
+        }
+    }
+
+    /// Stop and delete every workload whose paid time has run out
+    pub async fn cleanup_expired(&self) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let mut workloads = self.active_workloads.lock().await;
+        let expired: Vec<u32> = workloads
+            .iter()
+            .filter(|(_, w)| w.expires_at <= now)
+            .map(|(vmid, _)| *vmid)
+            .collect();
+
+        for vmid in expired {
+            info!("Cleaning up expired workload: {}", vmid);
             
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)?
-                .as_secs();
-
-            let mut workloads = self.active_workloads.lock().await;
-            let expired: Vec<u32> = workloads
-                .iter()
-                .filter(|(_, w)| w.expires_at <= now)
-                .map(|(vmid, _)| *vmid)
-                .collect();
-
-            for vmid in expired {
-                info!("Cleaning up expired workload: {}", vmid);
-                
-                if let Some(_workload) = workloads.remove(&vmid) {
-                    let stop_result = self.backend.stop_container(vmid).await;
-                    let result = match stop_result {
-                        Ok(_) => self.backend.delete_container(vmid).await,
-                        Err(e) => Err(e),
-                    };
-
-                    match result {
-                        Ok(_) => {
-                            info!("Cleaned up workload {}", vmid);
-                            let mut stats = self.stats.lock().await;
-                            stats.total_jobs_completed += 1;
-                        }
-                        Err(e) => error!("Failed to cleanup workload {}: {}", vmid, e),
+            if let Some(workload) = workloads.remove(&vmid) {
+                if let Err(e) = self.ledger.remove_workload(vmid) {
+                    warn!("Failed to remove workload {} from ledger: {}", vmid, e);
+                }
+                self.ports.lock().await.release(workload.host_port);
+
+                let stop_result = self.backend.stop_container(vmid, workload.workload_type).await;
+                let result = match stop_result {
+                    Ok(_) => self.backend.delete_container(vmid, workload.workload_type).await,
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(_) => {
+                        info!("Cleaned up workload {}", vmid);
+                        let mut stats = self.stats.lock().await;
+                        stats.total_jobs_completed += 1;
+                        persist_jobs_completed(&self.ledger, stats.total_jobs_completed);
                     }
+                    Err(e) => error!("Failed to cleanup workload {}: {}", vmid, e),
                 }
             }
         }
+
+        Ok(())
     }
 }
 
 // Clone impl removed as ComputeBackend is Arc'd
 
 /// Handle a spawn request
+#[allow(clippy::too_many_arguments)]
 async fn handle_spawn_request(
     backend: &dyn ComputeBackend,
     config: &ProviderConfig,
-    nostr: &NostrRelaySubscriber,
+    nostr: &dyn ProviderTransport,
+    payments: &dyn PaymentProcessor,
     workloads: &Arc<Mutex<HashMap<u32, WorkloadInfo>>>,
     stats: &Arc<Mutex<ProviderStats>>,
+    ledger: &WorkloadLedger,
+    ports: &Mutex<HostPortAllocator>,
+    admission: &AdmissionController,
     requester_pubkey: &str,
     message_type: &str,
     request: EncryptedSpawnPodRequest,
 ) -> Result<()> {
     info!("Processing spawn request from {} (tier: {:?})", requester_pubkey, request.pod_spec_id);
 
+    // Reject credentials that could break out of the provisioning scripts before touching anything
+    if let Err(e) = validate_credentials(&request.ssh_username, &request.ssh_password) {
+        warn!("Rejecting spawn request from {}: {}", requester_pubkey, e);
+        nostr.send_error_response_private_message(requester_pubkey, e.to_error_response(), message_type).await?;
+        return Ok(());
+    }
+
     // 1. Extract Cashu token value
-    let payment_msats = match crate::cashu::extract_token_value(&request.cashu_token).await {
+    let payment_msats = match payments.extract_token_value(&request.cashu_token).await {
         Ok(v) => v,
         Err(e) => {
             let err_msg = format!("Invalid Cashu token: {}", e);
@@ -468,6 +871,33 @@ async fn handle_spawn_request(
         }
     };
 
+    // Only accept tokens from mints we trust to honour the swap
+    if let Err(rejection) = payments.check_token_mint(&request.cashu_token, &config.whitelisted_mints) {
+        nostr.send_error_response_private_message(requester_pubkey, rejection, message_type).await?;
+        return Ok(());
+    }
+    // Where refunds for this token come from, settled before anything is redeemed or created
+    let (payment_mint, payment_unit) = match payments.token_source(&request.cashu_token) {
+        Ok(source) => source,
+        Err(e) => {
+            nostr.send_error_response(requester_pubkey, "invalid_token", &e.to_string(), None, message_type).await?;
+            return Ok(());
+        }
+    };
+
+    // Reject bad keys before the token is redeemed
+    if let Err(e) = validate_ssh_public_keys(&request.ssh_public_keys, request.disable_password_auth) {
+        warn!("{}", e);
+        nostr.send_error_response(
+            requester_pubkey,
+            "invalid_ssh_key",
+            &e,
+            None,
+            message_type,
+        ).await?;
+        return Ok(());
+    }
+
     // 2. Find matching spec/tier
     let spec = match config.specs.iter().find(|s| Some(s.id.clone()) == request.pod_spec_id) {
         Some(s) => s,
@@ -490,6 +920,53 @@ async fn handle_spawn_request(
         }
     };
 
+    // Only launch images from the catalog this backend can run as the spec's kind
+    let image = match find_image(&config.images, &request.pod_image)
+        .filter(|image| image.available_on(&config.backend_type, spec.kind))
+    {
+        Some(image) => image.name.clone(),
+        None => {
+            let available = available_images(&config.images, &config.backend_type, &[spec.kind]);
+            let err_msg = format!("Image '{}' is not available as a {} on this provider", request.pod_image, spec.kind.as_str());
+            warn!("{}", err_msg);
+            nostr.send_error_response(
+                requester_pubkey,
+                "image_not_available",
+                &err_msg,
+                Some(&format!("Available images for tier {}: {}", spec.id, available.join(", "))),
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Refuse tiers the node can't hold before the token is redeemed. The reservation
+    // covers the spawn until the workload is tracked below.
+    let status = match backend.get_node_status().await {
+        Ok(status) => status,
+        Err(e) => {
+            let err_msg = format!("Failed to check node capacity: {}", e);
+            error!("{}", err_msg);
+            nostr.send_error_response(
+                requester_pubkey,
+                "provisioning_error",
+                &err_msg,
+                None,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+    let committed = committed_resources(&*workloads.lock().await, &config.specs);
+    let reservation = match admission.admit(spec, node_resources(&status), committed) {
+        Ok(reservation) => reservation,
+        Err(e) => {
+            warn!("Rejecting spawn request from {}: {}", requester_pubkey, e);
+            nostr.send_error_response_private_message(requester_pubkey, e.to_error_response(), message_type).await?;
+            return Ok(());
+        }
+    };
+
     // 3. Calculate Duration
     let duration_secs = payment_msats / spec.rate_msats_per_sec;
     if duration_secs < config.minimum_duration_seconds {
@@ -531,68 +1008,160 @@ async fn handle_spawn_request(
         }
     };
 
-    // 5. Generate credentials
+    // 5. Redeem the token at its mint - only a successful swap pays for the workload
+    let received_msats = match payments.receive_token(&request.cashu_token).await {
+        Ok(v) => v,
+        Err(e) => {
+            let error_type = if crate::cashu::is_token_spent_error(&e) {
+                "token_already_spent"
+            } else {
+                "token_redeem_failed"
+            };
+            let err_msg = format!("Failed to redeem Cashu token: {}", e);
+            warn!("{}", err_msg);
+            nostr.send_error_response(
+                requester_pubkey,
+                error_type,
+                &err_msg,
+                None,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Mint fees may reduce what we actually received; bill the duration on that
+    let duration_secs = received_msats / spec.rate_msats_per_sec;
+    if duration_secs < config.minimum_duration_seconds {
+        let err_msg = format!(
+            "Token redeemed for {} msats after mint fees, below the minimum of {} msats",
+            received_msats,
+            config.minimum_duration_seconds * spec.rate_msats_per_sec
+        );
+        warn!("{}", err_msg);
+        send_error_with_refund(
+            nostr,
+            payments,
+            requester_pubkey,
+            "insufficient_payment",
+            &err_msg,
+            &request.cashu_token,
+            received_msats,
+            message_type,
+        ).await?;
+        return Ok(());
+    }
+
+    info!("Redeemed {} msats for {}s on tier {}", received_msats, duration_secs, spec.name);
+
+    // 6. Generate credentials
     let password = crate::sidecar_service::SidecarState::generate_password();
     
-    // Calculate host port for forwarding
-    let host_port = match config.ssh_port_start {
-        Some(start) => start + (id - config.vmid_range_start) as u16,
-        None => 30000 + (id % 10000) as u16,
+    // Reserve a host port for SSH forwarding
+    let host_port = match ports.lock().await.allocate() {
+        Some(port) => port,
+        None => {
+            let err_msg = "No free SSH ports left on this provider";
+            error!("{}", err_msg);
+            send_error_with_refund(
+                nostr,
+                payments,
+                requester_pubkey,
+                "no_ports_available",
+                err_msg,
+                &request.cashu_token,
+                received_msats,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
     };
 
-    // 6. Create Container
+    // 7. Create Container
     let container_config = ContainerConfig {
         id,
+        kind: spec.kind,
         name: format!("paygress-{}", id),
-        image: request.pod_image.clone(),
+        image,
         cpu_cores: (spec.cpu_millicores / 1000).max(1) as u32,
         memory_mb: spec.memory_mb as u32,
-        storage_gb: 10, // Default 10GB
+        storage_gb: spec.storage_gb as u32,
+        disk_iops: spec.disk_iops.map(|iops| iops as u32),
         password: password.clone(),
-        ssh_key: None,
+        ssh_public_keys: request.ssh_public_keys.clone(),
+        disable_password_auth: request.disable_password_auth,
         host_port: Some(host_port),
+        duration_secs,
+        network: spec.network.clone(),
     };
 
     debug!("Calling backend.create_container for workload {}", id);
     if let Err(e) = backend.create_container(&container_config).await {
         let err_msg = format!("Backend failed to create workload: {}", e);
         error!("{}", err_msg);
-        nostr.send_error_response(
+        // Creation can fail after the workload is already running (port forward, SSH setup),
+        // so remove whatever exists before its port is handed out again
+        if let Err(e) = backend.delete_container(id, spec.kind).await {
+            warn!("Failed to clean up partly created workload {}: {}", id, e);
+        }
+        ports.lock().await.release(host_port);
+        // Nothing was delivered, so hand the whole payment back
+        send_error_with_refund(
+            nostr,
+            payments,
             requester_pubkey,
             "backend_error",
             &err_msg,
-            None,
+            &request.cashu_token,
+            received_msats,
             message_type,
         ).await?;
         return Ok(());
     }
     debug!("Successfully created container {}", id);
 
+    // Return whatever doesn't buy a whole second at this tier's rate
+    let change_msats = received_msats - duration_secs * spec.rate_msats_per_sec;
+    let refund_token = payments.refund_or_log(&request.cashu_token, change_msats).await;
+
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs();
 
-    // 7. Track Workload
+    // 8. Track Workload
     let workload = WorkloadInfo {
         vmid: id,
-        workload_type: "lxc".to_string(), // Default for Proxmox/LXD
+        workload_type: spec.kind,
         spec_id: spec.id.clone(),
         created_at: now,
         expires_at: now + duration_secs,
         owner_npub: requester_pubkey.to_string(),
+        payment_mint,
+        payment_unit,
+        host_port,
+        rate_msats_per_sec: spec.rate_msats_per_sec,
+        received_msats: duration_secs * spec.rate_msats_per_sec,
     };
 
     workloads.lock().await.insert(id, workload.clone());
+    drop(reservation);
+    if let Err(e) = ledger.save_workload(&workload) {
+        warn!("Failed to persist workload {}: {}", id, e);
+    }
+    record_payment(ledger, id, "spawn", received_msats, &workload.payment_mint);
+    if refund_token.is_some() {
+        record_payment(ledger, id, "refund", change_msats, &workload.payment_mint);
+    }
     
     // Update stats
     {
         let mut s = stats.lock().await;
         s.total_jobs_completed += 1;
+        persist_jobs_completed(ledger, s.total_jobs_completed);
     }
 
-    // 8. Get Access Details
-    // Use configured public IP/host
-    let host = &config.public_ip;
+    // 9. Get Access Details
+    let host = ssh_host_for(backend, config, id, spec.kind).await;
     
     // Send access details
     let expires_dt = chrono::DateTime::from_timestamp(workload.expires_at as i64, 0).unwrap_or_default();
@@ -607,11 +1176,19 @@ async fn handle_spawn_request(
         instructions: vec![
             format!("🚀 Workload provisioned successfully!"),
             format!("👤 Username: root"),
-            format!("🔑 Password: {}", password),
+            if request.disable_password_auth {
+                format!("🔑 Login: SSH key only ({} key(s) installed)", request.ssh_public_keys.len())
+            } else {
+                format!("🔑 Password: {}", password)
+            },
             format!("⌛ Expires: {}", expires_dt.format("%Y-%m-%d %H:%M:%S UTC")),
-            format!("Access: You can connect to the container using SSH."),
+            format!("Access: You can connect to the {} using SSH.", match spec.kind {
+                WorkloadKind::Container => "container",
+                WorkloadKind::Vm => "VM",
+            }),
             format!("  ssh -p {} root@{}", host_port, host),
         ],
+        refund_token,
     };
 
     debug!("Sending access details to {}", requester_pubkey);
@@ -627,11 +1204,407 @@ async fn handle_spawn_request(
     Ok(())
 }
 
+/// Send an error response carrying a refund of `refund_msats` from an already redeemed token
+#[allow(clippy::too_many_arguments)]
+async fn send_error_with_refund(
+    nostr: &dyn ProviderTransport,
+    payments: &dyn PaymentProcessor,
+    requester_pubkey: &str,
+    error_type: &str,
+    message: &str,
+    paid_token: &str,
+    refund_msats: u64,
+    message_type: &str,
+) -> Result<()> {
+    let refund_token = payments.refund_or_log(paid_token, refund_msats).await;
+    let details = if refund_token.is_some() {
+        Some(format!("Refunded {} msats in refund_token", refund_msats))
+    } else {
+        None
+    };
+
+    let error = ErrorResponseContent {
+        error_type: error_type.to_string(),
+        message: message.to_string(),
+        details,
+        accepted_mints: vec![],
+        refund_token,
+    };
+    nostr.send_error_response_private_message(requester_pubkey, error, message_type).await?;
+    Ok(())
+}
+
+/// Append a payment to the ledger. Logged rather than fatal: the payment itself already happened.
+fn record_payment(ledger: &WorkloadLedger, vmid: u32, kind: &str, amount_msats: u64, mint_url: &str) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let payment = PaymentRecord {
+        vmid,
+        kind: kind.to_string(),
+        amount_msats,
+        mint_url: mint_url.to_string(),
+        timestamp,
+    };
+    if let Err(e) = ledger.record_payment(&payment) {
+        warn!("Failed to record {} payment for workload {}: {}", kind, vmid, e);
+    }
+}
+
+fn persist_jobs_completed(ledger: &WorkloadLedger, value: u64) {
+    if let Err(e) = ledger.set_stat(STAT_JOBS_COMPLETED, value) {
+        warn!("Failed to persist stats: {}", e);
+    }
+}
+
+/// Rebuild the port allocator from the ledger. Workloads recorded before ports were
+/// stored get the port the old fixed formula gave them, which is what they were forwarded on.
+fn restore_host_ports(
+    config: &ProviderConfig,
+    ledger: &WorkloadLedger,
+    workloads: &mut HashMap<u32, WorkloadInfo>,
+) -> HostPortAllocator {
+    let mut ports = HostPortAllocator::from_config(config);
+
+    for workload in workloads.values_mut() {
+        if workload.host_port == 0 {
+            let legacy = match config.ssh_port_start {
+                Some(start) => start as u32 + workload.vmid.saturating_sub(config.vmid_range_start),
+                None => 30000 + workload.vmid % 10000,
+            };
+            workload.host_port = u16::try_from(legacy).unwrap_or(0);
+            if let Err(e) = ledger.save_workload(workload) {
+                warn!("Failed to persist host port of workload {}: {}", workload.vmid, e);
+            }
+        }
+        ports.mark_allocated(workload.host_port);
+    }
+
+    ports
+}
+
+/// Parse a workload ID as handed out in access details ("container-1000") or bare ("1000")
+fn parse_workload_id(pod_id: &str) -> Option<u32> {
+    pod_id.strip_prefix("container-").unwrap_or(pod_id).parse().ok()
+}
+
+/// Handle a top-up request: redeem the payment and extend the workload's expiry
+#[allow(clippy::too_many_arguments)]
+async fn handle_topup_request(
+    backend: &dyn ComputeBackend,
+    config: &ProviderConfig,
+    nostr: &dyn ProviderTransport,
+    payments: &dyn PaymentProcessor,
+    workloads: &Arc<Mutex<HashMap<u32, WorkloadInfo>>>,
+    ledger: &WorkloadLedger,
+    requester_pubkey: &str,
+    message_type: &str,
+    request: EncryptedTopUpPodRequest,
+) -> Result<()> {
+    info!("Processing topup request for pod {} from {}", request.pod_npub, requester_pubkey);
+
+    // 1. Find the workload and the rate it was sold at (older ledgers: its tier's current rate)
+    let vmid = parse_workload_id(&request.pod_npub);
+    let found = match vmid {
+        Some(vmid) => workloads.lock().await.get(&vmid)
+            .map(|w| (w.workload_type, w.rate_msats_per_sec, w.spec_id.clone())),
+        None => None,
+    };
+    let billing = found.and_then(|(kind, rate, spec_id)| {
+        let rate = Some(rate)
+            .filter(|rate| *rate > 0)
+            .or_else(|| config.specs.iter().find(|s| s.id == spec_id).map(|s| s.rate_msats_per_sec))
+            .filter(|rate| *rate > 0)?;
+        Some((kind, rate))
+    });
+    let (vmid, kind, rate) = match (vmid, billing) {
+        (Some(vmid), Some((kind, rate))) => (vmid, kind, rate),
+        _ => {
+            let err_msg = format!("Workload {} not found or already expired", request.pod_npub);
+            warn!("{}", err_msg);
+            nostr.send_error_response(
+                requester_pubkey,
+                "not_found",
+                &err_msg,
+                None,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // 2. Only accept tokens from mints we trust to honour the swap
+    if let Err(rejection) = payments.check_token_mint(&request.cashu_token, &config.whitelisted_mints) {
+        nostr.send_error_response_private_message(requester_pubkey, rejection, message_type).await?;
+        return Ok(());
+    }
+    let (payment_mint, _) = match payments.token_source(&request.cashu_token) {
+        Ok(source) => source,
+        Err(e) => {
+            nostr.send_error_response(requester_pubkey, "invalid_token", &e.to_string(), None, message_type).await?;
+            return Ok(());
+        }
+    };
+
+    // 3. Redeem the token at its mint
+    let received_msats = match payments.receive_token(&request.cashu_token).await {
+        Ok(v) => v,
+        Err(e) => {
+            let error_type = if crate::cashu::is_token_spent_error(&e) {
+                "token_already_spent"
+            } else {
+                "token_redeem_failed"
+            };
+            let err_msg = format!("Failed to redeem Cashu token: {}", e);
+            warn!("{}", err_msg);
+            nostr.send_error_response(
+                requester_pubkey,
+                error_type,
+                &err_msg,
+                None,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let extension_secs = received_msats / rate;
+    if extension_secs == 0 {
+        let err_msg = format!(
+            "Token redeemed for {} msats, less than one second at {} msats/sec",
+            received_msats, rate
+        );
+        warn!("{}", err_msg);
+        send_error_with_refund(
+            nostr,
+            payments,
+            requester_pubkey,
+            "insufficient_payment",
+            &err_msg,
+            &request.cashu_token,
+            received_msats,
+            message_type,
+        ).await?;
+        return Ok(());
+    }
+
+    // Backends that enforce the expiry themselves (Kubernetes deadlines) must agree first
+    if let Err(e) = backend.extend_container(vmid, kind, extension_secs).await {
+        let err_msg = format!("Backend failed to extend workload: {}", e);
+        error!("{}", err_msg);
+        send_error_with_refund(
+            nostr,
+            payments,
+            requester_pubkey,
+            "backend_error",
+            &err_msg,
+            &request.cashu_token,
+            received_msats,
+            message_type,
+        ).await?;
+        return Ok(());
+    }
+
+    // 4. Extend the expiry; the cleanup loop reads it from the same map
+    let extended = {
+        let mut lock = workloads.lock().await;
+        lock.get_mut(&vmid).map(|w| {
+            w.expires_at += extension_secs;
+            w.rate_msats_per_sec = rate;
+            w.received_msats += extension_secs * rate;
+            w.clone()
+        })
+    };
+
+    let workload = match extended {
+        Some(w) => w,
+        None => {
+            // Expired and cleaned up while we were redeeming
+            let err_msg = format!("Workload {} expired before the top-up completed", request.pod_npub);
+            warn!("{}", err_msg);
+            send_error_with_refund(
+                nostr,
+                payments,
+                requester_pubkey,
+                "not_found",
+                &err_msg,
+                &request.cashu_token,
+                received_msats,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = ledger.save_workload(&workload) {
+        warn!("Failed to persist workload {}: {}", vmid, e);
+    }
+    record_payment(ledger, vmid, "topup", received_msats, &payment_mint);
+
+    let change_msats = received_msats - extension_secs * rate;
+    let refund_token = payments.refund_or_log(&request.cashu_token, change_msats).await;
+    if refund_token.is_some() {
+        record_payment(ledger, vmid, "refund", change_msats, &payment_mint);
+    }
+
+    info!("Workload {} extended by {}s", vmid, extension_secs);
+
+    let expires_dt = chrono::DateTime::from_timestamp(workload.expires_at as i64, 0).unwrap_or_default();
+    nostr.send_topup_response_private_message(
+        requester_pubkey,
+        TopUpResponseContent {
+            success: true,
+            pod_npub: format!("container-{}", vmid),
+            extended_duration_seconds: extension_secs,
+            new_expires_at: expires_dt.to_rfc3339(),
+            message: format!("Workload extended by {} seconds", extension_secs),
+            refund_token,
+        },
+        message_type,
+    ).await?;
+
+    Ok(())
+}
+
+/// Handle a terminate request: delete the workload and refund its unused seconds
+#[allow(clippy::too_many_arguments)]
+async fn handle_terminate_request(
+    backend: &dyn ComputeBackend,
+    nostr: &dyn ProviderTransport,
+    payments: &dyn PaymentProcessor,
+    workloads: &Arc<Mutex<HashMap<u32, WorkloadInfo>>>,
+    ledger: &WorkloadLedger,
+    ports: &Mutex<HostPortAllocator>,
+    requester_pubkey: &str,
+    message_type: &str,
+    request: TerminateRequestContent,
+) -> Result<()> {
+    info!("Processing terminate request for pod {} from {}", request.pod_id, requester_pubkey);
+
+    if !request.terminate {
+        nostr.send_error_response(
+            requester_pubkey,
+            "invalid_request",
+            "Terminate request must set \"terminate\": true",
+            None,
+            message_type,
+        ).await?;
+        return Ok(());
+    }
+
+    // Only the owner may terminate; remove it from tracking first so cleanup can't race us
+    let workload = {
+        let mut lock = workloads.lock().await;
+        match parse_workload_id(&request.pod_id) {
+            Some(vmid) if lock.get(&vmid).is_some_and(|w| w.owner_npub == requester_pubkey) => lock.remove(&vmid),
+            _ => None,
+        }
+    };
+
+    let workload = match workload {
+        Some(w) => w,
+        None => {
+            let err_msg = format!("Workload {} not found or you don't have access", request.pod_id);
+            warn!("{}", err_msg);
+            nostr.send_error_response(
+                requester_pubkey,
+                "not_found",
+                &err_msg,
+                None,
+                message_type,
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    // Stop may fail if the container is already stopped; delete is what matters
+    if let Err(e) = backend.stop_container(workload.vmid, workload.workload_type).await {
+        debug!("Stop before delete failed for {}: {}", workload.vmid, e);
+    }
+    if let Err(e) = backend.delete_container(workload.vmid, workload.workload_type).await {
+        let err_msg = format!("Failed to delete workload {}: {}", workload.vmid, e);
+        error!("{}", err_msg);
+        // Put it back so the cleanup loop still reaps it at expiry
+        workloads.lock().await.insert(workload.vmid, workload);
+        nostr.send_error_response(
+            requester_pubkey,
+            "backend_error",
+            &err_msg,
+            None,
+            message_type,
+        ).await?;
+        return Ok(());
+    }
+    ports.lock().await.release(workload.host_port);
+
+    // Pro-rate the refund at the rate the workload was paid at for every whole second left,
+    // never beyond what was redeemed for it (older ledgers recorded neither, so get nothing)
+    let unused_seconds = workload.expires_at.saturating_sub(now);
+    let refund_msats = unused_seconds
+        .saturating_mul(workload.rate_msats_per_sec)
+        .min(workload.received_msats);
+    let refund_token = payments.refund_at_mint_or_log(
+        &workload.payment_mint,
+        &workload.payment_unit,
+        refund_msats,
+    ).await;
+
+    if let Err(e) = ledger.remove_workload(workload.vmid) {
+        warn!("Failed to remove workload {} from ledger: {}", workload.vmid, e);
+    }
+    if refund_token.is_some() {
+        record_payment(ledger, workload.vmid, "refund", refund_msats, &workload.payment_mint);
+    }
+
+    let message = if refund_token.is_some() {
+        format!("Workload terminated. Refunded {} unused seconds ({} msats).", unused_seconds, refund_msats)
+    } else if refund_msats > 0 {
+        format!("Workload terminated. The {} msats refund could not be issued; contact the provider.", refund_msats)
+    } else {
+        "Workload terminated.".to_string()
+    };
+
+    info!("Workload {} terminated early by owner ({}s unused)", workload.vmid, unused_seconds);
+
+    nostr.send_terminate_response(
+        requester_pubkey,
+        TerminateResponseContent {
+            pod_id: format!("container-{}", workload.vmid),
+            terminated: true,
+            unused_seconds,
+            refund_msats,
+            refund_token,
+            message,
+        },
+        message_type,
+    ).await?;
+
+    Ok(())
+}
+
+/// Host users SSH to: the backend's external address for the workload, or the public IP
+async fn ssh_host_for(backend: &dyn ComputeBackend, config: &ProviderConfig, id: u32, kind: WorkloadKind) -> String {
+    match backend.get_external_host(id, kind).await {
+        Ok(Some(host)) => host,
+        Ok(None) => config.public_ip.clone(),
+        Err(e) => {
+            warn!("Failed to look up the external address of workload {}: {}", id, e);
+            config.public_ip.clone()
+        }
+    }
+}
+
 /// Handle a status request
 async fn handle_status_request(
     backend: &dyn ComputeBackend,
     config: &ProviderConfig,
-    nostr: &NostrRelaySubscriber,
+    nostr: &dyn ProviderTransport,
     workloads: &Arc<Mutex<HashMap<u32, WorkloadInfo>>>,
     requester_pubkey: &str,
     message_type: &str,
@@ -668,13 +1641,7 @@ async fn handle_status_request(
         }
     };
 
-    // 2. Check backend status
-    let status_info = match backend.get_node_status().await {
-        Ok(s) => s,
-        Err(_) => crate::compute::NodeStatus { cpu_usage: 0.0, memory_used: 0, memory_total: 0, disk_used: 0, disk_total: 0 },
-    };
-
-    // 3. Prepare response
+    // 2. Prepare response
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs();
@@ -689,11 +1656,6 @@ async fn handle_status_request(
         .find(|s| s.id == workload.spec_id);
     let cpu = spec.map(|s| s.cpu_millicores).unwrap_or(1000);
     let mem = spec.map(|s| s.memory_mb).unwrap_or(1024);
-    let host_port = match config.ssh_port_start {
-        Some(start) => start + (workload.vmid - config.vmid_range_start) as u16,
-        None => (30000 + (workload.vmid % 10000)) as u16,
-    };
-
     let response = StatusResponseContent {
         pod_id: workload.vmid.to_string(),
         status: status.to_string(),
@@ -701,8 +1663,8 @@ async fn handle_status_request(
         time_remaining_seconds: time_remaining,
         cpu_millicores: cpu,
         memory_mb: mem,
-        ssh_host: config.public_ip.clone(),
-        ssh_port: host_port,
+        ssh_host: ssh_host_for(backend, config, workload.vmid, workload.workload_type).await,
+        ssh_port: workload.host_port,
         ssh_username: "root".to_string(),
     };
 
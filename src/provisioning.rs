@@ -0,0 +1,266 @@
+// Workload Provisioning
+//
+// Scripts that set up SSH inside workloads, and the checks on the credentials
+// they install. The scripts are constants: usernames, passwords and keys reach
+// them through environment variables (or stdin for the lxc CLI), never by being
+// spliced into shell source.
+
+use std::fmt;
+
+use crate::nostr::ErrorResponseContent;
+
+// Environment variables the scripts below read
+pub const ENV_SSH_PORT: &str = "PAYGRESS_SSH_PORT";
+pub const ENV_SSH_USER: &str = "PAYGRESS_SSH_USER";
+pub const ENV_SSH_PASSWORD: &str = "PAYGRESS_SSH_PASSWORD";
+pub const ENV_SSH_KEYS: &str = "PAYGRESS_SSH_KEYS";
+pub const ENV_KEY_ONLY: &str = "PAYGRESS_SSH_KEY_ONLY";
+
+// useradd's default limit, and what most distros accept
+const MAX_USERNAME_LEN: usize = 32;
+
+/// A spawn request rejected before anything was provisioned, reported as "invalid_request"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRequest(pub String);
+
+impl InvalidRequest {
+    pub const ERROR_TYPE: &'static str = "invalid_request";
+
+    pub fn to_error_response(&self) -> ErrorResponseContent {
+        ErrorResponseContent {
+            error_type: Self::ERROR_TYPE.to_string(),
+            message: self.0.clone(),
+            details: None,
+            accepted_mints: vec![],
+            refund_token: None,
+        }
+    }
+}
+
+impl fmt::Display for InvalidRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidRequest {}
+
+/// Check `username` against the portable POSIX pattern `[a-z_][a-z0-9_-]*`, at most 32 characters
+pub fn validate_username(username: &str) -> Result<(), InvalidRequest> {
+    let mut chars = username.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+        && username.len() <= MAX_USERNAME_LEN;
+
+    if !valid {
+        return Err(InvalidRequest(format!(
+            "Invalid SSH username '{}': use up to {} lowercase letters, digits, '_' or '-', starting with a letter or '_'",
+            username.escape_debug(),
+            MAX_USERNAME_LEN,
+        )));
+    }
+    Ok(())
+}
+
+/// chpasswd reads one `user:password` line, so passwords can't contain control characters
+pub fn validate_password(password: &str) -> Result<(), InvalidRequest> {
+    if password.chars().any(|c| c.is_control()) {
+        return Err(InvalidRequest("SSH password must not contain control characters".to_string()));
+    }
+    Ok(())
+}
+
+/// Validate the SSH credentials of a spawn request
+pub fn validate_credentials(username: &str, password: &str) -> Result<(), InvalidRequest> {
+    validate_username(username)?;
+    validate_password(password)
+}
+
+/// Environment for SSH_BOOTSTRAP_SCRIPT
+pub fn ssh_bootstrap_env(
+    ssh_port: u16,
+    username: &str,
+    password: &str,
+    ssh_public_keys: &[String],
+    disable_password_auth: bool,
+) -> Vec<(&'static str, String)> {
+    vec![
+        (ENV_SSH_PORT, ssh_port.to_string()),
+        (ENV_SSH_USER, username.to_string()),
+        (ENV_SSH_PASSWORD, password.to_string()),
+        (ENV_SSH_KEYS, authorized_keys(ssh_public_keys)),
+        (ENV_KEY_ONLY, disable_password_auth.to_string()),
+    ]
+}
+
+/// Contents of authorized_keys, for ENV_SSH_KEYS
+pub fn authorized_keys(ssh_public_keys: &[String]) -> String {
+    ssh_public_keys.iter()
+        .map(|key| key.trim())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Installs and starts sshd on any common base image (apt, apk or yum), creates the
+/// user from ssh_bootstrap_env, then keeps the container alive.
+/// Used as the container command for K8s pods and Docker containers.
+pub const SSH_BOOTSTRAP_SCRIPT: &str = r#"set -e
+SSH_USER="$PAYGRESS_SSH_USER"
+echo "Setting up SSH access on port $PAYGRESS_SSH_PORT..."
+
+# Detect package manager and install OpenSSH if not present
+if command -v apt-get >/dev/null 2>&1; then
+    export DEBIAN_FRONTEND=noninteractive
+    apt-get update -qq && apt-get install -y -qq openssh-server sudo 2>/dev/null || true
+    mkdir -p /run/sshd
+elif command -v apk >/dev/null 2>&1; then
+    apk add --no-cache openssh sudo 2>/dev/null || true
+    ssh-keygen -A 2>/dev/null || true
+elif command -v yum >/dev/null 2>&1; then
+    yum install -y openssh-server sudo 2>/dev/null || true
+fi
+
+# Detect available shell
+if [ -f /bin/bash ]; then
+    DEFAULT_SHELL="/bin/bash"
+else
+    DEFAULT_SHELL="/bin/sh"
+fi
+
+# Create user if it doesn't exist
+if ! id "$SSH_USER" >/dev/null 2>&1; then
+    useradd -m -s "$DEFAULT_SHELL" "$SSH_USER" 2>/dev/null || adduser -D -s "$DEFAULT_SHELL" "$SSH_USER" 2>/dev/null || true
+fi
+
+# Set password
+printf '%s:%s\n' "$SSH_USER" "$PAYGRESS_SSH_PASSWORD" | chpasswd 2>/dev/null || true
+
+# Add user to sudoers
+printf '%s ALL=(ALL) NOPASSWD:ALL\n' "$SSH_USER" > "/etc/sudoers.d/$SSH_USER" 2>/dev/null || true
+chmod 0440 "/etc/sudoers.d/$SSH_USER" 2>/dev/null || true
+
+# Install SSH public keys
+if [ -n "$PAYGRESS_SSH_KEYS" ]; then
+    USER_HOME=$(awk -F: -v user="$SSH_USER" '$1 == user { print $6 }' /etc/passwd)
+    [ -n "$USER_HOME" ] || USER_HOME="/home/$SSH_USER"
+    mkdir -p "$USER_HOME/.ssh"
+    printf '%s\n' "$PAYGRESS_SSH_KEYS" > "$USER_HOME/.ssh/authorized_keys"
+    chmod 700 "$USER_HOME/.ssh"
+    chmod 600 "$USER_HOME/.ssh/authorized_keys"
+    chown -R "$SSH_USER" "$USER_HOME/.ssh" 2>/dev/null || true
+fi
+
+if [ "$PAYGRESS_SSH_KEY_ONLY" = "true" ]; then
+    PASSWORD_AUTH="no"
+    ROOT_LOGIN="prohibit-password"
+else
+    PASSWORD_AUTH="yes"
+    ROOT_LOGIN="yes"
+fi
+
+# Configure SSH
+mkdir -p /etc/ssh
+cat > /etc/ssh/sshd_config <<EOF
+Port $PAYGRESS_SSH_PORT
+ListenAddress 0.0.0.0
+PermitRootLogin $ROOT_LOGIN
+PasswordAuthentication $PASSWORD_AUTH
+PubkeyAuthentication yes
+UseDNS no
+X11Forwarding yes
+PrintMotd no
+AcceptEnv LANG LC_*
+Subsystem sftp internal-sftp
+EOF
+
+# Start SSH daemon
+if command -v sshd >/dev/null 2>&1; then
+    # Use absolute path to sshd if possible
+    SSHD_BIN=$(command -v sshd)
+    $SSHD_BIN -f /etc/ssh/sshd_config -D &
+    echo "SSH server started on port $PAYGRESS_SSH_PORT"
+else
+    echo "Warning: sshd not found"
+fi
+
+echo "Container ready. SSH on port $PAYGRESS_SSH_PORT, user: $SSH_USER (shell: $DEFAULT_SHELL)"
+tail -f /dev/null
+"#;
+
+// The LXD backends run the scripts below one at a time through exec
+
+/// Install/enable SSH on various distros (Alpine, Debian, etc) and allow root password login
+pub const SSH_SETUP_SCRIPT: &str = r#"
+    # Detect package manager and install SSH if missing
+    if command -v apk >/dev/null; then
+        # Alpine
+        apk add --no-cache openssh
+        rc-update add sshd default
+        service sshd start
+    elif command -v apt-get >/dev/null; then
+        # Debian/Ubuntu
+        # Usually installed, but ensure it runs
+        systemctl enable ssh
+        systemctl start ssh
+    fi
+
+    # Configure SSH for root access with password
+    # Check if config exists
+    if [ -f /etc/ssh/sshd_config ]; then
+        # Remove cloud-init config that disables password auth
+        rm -f /etc/ssh/sshd_config.d/*-cloudimg-settings.conf
+
+        sed -i 's/#PermitRootLogin.*/PermitRootLogin yes/' /etc/ssh/sshd_config
+        sed -i 's/PermitRootLogin.*/PermitRootLogin yes/' /etc/ssh/sshd_config
+        sed -i 's/PasswordAuthentication no/PasswordAuthentication yes/' /etc/ssh/sshd_config
+
+        # Restart service
+        service sshd restart || systemctl restart ssh || systemctl restart sshd
+    fi
+"#;
+
+/// Switch sshd to key-only login
+pub const KEY_ONLY_SSH_SCRIPT: &str = r#"
+    sed -i 's/^#\?PasswordAuthentication.*/PasswordAuthentication no/' /etc/ssh/sshd_config
+    sed -i 's/^#\?PermitRootLogin.*/PermitRootLogin prohibit-password/' /etc/ssh/sshd_config
+    service sshd restart || systemctl restart ssh || systemctl restart sshd
+"#;
+
+/// Set root's password to ENV_SSH_PASSWORD
+pub const ROOT_PASSWORD_SCRIPT: &str = r#"printf 'root:%s\n' "$PAYGRESS_SSH_PASSWORD" | chpasswd"#;
+
+/// Write root's authorized_keys from ENV_SSH_KEYS
+pub const AUTHORIZED_KEYS_SCRIPT: &str = r#"mkdir -p /root/.ssh && chmod 700 /root/.ssh && printf '%s\n' "$PAYGRESS_SSH_KEYS" > /root/.ssh/authorized_keys && chmod 600 /root/.ssh/authorized_keys"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_username() {
+        for name in ["root", "user", "_svc", "dev-01", "a_b"] {
+            assert!(validate_username(name).is_ok(), "{} should be accepted", name);
+        }
+        for name in ["", "Root", "1user", "-user", "user name", "user;id", "$(id)", "a\nb", &"a".repeat(33)] {
+            assert!(validate_username(name).is_err(), "{:?} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn test_validate_password() {
+        assert!(validate_password("").is_ok());
+        assert!(validate_password("p@ss'w\"ord $(id)").is_ok());
+        assert!(validate_password("line\nroot:owned").is_err());
+    }
+
+    #[test]
+    fn test_scripts_take_credentials_from_env() {
+        let env = ssh_bootstrap_env(22, "user", "secret", &["ssh-ed25519 AAAA a@b".to_string()], true);
+        for (name, _) in &env {
+            assert!(SSH_BOOTSTRAP_SCRIPT.contains(name), "bootstrap script doesn't read {}", name);
+        }
+        assert!(env.contains(&(ENV_SSH_KEYS, "ssh-ed25519 AAAA a@b".to_string())));
+        assert!(ROOT_PASSWORD_SCRIPT.contains(ENV_SSH_PASSWORD));
+        assert!(AUTHORIZED_KEYS_SCRIPT.contains(ENV_SSH_KEYS));
+    }
+}
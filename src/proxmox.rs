@@ -29,16 +29,10 @@ pub struct LxcConfig {
     pub password: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ssh_public_keys: Option<String>,
-    #[serde(default = "default_true")]
     pub start: bool,
-    #[serde(default = "default_true")]
     pub unprivileged: bool,
 }
 
-fn default_true() -> bool {
-    true
-}
-
 /// Configuration for creating a VM
 #[derive(Debug, Clone, Serialize)]
 pub struct VmConfig {
@@ -51,10 +45,71 @@ pub struct VmConfig {
     pub scsi0: String,     // Disk
     pub net0: String,
     pub ostype: String,
-    #[serde(default = "default_true")]
     pub start: bool,
 }
 
+/// Parameters for cloning a VM from a (cloud-init) template
+#[derive(Debug, Clone, Serialize)]
+pub struct VmCloneConfig {
+    pub newid: u32,
+    pub name: String,
+    pub full: u8, // 1 = full clone (independent of the template)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+}
+
+/// Cloud-init and sizing applied to a cloned VM before first boot
+#[derive(Debug, Clone, Serialize)]
+pub struct VmCloudInitConfig {
+    pub memory: u32,
+    pub cores: u32,
+    pub agent: u8, // 1 = enable the QEMU guest agent channel (used for IP discovery)
+    pub net0: String,
+    pub ipconfig0: String,
+    pub ciuser: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cipassword: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sshkeys: Option<String>, // Must be URL-encoded (see encode_sshkeys)
+}
+
+/// A rule in a guest's firewall; rules match in `pos` order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FirewallRule {
+    #[serde(rename = "type")]
+    pub direction: String, // "in" or "out"
+    pub action: String, // ACCEPT, REJECT or DROP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest: Option<String>, // Comma-separated networks of one address family
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proto: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dport: Option<String>,
+    pub pos: u32,
+    pub enable: u8,
+}
+
+/// Guest firewall options
+#[derive(Debug, Clone, Serialize)]
+pub struct FirewallOptions {
+    pub enable: u8,
+    pub dhcp: u8, // 1 = let DHCP through whatever the policies say
+    pub policy_in: String,
+    pub policy_out: String,
+}
+
+/// Encode authorized_keys content the way Proxmox expects for `sshkeys`
+/// (percent-encoded, with spaces as %20 rather than '+')
+pub fn encode_sshkeys(keys: &[String]) -> String {
+    keys.join("\n")
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
 /// Node status information
 #[derive(Debug, Clone, Deserialize)]
 pub struct NodeStatus {
@@ -63,6 +118,22 @@ pub struct NodeStatus {
     pub uptime: u64,
     #[serde(default)]
     pub loadavg: Vec<f64>,
+    #[serde(default)]
+    pub cpuinfo: Option<CpuInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CpuInfo {
+    pub cpus: u32, // Logical CPUs
+}
+
+/// Storage usage in bytes
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StorageStatus {
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub used: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -88,12 +159,39 @@ pub struct WorkloadStatus {
     pub maxmem: u64,
 }
 
+/// Network interface of a running LXC container (`/lxc/{vmid}/interfaces`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct LxcInterface {
+    pub name: String,
+    #[serde(default)]
+    pub inet: Option<String>, // e.g. "192.168.1.50/24"
+}
+
+/// Network interface reported by the QEMU guest agent
+#[derive(Debug, Clone, Deserialize)]
+pub struct AgentInterface {
+    pub name: String,
+    #[serde(default, rename = "ip-addresses")]
+    pub ip_addresses: Vec<AgentIpAddress>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AgentIpAddress {
+    #[serde(rename = "ip-address")]
+    pub ip_address: String,
+    #[serde(rename = "ip-address-type")]
+    pub ip_address_type: String, // "ipv4" or "ipv6"
+}
+
+#[derive(Debug, Deserialize)]
+struct AgentResult<T> {
+    result: T,
+}
+
 /// Proxmox API response wrapper
 #[derive(Debug, Deserialize)]
 struct ProxmoxResponse<T> {
     data: Option<T>,
-    #[serde(default)]
-    errors: Option<serde_json::Value>,
 }
 
 /// Task response from Proxmox (for async operations)
@@ -260,6 +358,29 @@ impl ProxmoxClient {
         resp.data.context("No status data returned")
     }
 
+    /// Network interfaces of a running LXC container
+    pub async fn get_lxc_interfaces(&self, vmid: u32) -> Result<Vec<LxcInterface>> {
+        let url = format!("{}/lxc/{}/interfaces", self.node_url(), vmid);
+
+        let response = self.client
+            .get(&url)
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .send()
+            .await
+            .context("Failed to get LXC interfaces")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to get interfaces of LXC {}: {} - {}", vmid, status, body);
+        }
+
+        let resp: ProxmoxResponse<Vec<LxcInterface>> = response.json().await
+            .context("Failed to parse LXC interfaces response")?;
+
+        Ok(resp.data.unwrap_or_default())
+    }
+
     /// List all LXC containers on the node
     pub async fn list_lxc(&self) -> Result<Vec<WorkloadStatus>> {
         let url = format!("{}/lxc", self.node_url());
@@ -313,6 +434,84 @@ impl ProxmoxClient {
         Ok(task.data)
     }
 
+    /// Clone a VM template into a new VM
+    pub async fn clone_vm(&self, template_vmid: u32, config: &VmCloneConfig) -> Result<String> {
+        let url = format!("{}/qemu/{}/clone", self.node_url(), template_vmid);
+
+        info!("Cloning VM template {} to {} on node {}", template_vmid, config.newid, self.node);
+
+        let response = self.client
+            .post(&url)
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .form(config)
+            .send()
+            .await
+            .context("Failed to send clone VM request")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            error!("Failed to clone VM: {} - {}", status, body);
+            anyhow::bail!("Proxmox API error: {} - {}", status, body);
+        }
+
+        let task: TaskResponse = response.json().await
+            .context("Failed to parse clone VM response")?;
+
+        info!("VM clone task started: {}", task.data);
+        Ok(task.data)
+    }
+
+    /// Apply cloud-init settings and sizing to a VM (synchronous)
+    pub async fn configure_vm(&self, vmid: u32, config: &VmCloudInitConfig) -> Result<()> {
+        let url = format!("{}/qemu/{}/config", self.node_url(), vmid);
+
+        info!("Configuring VM {}", vmid);
+
+        let response = self.client
+            .put(&url)
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .form(config)
+            .send()
+            .await
+            .context("Failed to send configure VM request")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to configure VM {}: {} - {}", vmid, status, body);
+        }
+
+        Ok(())
+    }
+
+    /// Grow a VM disk to `size` (e.g. "20G"). Newer Proxmox versions run this as
+    /// a task and return its UPID; older ones resize synchronously.
+    pub async fn resize_vm_disk(&self, vmid: u32, disk: &str, size: &str) -> Result<Option<String>> {
+        let url = format!("{}/qemu/{}/resize", self.node_url(), vmid);
+
+        info!("Resizing disk {} of VM {} to {}", disk, vmid, size);
+
+        let response = self.client
+            .put(&url)
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .form(&[("disk", disk), ("size", size)])
+            .send()
+            .await
+            .context("Failed to send resize disk request")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to resize disk of VM {}: {} - {}", vmid, status, body);
+        }
+
+        let resp: ProxmoxResponse<String> = response.json().await
+            .context("Failed to parse resize disk response")?;
+
+        Ok(resp.data)
+    }
+
     /// Start a VM
     pub async fn start_vm(&self, vmid: u32) -> Result<String> {
         let url = format!("{}/qemu/{}/status/start", self.node_url(), vmid);
@@ -411,6 +610,29 @@ impl ProxmoxClient {
         resp.data.context("No status data returned")
     }
 
+    /// Network interfaces of a VM, as reported by its QEMU guest agent
+    pub async fn get_vm_agent_interfaces(&self, vmid: u32) -> Result<Vec<AgentInterface>> {
+        let url = format!("{}/qemu/{}/agent/network-get-interfaces", self.node_url(), vmid);
+
+        let response = self.client
+            .get(&url)
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .send()
+            .await
+            .context("Failed to query VM guest agent")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Guest agent of VM {} not available: {} - {}", vmid, status, body);
+        }
+
+        let resp: ProxmoxResponse<AgentResult<Vec<AgentInterface>>> = response.json().await
+            .context("Failed to parse guest agent interfaces response")?;
+
+        Ok(resp.data.map(|d| d.result).unwrap_or_default())
+    }
+
     /// List all VMs on the node
     pub async fn list_vm(&self) -> Result<Vec<WorkloadStatus>> {
         let url = format!("{}/qemu", self.node_url());
@@ -459,6 +681,29 @@ impl ProxmoxClient {
         resp.data.context("No node status data returned")
     }
 
+    /// Get the size and usage of a storage on this node
+    pub async fn get_storage_status(&self, storage: &str) -> Result<StorageStatus> {
+        let url = format!("{}/storage/{}/status", self.node_url(), storage);
+
+        let response = self.client
+            .get(&url)
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .send()
+            .await
+            .context("Failed to get storage status")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to get storage status: {} - {}", status, body);
+        }
+
+        let resp: ProxmoxResponse<StorageStatus> = response.json().await
+            .context("Failed to parse storage status response")?;
+
+        resp.data.context("No storage status data returned")
+    }
+
     /// Find the next available VMID in a given range
     pub async fn find_available_vmid(&self, range_start: u32, range_end: u32) -> Result<u32> {
         let lxc_list = self.list_lxc().await?;
@@ -478,6 +723,48 @@ impl ProxmoxClient {
         anyhow::bail!("No available VMID in range {}-{}", range_start, range_end)
     }
 
+    // ==================== Guest Firewall ====================
+
+    /// Enable a guest's firewall with `options` and add `rules`.
+    /// `guest` is "lxc" or "qemu"; the NIC also needs `firewall=1`.
+    pub async fn configure_firewall(&self, guest: &str, vmid: u32, options: &FirewallOptions, rules: &[FirewallRule]) -> Result<()> {
+        let base = format!("{}/{}/{}/firewall", self.node_url(), guest, vmid);
+
+        info!("Configuring firewall for {} {} ({} rules)", guest, vmid, rules.len());
+
+        let response = self.client
+            .put(format!("{}/options", base))
+            .header(header::AUTHORIZATION, &self.auth_header)
+            .form(options)
+            .send()
+            .await
+            .context("Failed to send firewall options request")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to set firewall options for {}: {} - {}", vmid, status, body);
+        }
+
+        for rule in rules {
+            let response = self.client
+                .post(format!("{}/rules", base))
+                .header(header::AUTHORIZATION, &self.auth_header)
+                .form(rule)
+                .send()
+                .await
+                .context("Failed to send firewall rule request")?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                anyhow::bail!("Failed to add firewall rule for {}: {} - {}", vmid, status, body);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Wait for a task to complete
     pub async fn wait_for_task(&self, upid: &str, timeout_secs: u64) -> Result<()> {
         use tokio::time::{sleep, Duration};
@@ -527,36 +814,56 @@ impl ProxmoxClient {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// ==================== ComputeBackend Implementation ====================
 
-    #[test]
-    fn test_lxc_config_serialization() {
-        let config = LxcConfig {
-            vmid: 100,
-            hostname: "test-container".to_string(),
-            ostemplate: "local:vztmpl/ubuntu-22.04-standard.tar.zst".to_string(),
-            storage: "local-lvm".to_string(),
-            rootfs: "local-lvm:8".to_string(),
-            memory: 1024,
-            cores: 1,
-            net0: "name=eth0,bridge=vmbr0,ip=dhcp".to_string(),
-            password: "testpass".to_string(),
-            ssh_public_keys: None,
-            start: true,
-            unprivileged: true,
-        };
+use async_trait::async_trait;
+use crate::compute::{ComputeBackend, ContainerConfig, NetworkPolicy, NodeStatus as ComputeNodeStatus, WorkloadKind};
+use crate::network_policy::DNS_PORT;
+use crate::images::{find_image, ImageEntry};
+use crate::port_forward::PortForwarder;
+
+/// Outbound firewall rules for a network policy. DNS is let through first, since
+/// guests usually get a resolver on the LAN from DHCP.
+fn firewall_rules(policy: &NetworkPolicy) -> Vec<FirewallRule> {
+    let rule = |action: &str, dest: Option<String>, proto: Option<&str>, dport: Option<u16>| FirewallRule {
+        direction: "out".to_string(),
+        action: action.to_string(),
+        dest,
+        proto: proto.map(str::to_string),
+        dport: dport.map(|port| port.to_string()),
+        pos: 0,
+        enable: 1,
+    };
+
+    let mut rules: Vec<FirewallRule> = policy.blocked_tcp_ports().into_iter()
+        .map(|port| rule("REJECT", None, Some("tcp"), Some(port)))
+        .collect();
+
+    let (ipv6, ipv4): (Vec<&str>, Vec<&str>) = policy.blocked_ranges().into_iter().partition(|range| range.contains(':'));
+    if !ipv4.is_empty() || !ipv6.is_empty() {
+        rules.push(rule("ACCEPT", None, Some("udp"), Some(DNS_PORT)));
+        rules.push(rule("ACCEPT", None, Some("tcp"), Some(DNS_PORT)));
+    }
+    // Proxmox doesn't allow mixing address families in one list
+    for ranges in [ipv4, ipv6] {
+        if !ranges.is_empty() {
+            rules.push(rule("REJECT", Some(ranges.join(",")), None, None));
+        }
+    }
 
-        // Should serialize without errors
-        let _serialized = serde_urlencoded::to_string(&config).unwrap();
+    for (pos, rule) in rules.iter_mut().enumerate() {
+        rule.pos = pos as u32;
     }
+    rules
 }
 
-// ==================== ComputeBackend Implementation ====================
-
-use async_trait::async_trait;
-use crate::compute::{ComputeBackend, ContainerConfig, NodeStatus as ComputeNodeStatus};
+/// net0 options enabling the guest firewall and any bandwidth cap (`rate` is in MB/s)
+fn net0_policy_options(policy: &NetworkPolicy) -> String {
+    match policy.bandwidth_mbit {
+        Some(mbit) => format!(",firewall=1,rate={}", mbit as f64 / 8.0),
+        None => ",firewall=1".to_string(),
+    }
+}
 
 /// Wrapper around ProxmoxClient to implement ComputeBackend trait
 pub struct ProxmoxBackend {
@@ -564,17 +871,127 @@ pub struct ProxmoxBackend {
     storage: String,
     bridge: String,
     template: String,
+    images: Vec<ImageEntry>,
+    port_forwarder: Option<PortForwarder>, // Set when the provider runs on the Proxmox node
 }
 
 impl ProxmoxBackend {
-    pub fn new(client: ProxmoxClient, storage: &str, bridge: &str, template: &str) -> Self {
+    pub fn new(client: ProxmoxClient, storage: &str, bridge: &str, template: &str, images: Vec<ImageEntry>) -> Self {
         Self {
             client,
             storage: storage.to_string(),
             bridge: bridge.to_string(),
             template: template.to_string(),
+            images,
+            port_forwarder: None,
         }
     }
+
+    /// Forward each workload's `host_port` to its SSH port with DNAT rules on this host
+    pub fn with_port_forwarding(mut self, forwarder: PortForwarder) -> Self {
+        self.port_forwarder = Some(forwarder);
+        self
+    }
+
+    /// Turn on the guest firewall with the spec's network policy, before first boot
+    async fn apply_network_policy(&self, guest: &str, config: &ContainerConfig) -> Result<()> {
+        let options = FirewallOptions {
+            enable: 1,
+            dhcp: 1,
+            policy_in: "ACCEPT".to_string(),
+            policy_out: if config.network.allows_egress() { "ACCEPT" } else { "REJECT" }.to_string(),
+        };
+        self.client.configure_firewall(guest, config.id, &options, &firewall_rules(&config.network)).await
+    }
+
+    /// Poll until the workload reports an IPv4 address (VMs need their guest agent up)
+    async fn wait_for_ip(&self, id: u32, kind: WorkloadKind, timeout_secs: u64) -> Result<String> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout_secs);
+        loop {
+            match self.get_container_ip(id, kind).await {
+                Ok(Some(ip)) => return Ok(ip),
+                Ok(None) => {}
+                Err(e) => tracing::debug!("IP of {} not available yet: {}", id, e),
+            }
+            if std::time::Instant::now() >= deadline {
+                anyhow::bail!("Workload {} reported no IPv4 address within {}s", id, timeout_secs);
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        }
+    }
+
+    /// Expose the workload's SSH port on `config.host_port`, if forwarding is enabled
+    async fn setup_port_forward(&self, config: &ContainerConfig) -> Result<()> {
+        let (Some(forwarder), Some(host_port)) = (&self.port_forwarder, config.host_port) else {
+            return Ok(());
+        };
+
+        let ip = self.wait_for_ip(config.id, config.kind, 180).await?;
+        forwarder.forward(config.id, host_port, &ip, 22)
+    }
+
+    /// vztmpl volume for a catalog image (the configured template if no image was requested)
+    fn resolve_template(&self, image: &str) -> Result<String> {
+        if image.is_empty() {
+            return Ok(self.template.clone());
+        }
+
+        find_image(&self.images, image)
+            .and_then(|entry| entry.proxmox_template.clone())
+            .ok_or_else(|| anyhow::anyhow!("Image '{}' has no Proxmox template in the catalog", image))
+    }
+
+    /// Clone the image's cloud-init template, inject credentials and boot it
+    async fn create_vm(&self, config: &ContainerConfig) -> Result<String> {
+        let template_vmid = find_image(&self.images, &config.image)
+            .and_then(|entry| entry.proxmox_vm_template)
+            .ok_or_else(|| anyhow::anyhow!("Image '{}' has no Proxmox VM template in the catalog", config.image))?;
+
+        let clone = VmCloneConfig {
+            newid: config.id,
+            name: config.name.clone(),
+            full: 1,
+            storage: Some(self.storage.clone()),
+        };
+        let task = self.client.clone_vm(template_vmid, &clone).await?;
+        self.client.wait_for_task(&task, 600).await?;
+
+        let cloud_init = VmCloudInitConfig {
+            memory: config.memory_mb,
+            cores: config.cpu_cores,
+            net0: format!("virtio,bridge={}{}", self.bridge, net0_policy_options(&config.network)),
+            agent: 1,
+            ipconfig0: "ip=dhcp".to_string(),
+            ciuser: "root".to_string(),
+            // Without a password cloud-init leaves SSH password login off
+            cipassword: if config.disable_password_auth {
+                None
+            } else {
+                Some(config.password.clone())
+            },
+            sshkeys: if config.ssh_public_keys.is_empty() {
+                None
+            } else {
+                Some(encode_sshkeys(&config.ssh_public_keys))
+            },
+        };
+        self.client.configure_vm(config.id, &cloud_init).await?;
+        self.apply_network_policy("qemu", config).await?;
+
+        // Cloud-init templates conventionally boot from scsi0. Proxmox can't shrink disks,
+        // so a template larger than the spec keeps its size.
+        match self.client.resize_vm_disk(config.id, "scsi0", &format!("{}G", config.storage_gb)).await {
+            Ok(Some(task)) => self.client.wait_for_task(&task, 120).await?,
+            Ok(None) => {}
+            Err(e) => warn!("Keeping template disk size for VM {}: {}", config.id, e),
+        }
+
+        let task = self.client.start_vm(config.id).await?;
+        self.client.wait_for_task(&task, 120).await?;
+
+        self.setup_port_forward(config).await?;
+        Ok(config.id.to_string())
+    }
 }
 
 #[async_trait]
@@ -583,62 +1000,175 @@ impl ComputeBackend for ProxmoxBackend {
         self.client.find_available_vmid(range_start, range_end).await
     }
     
+    fn supported_kinds(&self) -> Vec<WorkloadKind> {
+        vec![WorkloadKind::Container, WorkloadKind::Vm]
+    }
+
     async fn create_container(&self, config: &ContainerConfig) -> Result<String> {
-        // Use default template if config doesn't specify (config.image is usually "ubuntu:22.04" etc)
-        // But Proxmox needs full template path "local:vztmpl/..."
-        // For now, we ignore config.image and use self.template
-        
+        if config.kind == WorkloadKind::Vm {
+            return self.create_vm(config).await;
+        }
+
+        // Proxmox needs the full template volume ("local:vztmpl/..."), not the catalog name
+        let ostemplate = self.resolve_template(&config.image)?;
+
         let lxc = LxcConfig {
             vmid: config.id,
             hostname: config.name.clone(),
-            ostemplate: self.template.clone(),
+            ostemplate,
             storage: self.storage.clone(),
-            rootfs: format!("{}:8", self.storage),
+            rootfs: format!("{}:{}", self.storage, config.storage_gb),
             memory: config.memory_mb,
             cores: config.cpu_cores,
-            net0: format!("name=eth0,bridge={},ip=dhcp", self.bridge),
+            net0: format!("name=eth0,bridge={},ip=dhcp{}", self.bridge, net0_policy_options(&config.network)),
             password: config.password.clone(),
-            ssh_public_keys: config.ssh_key.clone(),
-            start: true,
+            // Proxmox takes authorized_keys content (one key per line).
+            // It can't reconfigure sshd inside the template, so with password auth disabled
+            // the root password is simply never shared with the user.
+            ssh_public_keys: if config.ssh_public_keys.is_empty() {
+                None
+            } else {
+                Some(config.ssh_public_keys.join("\n"))
+            },
+            // Started once the firewall is in place
+            start: false,
             unprivileged: true,
         };
         
         let task = self.client.create_lxc(&lxc).await?;
         self.client.wait_for_task(&task, 120).await?;
+
+        self.apply_network_policy("lxc", config).await?;
+        let task = self.client.start_lxc(config.id).await?;
+        self.client.wait_for_task(&task, 120).await?;
+
+        self.setup_port_forward(config).await?;
         Ok(config.id.to_string())
     }
     
-    async fn start_container(&self, id: u32) -> Result<()> {
-        let task = self.client.start_lxc(id).await?;
+    async fn start_container(&self, id: u32, kind: WorkloadKind) -> Result<()> {
+        let task = match kind {
+            WorkloadKind::Container => self.client.start_lxc(id).await?,
+            WorkloadKind::Vm => self.client.start_vm(id).await?,
+        };
         self.client.wait_for_task(&task, 60).await?;
         Ok(())
     }
     
-    async fn stop_container(&self, id: u32) -> Result<()> {
-        let task = self.client.stop_lxc(id).await?;
+    async fn stop_container(&self, id: u32, kind: WorkloadKind) -> Result<()> {
+        let task = match kind {
+            WorkloadKind::Container => self.client.stop_lxc(id).await?,
+            WorkloadKind::Vm => self.client.stop_vm(id).await?,
+        };
         self.client.wait_for_task(&task, 60).await?;
         Ok(())
     }
 
-    async fn delete_container(&self, id: u32) -> Result<()> {
-        let task = self.client.delete_lxc(id).await?;
+    async fn delete_container(&self, id: u32, kind: WorkloadKind) -> Result<()> {
+        if let Some(forwarder) = &self.port_forwarder {
+            if let Err(e) = forwarder.remove(id) {
+                warn!("Failed to remove port forward for {}: {}", id, e);
+            }
+        }
+
+        let task = match kind {
+            WorkloadKind::Container => self.client.delete_lxc(id).await?,
+            WorkloadKind::Vm => self.client.delete_vm(id).await?,
+        };
         self.client.wait_for_task(&task, 60).await?;
         Ok(())
     }
 
+    async fn list_containers(&self) -> Result<Vec<(u32, WorkloadKind)>> {
+        // Only count workloads we created (name "paygress-<vmid>")
+        let containers = self.client.list_lxc().await?.into_iter()
+            .filter(|c| c.name.starts_with("paygress-"))
+            .map(|c| (c.vmid, WorkloadKind::Container));
+        let vms = self.client.list_vm().await?.into_iter()
+            .filter(|vm| vm.name.starts_with("paygress-"))
+            .map(|vm| (vm.vmid, WorkloadKind::Vm));
+        Ok(containers.chain(vms).collect())
+    }
+
     async fn get_node_status(&self) -> Result<ComputeNodeStatus> {
         let status = self.client.get_node_status().await?;
+        // Disks are allocated on the configured storage, not the node's root filesystem
+        let storage = self.client.get_storage_status(&self.storage).await.unwrap_or_else(|e| {
+            warn!("Failed to get status of storage {}: {}", self.storage, e);
+            StorageStatus::default()
+        });
         Ok(ComputeNodeStatus {
             cpu_usage: status.cpu,
+            cpu_total: status.cpuinfo.map(|info| info.cpus as u64 * 1000).unwrap_or(0),
             memory_used: status.memory.used,
             memory_total: status.memory.total,
-            disk_used: 0, 
-            disk_total: 0,
+            disk_used: storage.used,
+            disk_total: storage.total,
         })
     }
     
-    async fn get_container_ip(&self, _id: u32) -> Result<Option<String>> {
-         // Proxmox API connection logic to get IP is complex without guest agent
-         Ok(None)
+    async fn get_container_ip(&self, id: u32, kind: WorkloadKind) -> Result<Option<String>> {
+        let addresses: Vec<String> = match kind {
+            // "inet" is CIDR notation, e.g. "192.168.1.50/24"
+            WorkloadKind::Container => self.client.get_lxc_interfaces(id).await?
+                .into_iter()
+                .filter(|iface| iface.name != "lo")
+                .filter_map(|iface| iface.inet)
+                .filter_map(|inet| inet.split('/').next().map(str::to_string))
+                .collect(),
+            // Needs qemu-guest-agent running inside the VM
+            WorkloadKind::Vm => self.client.get_vm_agent_interfaces(id).await?
+                .into_iter()
+                .filter(|iface| iface.name != "lo")
+                .flat_map(|iface| iface.ip_addresses)
+                .filter(|addr| addr.ip_address_type == "ipv4")
+                .map(|addr| addr.ip_address)
+                .collect(),
+        };
+
+        Ok(addresses.into_iter().find(|ip| !ip.starts_with("127.")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lxc_config_serialization() {
+        let config = LxcConfig {
+            vmid: 100,
+            hostname: "test-container".to_string(),
+            ostemplate: "local:vztmpl/ubuntu-22.04-standard.tar.zst".to_string(),
+            storage: "local-lvm".to_string(),
+            rootfs: "local-lvm:8".to_string(),
+            memory: 1024,
+            cores: 1,
+            net0: "name=eth0,bridge=vmbr0,ip=dhcp".to_string(),
+            password: "testpass".to_string(),
+            ssh_public_keys: None,
+            start: true,
+            unprivileged: true,
+        };
+
+        // Should serialize without errors
+        let _serialized = serde_urlencoded::to_string(&config).unwrap();
+    }
+
+    #[test]
+    fn test_firewall_rules_for_policy() {
+        let policy = NetworkPolicy { block_smtp: true, bandwidth_mbit: Some(100), ..Default::default() };
+        let rules = firewall_rules(&policy);
+
+        assert_eq!(rules[0].dport.as_deref(), Some("25"));
+        assert_eq!(rules[0].action, "REJECT");
+        assert!(rules.iter().any(|r| r.action == "ACCEPT" && r.dport.as_deref() == Some("53")));
+        let v4 = rules.iter().find(|r| r.dest.as_deref().is_some_and(|d| d.contains("10.0.0.0/8"))).unwrap();
+        assert!(!v4.dest.as_ref().unwrap().contains(':'));
+        assert!(rules.windows(2).all(|w| w[0].pos < w[1].pos));
+        assert_eq!(net0_policy_options(&policy), ",firewall=1,rate=12.5");
+
+        let open = NetworkPolicy { egress: crate::network_policy::Egress::Unrestricted, ..Default::default() };
+        assert!(firewall_rules(&open).is_empty());
     }
 }
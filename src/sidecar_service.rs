@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{info, warn};
 use std::collections::{HashMap, BTreeMap, HashSet};
@@ -9,6 +9,24 @@ use std::sync::Mutex;
 
 use crate::nostr;
 use crate::cashu::initialize_cashu;
+use crate::admission::{OvercommitRatios, Resources};
+use crate::network_policy::{NetworkPolicy, DNS_PORT};
+use crate::provisioning::{ssh_bootstrap_env, SSH_BOOTSTRAP_SCRIPT};
+
+// Label selector for pods created by this service
+const MANAGED_BY_SELECTOR: &str = "managed-by=paygress-sidecar";
+
+// Pod annotations PodInfo is rebuilt from after a restart
+const ANNOTATION_CREATED_AT: &str = "paygress.io/created-at";
+const ANNOTATION_EXPIRES_AT: &str = "paygress.io/expires-at";
+const ANNOTATION_DURATION_SECONDS: &str = "paygress.io/duration-seconds";
+const ANNOTATION_SSH_USERNAME: &str = "paygress.io/ssh-username";
+const ANNOTATION_PAYMENT_MSATS: &str = "paygress.io/payment-msats";
+const ANNOTATION_RATE_MSATS_PER_SEC: &str = "paygress.io/rate-msats-per-sec";
+const ANNOTATION_PAYMENT_MINT: &str = "paygress.io/payment-mint";
+const ANNOTATION_PAYMENT_UNIT: &str = "paygress.io/payment-unit";
+const ANNOTATION_RECEIVED_MSATS: &str = "paygress.io/received-msats";
+const ANNOTATION_SSH_PORT: &str = "paygress.io/ssh-port";
 
 // Configuration for the sidecar service
 #[derive(Clone, Debug)]
@@ -23,6 +41,49 @@ pub struct SidecarConfig {
     pub enable_cleanup_task: bool,
     pub whitelisted_mints: Vec<String>, // Allowed Cashu mint URLs
     pub pod_specs: Vec<nostr::PodSpec>, // Available pod specifications
+    pub pod_networking: PodNetworking, // How SSH in pods is exposed
+    pub overcommit: OvercommitRatios, // How far the cluster's allocatable resources may be oversold
+}
+
+/// How SSH in a pod is reached from outside the cluster
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PodNetworking {
+    /// Pod shares the node's network namespace and sshd listens on the allocated port.
+    /// Tenants can see each other's traffic, so only use this on single-tenant nodes.
+    HostNetwork,
+    /// Isolated pod behind a NodePort service whose node port is the allocated port
+    #[default]
+    NodePort,
+    /// Isolated pod behind a LoadBalancer service listening on the allocated port
+    LoadBalancer,
+}
+
+impl PodNetworking {
+    /// Parse the POD_NETWORKING setting ("host", "nodeport" or "loadbalancer")
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "host" | "hostnetwork" => Some(Self::HostNetwork),
+            "nodeport" => Some(Self::NodePort),
+            "loadbalancer" => Some(Self::LoadBalancer),
+            _ => None,
+        }
+    }
+
+    /// Kubernetes' default service-node-port-range
+    pub const NODE_PORT_RANGE: (u16, u16) = (30000, 32767);
+
+    /// Refuse SSH port ranges the API server would reject as node ports, so a bad
+    /// range fails at startup instead of on every spawn past the end of the node port range
+    pub fn check_port_range(&self, start: u16, end: u16) -> Result<(), String> {
+        let (min, max) = Self::NODE_PORT_RANGE;
+        if *self == Self::NodePort && (start < min || end > max) {
+            return Err(format!(
+                "SSH port range {}-{} is outside the NodePort range {}-{}; configure a range inside it",
+                start, end, min, max
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Default for SidecarConfig {
@@ -38,11 +99,14 @@ impl Default for SidecarConfig {
             enable_cleanup_task: true,
             whitelisted_mints: vec![], // Will be populated from environment variables
             pod_specs: vec![], // Will be populated from environment variables
+            pod_networking: PodNetworking::default(),
+            overcommit: OvercommitRatios::default(),
         }
     }
 }
 
-// Port pool for managing SSH port allocation
+// Port pool for managing SSH port allocation: host ports with host networking,
+// otherwise the node port (NodePort) or service port (LoadBalancer) of each pod's service
 #[derive(Debug)]
 pub struct PortPool {
     available_ports: HashSet<u16>,
@@ -74,6 +138,19 @@ impl PortPool {
     pub fn allocated_count(&self) -> usize {
         self.allocated_ports.len()
     }
+
+    // Mark a port as taken (e.g. by a pod that existed before this process started)
+    pub fn mark_allocated(&mut self, port: u16) {
+        self.available_ports.remove(&port);
+        self.allocated_ports.insert(port);
+    }
+
+    // Return a port to the pool once its pod is gone
+    pub fn release(&mut self, port: u16) {
+        if self.allocated_ports.remove(&port) && (self.range_start..=self.range_end).contains(&port) {
+            self.available_ports.insert(port);
+        }
+    }
 }
 
 // Sidecar service state
@@ -97,6 +174,10 @@ pub struct PodInfo {
     pub ssh_password: String,
     pub payment_amount_msats: u64,
     pub duration_seconds: u64,
+    pub rate_msats_per_sec: u64,
+    pub payment_mint: String, // Mint of the paying token (refunds are paid from here)
+    pub payment_unit: String,
+    pub received_msats: u64, // Redeemed by the service and paying for time (refunds never exceed it)
     pub node_port: Option<u16>,
     pub nostr_public_key: String,  // Pod's npub
     pub nostr_private_key: String, // Pod's nsec
@@ -107,17 +188,18 @@ pub struct PodInfo {
 // Pod management service
 pub struct PodManager {
     pub client: kube::Client,
+    pub networking: PodNetworking,
 }
 
 impl PodManager {
-    pub async fn new() -> Result<Self, String> {
+    pub async fn new(networking: PodNetworking) -> Result<Self, String> {
         let client = kube::Client::try_default().await.map_err(|e| format!("Failed to create Kubernetes client: {}", e))?;
-        Ok(Self { client })
+        Ok(Self { client, networking })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_ssh_pod(
         &self,
-        _config: &SidecarConfig,
         namespace: &str,
         pod_name: &str,
         pod_npub: &str,        // Add NPUB parameter
@@ -129,7 +211,12 @@ impl PodManager {
         duration_seconds: u64,
         memory_mb: u64,
         cpu_millicores: u64,
+        storage_gb: u64, // ephemeral-storage limit; the kubelet evicts pods that write more
         user_pubkey: &str, // User's public key for sending access events
+        ssh_public_keys: &[String], // Installed into the user's authorized_keys
+        disable_password_auth: bool, // Key-only login (requires ssh_public_keys)
+        network: &NetworkPolicy, // Egress and bandwidth rules from the spec
+        extra_annotations: BTreeMap<String, String>, // e.g. payment_annotations()
     ) -> Result<u16, String> { // Return only node_port since we have NPUB
         use k8s_openapi::api::core::v1::{
             Container, Pod, PodSpec, EnvVar, ContainerPort, Volume,
@@ -139,8 +226,12 @@ impl PodManager {
 
         let pods: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
 
+        // sshd listens on the allocated port on the host, or on 22 inside an isolated pod
+        let host_network = self.networking == PodNetworking::HostNetwork;
+        let listen_port = if host_network { ssh_port } else { 22 };
+
         // Create SSH environment variables
-        let env_vars = vec![
+        let mut env_vars = vec![
             EnvVar {
                 name: "PUID".to_string(),
                 value: Some("1000".to_string()),
@@ -173,7 +264,7 @@ impl PodManager {
             },
             EnvVar {
                 name: "PASSWORD_ACCESS".to_string(),
-                value: Some("true".to_string()),
+                value: Some((!disable_password_auth).to_string()),
                 value_from: None,
             },
             // Nostr keys for the pod
@@ -199,10 +290,16 @@ impl PodManager {
             },
             EnvVar {
                 name: "SSH_PORT".to_string(),
-                value: Some(ssh_port.to_string()), // SSH should listen on this port with hostNetwork
+                value: Some(listen_port.to_string()),
                 value_from: None,
             },
         ];
+        // Credentials reach the bootstrap script through the environment, never its source
+        env_vars.extend(
+            ssh_bootstrap_env(listen_port, username, password, ssh_public_keys, disable_password_auth)
+                .into_iter()
+                .map(|(name, value)| EnvVar { name: name.to_string(), value: Some(value), value_from: None }),
+        );
 
         // Create pod labels and annotations
         let mut labels = BTreeMap::new();
@@ -224,24 +321,40 @@ impl PodManager {
         };
         labels.insert("pod-npub".to_string(), truncated_hex.to_string()); // Add NPUB hex as label
 
-        let mut annotations = BTreeMap::new();
-        annotations.insert("paygress.io/created-at".to_string(), Utc::now().to_rfc3339());
-        annotations.insert("paygress.io/expires-at".to_string(), 
+        let mut annotations = extra_annotations;
+        annotations.insert(ANNOTATION_CREATED_AT.to_string(), Utc::now().to_rfc3339());
+        annotations.insert(ANNOTATION_EXPIRES_AT.to_string(), 
             (Utc::now() + chrono::Duration::seconds(duration_seconds as i64)).to_rfc3339());
-        annotations.insert("paygress.io/duration-seconds".to_string(), duration_seconds.to_string());
-        annotations.insert("paygress.io/ssh-username".to_string(), username.to_string());
+        annotations.insert(ANNOTATION_DURATION_SECONDS.to_string(), duration_seconds.to_string());
+        annotations.insert(ANNOTATION_SSH_USERNAME.to_string(), username.to_string());
+        annotations.insert(ANNOTATION_SSH_PORT.to_string(), ssh_port.to_string());
+        // Enforced by the CNI bandwidth plugin where it is installed
+        if let Some(mbit) = network.bandwidth_mbit {
+            annotations.insert("kubernetes.io/ingress-bandwidth".to_string(), format!("{}M", mbit));
+            annotations.insert("kubernetes.io/egress-bandwidth".to_string(), format!("{}M", mbit));
+        }
         // Note: No TTL annotations needed - activeDeadlineSeconds handles pod termination
 
         // Create volumes
         let _volumes: Vec<Volume> = Vec::new();
 
-        // Create containers with direct host port binding for SSH access
+        // Isolated pods are only ready once sshd accepts connections
+        let readiness_probe = (!host_network).then(|| k8s_openapi::api::core::v1::Probe {
+            tcp_socket: Some(k8s_openapi::api::core::v1::TCPSocketAction {
+                port: k8s_openapi::apimachinery::pkg::util::intstr::IntOrString::Int(22),
+                ..Default::default()
+            }),
+            period_seconds: Some(2),
+            ..Default::default()
+        });
+
+        // Create the SSH container (on the host's network, or isolated behind a service)
         let containers = vec![Container {
             name: "ssh-server".to_string(),
             image: Some(image.to_string()),
             ports: Some(vec![ContainerPort {
-                container_port: ssh_port as i32, // With hostNetwork, container listens directly on host port
-                host_port: None, // Not needed with hostNetwork: true
+                container_port: listen_port as i32,
+                host_port: None, // Host networking binds on the node directly; otherwise the service exposes it
                 name: Some("ssh".to_string()),
                 protocol: Some("TCP".to_string()),
                 ..Default::default()
@@ -253,85 +366,22 @@ impl PodManager {
             command: Some(vec![
                 "/bin/sh".to_string(),
                 "-c".to_string(),
-                format!(
-                    r#"set -e
-echo "Setting up SSH access on port {ssh_port}..."
-
-# Detect package manager and install OpenSSH if not present
-if command -v apt-get >/dev/null 2>&1; then
-    export DEBIAN_FRONTEND=noninteractive
-    apt-get update -qq && apt-get install -y -qq openssh-server sudo 2>/dev/null || true
-    mkdir -p /run/sshd
-elif command -v apk >/dev/null 2>&1; then
-    apk add --no-cache openssh sudo 2>/dev/null || true
-    ssh-keygen -A 2>/dev/null || true
-elif command -v yum >/dev/null 2>&1; then
-    yum install -y openssh-server sudo 2>/dev/null || true
-fi
-
-# Detect available shell
-if [ -f /bin/bash ]; then
-    DEFAULT_SHELL="/bin/bash"
-else
-    DEFAULT_SHELL="/bin/sh"
-fi
-
-# Create user if it doesn't exist
-if ! id "{username}" >/dev/null 2>&1; then
-    useradd -m -s "$DEFAULT_SHELL" "{username}" 2>/dev/null || adduser -D -s "$DEFAULT_SHELL" "{username}" 2>/dev/null || true
-fi
-
-# Set password
-echo "{username}:{password}" | chpasswd 2>/dev/null || true
-
-# Add user to sudoers
-echo "{username} ALL=(ALL) NOPASSWD:ALL" > /etc/sudoers.d/{username} 2>/dev/null || true
-chmod 0440 /etc/sudoers.d/{username} 2>/dev/null || true
-
-# Configure SSH
-mkdir -p /etc/ssh
-cat > /etc/ssh/sshd_config <<EOF
-Port {ssh_port}
-ListenAddress 0.0.0.0
-PermitRootLogin yes
-PasswordAuthentication yes
-PubkeyAuthentication yes
-UseDNS no
-X11Forwarding yes
-PrintMotd no
-AcceptEnv LANG LC_*
-Subsystem sftp internal-sftp
-EOF
-
-# Start SSH daemon
-if command -v sshd >/dev/null 2>&1; then
-    # Use absolute path to sshd if possible
-    SSHD_BIN=$(command -v sshd)
-    $SSHD_BIN -f /etc/ssh/sshd_config -D &
-    echo "SSH server started on port {ssh_port}"
-else
-    echo "Warning: sshd not found"
-fi
-
-echo "Container ready. SSH on port {ssh_port}, user: {username} (shell: $DEFAULT_SHELL)"
-tail -f /dev/null
-"#,
-                    ssh_port = ssh_port,
-                    username = username,
-                    password = password
-                ),
+                SSH_BOOTSTRAP_SCRIPT.to_string(),
             ]),
+            readiness_probe,
             resources: Some(k8s_openapi::api::core::v1::ResourceRequirements {
                 limits: Some({
                     let mut limits = std::collections::BTreeMap::new();
                     limits.insert("memory".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity(format!("{}Mi", memory_mb)));
                     limits.insert("cpu".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity(format!("{}m", cpu_millicores)));
+                    limits.insert("ephemeral-storage".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity(format!("{}Gi", storage_gb)));
                     limits
                 }),
                 requests: Some({
                     let mut requests = std::collections::BTreeMap::new();
                     requests.insert("memory".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity(format!("{}Mi", memory_mb)));
                     requests.insert("cpu".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity(format!("{}m", cpu_millicores)));
+                    requests.insert("ephemeral-storage".to_string(), k8s_openapi::apimachinery::pkg::api::resource::Quantity(format!("{}Gi", storage_gb)));
                     requests
                 }),
                 claims: None,
@@ -353,8 +403,8 @@ tail -f /dev/null
                 volumes: None,
                 restart_policy: Some("Never".to_string()),
                 active_deadline_seconds: Some(duration_seconds as i64), // Kubernetes will auto-terminate after this time
-                host_network: Some(true), // Use host networking - required for direct port access
-                dns_policy: Some("ClusterFirstWithHostNet".to_string()), // Required when hostNetwork is true
+                host_network: Some(host_network),
+                dns_policy: host_network.then(|| "ClusterFirstWithHostNet".to_string()), // Required when hostNetwork is true
                 ..Default::default()
             }),
             ..Default::default()
@@ -362,11 +412,20 @@ tail -f /dev/null
 
         // Create the pod
         let pp = PostParams::default();
-        pods.create(&pp, &pod).await.map_err(|e| format!("Failed to create pod: {}", e))?;
+        let created = pods.create(&pp, &pod).await.map_err(|e| format!("Failed to create pod: {}", e))?;
 
-        // Use host networking with direct port binding for efficiency
-        // This eliminates the need for separate services per pod
-        // Each pod gets a unique port on the host directly
+        // Isolated pods get their own NetworkPolicy and are reached through their own
+        // service. Both are in place before sshd is up, so no tenant code runs unfiltered.
+        let exposed = match self.create_network_policy(namespace, &created, network).await {
+            Ok(()) => self.create_ssh_service(namespace, &created, ssh_port).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = exposed {
+            if let Err(delete_error) = self.delete_pod(namespace, pod_name).await {
+                warn!("Failed to delete pod {} after its setup failed: {}", pod_name, delete_error);
+            }
+            return Err(e);
+        }
 
         // Wait for pod to be running and SSH to be ready
         info!("Waiting for pod {} to be ready...", pod_name);
@@ -386,7 +445,12 @@ tail -f /dev/null
                                 // Check if container is ready
                                 if let Some(container_statuses) = &status.container_statuses {
                                     if let Some(container_status) = container_statuses.first() {
-                                        if container_status.ready {
+                                        if container_status.ready && !host_network {
+                                            // The readiness probe already checked sshd
+                                            pod_ready = true;
+                                            info!("Pod {} is ready and SSH is listening", pod_name);
+                                            break;
+                                        } else if container_status.ready {
                                             // Verify SSH is actually listening on the port
                                             use std::net::TcpStream;
                                             match TcpStream::connect_timeout(
@@ -428,8 +492,8 @@ tail -f /dev/null
             warn!("Pod {} may not be fully ready, but proceeding anyway", pod_name);
         }
 
-        // SSH is directly accessible via hostPort binding
-        let node_port = ssh_port; // The allocated port is directly accessible on the host
+        // The allocated port is the host port, node port or load balancer port
+        let node_port = ssh_port;
 
         info!(
             pod_name = %pod_name, 
@@ -438,7 +502,8 @@ tail -f /dev/null
             ssh_port = %ssh_port,
             username = %username,
             node_port = %node_port,
-            "SSH pod created with direct host port access (no service overhead)"
+            networking = ?self.networking,
+            "SSH pod created"
         );
 
         // Access details are now sent via NIP-17 Gift Wrap private messages from main.rs
@@ -447,12 +512,149 @@ tail -f /dev/null
         Ok(node_port)
     }
 
+    // Expose an isolated pod's sshd through a NodePort or LoadBalancer service on `ssh_port`.
+    // The service is owned by the pod, so Kubernetes deletes it along with the pod.
+    async fn create_ssh_service(&self, namespace: &str, pod: &k8s_openapi::api::core::v1::Pod, ssh_port: u16) -> Result<(), String> {
+        use k8s_openapi::api::core::v1::{Service, ServicePort, ServiceSpec};
+        use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+        use kube::api::PostParams;
+        use kube::Api;
+
+        let (service_type, port, node_port) = match self.networking {
+            PodNetworking::HostNetwork => return Ok(()),
+            PodNetworking::NodePort => ("NodePort", 22, Some(ssh_port as i32)),
+            PodNetworking::LoadBalancer => ("LoadBalancer", ssh_port as i32, None),
+        };
+
+        let pod_name = pod.metadata.name.clone().unwrap_or_default();
+        let mut selector = BTreeMap::new();
+        selector.insert("pod-name".to_string(), pod_name.clone());
+        let mut labels = selector.clone();
+        labels.insert("managed-by".to_string(), "paygress-sidecar".to_string());
+
+        let service = Service {
+            metadata: kube::core::ObjectMeta {
+                name: Some(ssh_service_name(&pod_name)),
+                labels: Some(labels),
+                owner_references: Some(vec![owned_by(pod)]),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                type_: Some(service_type.to_string()),
+                selector: Some(selector),
+                ports: Some(vec![ServicePort {
+                    name: Some("ssh".to_string()),
+                    protocol: Some("TCP".to_string()),
+                    port,
+                    target_port: Some(IntOrString::Int(22)),
+                    node_port,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let services: Api<Service> = Api::namespaced(self.client.clone(), namespace);
+        services.create(&PostParams::default(), &service).await
+            .map_err(|e| format!("Failed to create {} service for pod {}: {}", service_type, pod_name, e))?;
+
+        info!(pod_name = %pod_name, service_type = %service_type, ssh_port = %ssh_port, "Created SSH service");
+        Ok(())
+    }
+
+    // Restrict what an isolated pod can reach with a NetworkPolicy owned by the pod.
+    // Needs a CNI that enforces NetworkPolicy (Calico, Cilium, ...).
+    async fn create_network_policy(&self, namespace: &str, pod: &k8s_openapi::api::core::v1::Pod, network: &NetworkPolicy) -> Result<(), String> {
+        use k8s_openapi::api::networking::v1::NetworkPolicy as K8sNetworkPolicy;
+        use kube::api::PostParams;
+        use kube::Api;
+
+        let pod_name = pod.metadata.name.clone().unwrap_or_default();
+        if self.networking == PodNetworking::HostNetwork {
+            warn!(pod_name = %pod_name, "NetworkPolicy doesn't apply to host-network pods; the pod is not isolated");
+            return Ok(());
+        }
+
+        let mut labels = BTreeMap::new();
+        labels.insert("managed-by".to_string(), "paygress-sidecar".to_string());
+
+        let policy = K8sNetworkPolicy {
+            metadata: kube::core::ObjectMeta {
+                name: Some(format!("{}-net", pod_name)),
+                labels: Some(labels),
+                owner_references: Some(vec![owned_by(pod)]),
+                ..Default::default()
+            },
+            spec: Some(network_policy_spec(&pod_name, network)),
+        };
+
+        let policies: Api<K8sNetworkPolicy> = Api::namespaced(self.client.clone(), namespace);
+        policies.create(&PostParams::default(), &policy).await
+            .map_err(|e| format!("Failed to create NetworkPolicy for pod {}: {}", pod_name, e))?;
+
+        info!(pod_name = %pod_name, egress = ?network.egress, "Created NetworkPolicy");
+        Ok(())
+    }
+
+    // Address clients should SSH to when it isn't the configured ssh_host: the
+    // load balancer's IP or hostname, once one has been assigned
+    pub async fn external_host(&self, namespace: &str, pod_name: &str) -> Option<String> {
+        use k8s_openapi::api::core::v1::Service;
+        use kube::Api;
 
+        if self.networking != PodNetworking::LoadBalancer {
+            return None;
+        }
+
+        let services: Api<Service> = Api::namespaced(self.client.clone(), namespace);
+        let service = services.get(&ssh_service_name(pod_name)).await.ok()?;
+        let ingress = service.status?.load_balancer?.ingress?.into_iter().next()?;
+        ingress.ip.or(ingress.hostname)
+    }
 
+    /// Allocatable resources of the cluster's nodes, and what the running
+    /// Paygress pods in `namespace` request
+    pub async fn resource_usage(&self, namespace: &str) -> Result<(Resources, Resources), String> {
+        use k8s_openapi::api::core::v1::{Node, Pod};
+        use kube::api::ListParams;
+        use kube::Api;
+
+        let nodes: Api<Node> = Api::all(self.client.clone());
+        let allocatable = nodes.list(&ListParams::default()).await
+            .map_err(|e| format!("Failed to list nodes: {}", e))?
+            .items.into_iter()
+            .filter_map(|node| node.status.and_then(|s| s.allocatable))
+            .map(|amounts| resources_of(&amounts))
+            .sum();
 
+        let pods: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
+        let active = ListParams::default()
+            .labels(MANAGED_BY_SELECTOR)
+            .fields("status.phase!=Succeeded,status.phase!=Failed");
+        let requested = pods.list(&active).await
+            .map_err(|e| format!("Failed to list pods: {}", e))?
+            .items.into_iter()
+            .flat_map(|pod| pod.spec.map(|s| s.containers).unwrap_or_default())
+            .filter_map(|container| container.resources.and_then(|r| r.requests))
+            .map(|amounts| resources_of(&amounts))
+            .sum();
+
+        Ok((allocatable, requested))
+    }
 
+    pub async fn delete_pod(&self, namespace: &str, pod_name: &str) -> Result<(), String> {
+        use kube::api::DeleteParams;
+        use kube::Api;
+        use k8s_openapi::api::core::v1::Pod;
 
+        let pods: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
+        pods.delete(pod_name, &DeleteParams::default()).await
+            .map_err(|e| format!("Failed to delete pod: {}", e))?;
 
+        info!(pod_name = %pod_name, namespace = %namespace, "Deleted pod");
+        Ok(())
+    }
 
     pub async fn extend_pod_deadline(&self, namespace: &str, pod_name: &str, additional_duration_seconds: u64) -> Result<(), String> {
         use kube::api::{Patch, PatchParams};
@@ -473,7 +675,11 @@ tail -f /dev/null
             .unwrap_or(0);
         
         let new_deadline_seconds = current_deadline_seconds + additional_duration_seconds as i64;
-        let new_expires_at = Utc::now() + chrono::Duration::seconds(additional_duration_seconds as i64);
+        // activeDeadlineSeconds counts from pod start, so the expiry is creation + new deadline
+        let new_expires_at = match &current_pod.metadata.creation_timestamp {
+            Some(created) => created.0 + chrono::Duration::seconds(new_deadline_seconds),
+            None => Utc::now() + chrono::Duration::seconds(additional_duration_seconds as i64),
+        };
 
         // Create patch to update activeDeadlineSeconds and annotations
         let patch = json!({
@@ -482,7 +688,8 @@ tail -f /dev/null
             },
             "metadata": {
                 "annotations": {
-                    "paygress.io/expires-at": new_expires_at.to_rfc3339(),
+                    ANNOTATION_EXPIRES_AT: new_expires_at.to_rfc3339(),
+                    ANNOTATION_DURATION_SECONDS: new_deadline_seconds.to_string(),
                     "paygress.io/extended-at": Utc::now().to_rfc3339()
                 }
             }
@@ -502,16 +709,39 @@ tail -f /dev/null
 
         Ok(())
     }
+
+    // Record the total the service has redeemed for a pod, so refunds stay capped after a restart
+    pub async fn record_received_msats(&self, namespace: &str, pod_name: &str, received_msats: u64) -> Result<(), String> {
+        use kube::api::{Patch, PatchParams};
+        use kube::Api;
+        use k8s_openapi::api::core::v1::Pod;
+        use serde_json::json;
+
+        let pods: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
+        let patch = json!({
+            "metadata": {
+                "annotations": {
+                    ANNOTATION_RECEIVED_MSATS: received_msats.to_string()
+                }
+            }
+        });
+
+        pods.patch(pod_name, &PatchParams::default(), &Patch::Merge(patch)).await
+            .map_err(|e| format!("Failed to record received payment: {}", e))?;
+        Ok(())
+    }
 }
 
 impl SidecarState {
     pub async fn new(config: SidecarConfig) -> Result<Self, String> {
+        config.pod_networking.check_port_range(config.ssh_port_range_start, config.ssh_port_range_end)?;
+
         // Initialize Cashu
         initialize_cashu(&config.cashu_db_path).await
             .map_err(|e| format!("Cashu init failed: {}", e))?;
 
         // Initialize Kubernetes client
-        let k8s_client = Arc::new(PodManager::new().await?);
+        let k8s_client = Arc::new(PodManager::new(config.pod_networking).await?);
 
         let active_pods = Arc::new(tokio::sync::RwLock::new(HashMap::new()));
         
@@ -529,6 +759,120 @@ impl SidecarState {
         })
     }
 
+    // Rebuild active_pods and the port pool from the pods currently in the cluster.
+    // Run at startup so pods created before a restart are still known.
+    pub async fn reconcile_from_cluster(&self) -> Result<usize, String> {
+        use kube::Api;
+        use kube::api::ListParams;
+        use k8s_openapi::api::core::v1::Pod;
+
+        let pods_api: Api<Pod> = Api::namespaced(self.k8s_client.client.clone(), &self.config.pod_namespace);
+        let pods = pods_api.list(&ListParams::default().labels(MANAGED_BY_SELECTOR)).await
+            .map_err(|e| format!("Failed to list pods: {}", e))?;
+
+        let recovered: HashMap<String, PodInfo> = pods.items.iter()
+            .filter_map(pod_info_from_pod)
+            .map(|info| (info.pod_npub.clone(), info))
+            .collect();
+
+        {
+            let mut port_pool = self.port_pool.lock().map_err(|e| format!("Failed to lock port pool: {}", e))?;
+            *port_pool = PortPool::new(self.config.ssh_port_range_start, self.config.ssh_port_range_end);
+            for info in recovered.values() {
+                port_pool.mark_allocated(info.allocated_port);
+            }
+        }
+
+        let count = recovered.len();
+        *self.active_pods.write().await = recovered;
+        Ok(count)
+    }
+
+    // Keep active_pods and the port pool in sync with the cluster while the service runs
+    pub async fn watch_pods(self) {
+        use futures_util::StreamExt;
+        use k8s_openapi::api::core::v1::Pod;
+        use kube::runtime::{watcher, WatchStreamExt};
+        use kube::Api;
+
+        let pods_api: Api<Pod> = Api::namespaced(self.k8s_client.client.clone(), &self.config.pod_namespace);
+        let mut events = watcher(pods_api, watcher::Config::default().labels(MANAGED_BY_SELECTOR))
+            .default_backoff()
+            .boxed();
+
+        // Pods listed since the last (re)list; anything else was deleted while we weren't watching
+        let mut listed = HashSet::new();
+
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(watcher::Event::Init) => listed.clear(),
+                Ok(watcher::Event::InitApply(pod)) => {
+                    if let Some(pod_npub) = self.apply_pod(&pod).await {
+                        listed.insert(pod_npub);
+                    }
+                }
+                Ok(watcher::Event::InitDone) => {
+                    let stale: Vec<String> = self.active_pods.read().await.keys()
+                        .filter(|npub| !listed.contains(*npub))
+                        .cloned()
+                        .collect();
+                    for pod_npub in stale {
+                        self.forget_pod(&pod_npub).await;
+                    }
+                }
+                Ok(watcher::Event::Apply(pod)) => {
+                    self.apply_pod(&pod).await;
+                }
+                Ok(watcher::Event::Delete(pod)) => {
+                    if let Some(pod_npub) = npub_of_pod(&pod) {
+                        self.forget_pod(&pod_npub).await;
+                    }
+                }
+                Err(e) => warn!("Pod watcher error: {}", e),
+            }
+        }
+
+        warn!("Pod watcher stream ended");
+    }
+
+    // Track a live pod (or forget a finished one). Returns the NPUB if the pod is live.
+    async fn apply_pod(&self, pod: &k8s_openapi::api::core::v1::Pod) -> Option<String> {
+        let info = match pod_info_from_pod(pod) {
+            Some(info) => info,
+            None => {
+                if let Some(pod_npub) = npub_of_pod(pod) {
+                    self.forget_pod(&pod_npub).await;
+                }
+                return None;
+            }
+        };
+
+        if let Ok(mut port_pool) = self.port_pool.lock() {
+            port_pool.mark_allocated(info.allocated_port);
+        }
+
+        let pod_npub = info.pod_npub.clone();
+        let mut active_pods = self.active_pods.write().await;
+        match active_pods.get_mut(&pod_npub) {
+            // Already tracked: only the expiry can change (top-ups patch the annotation)
+            Some(existing) => existing.expires_at = info.expires_at,
+            None => {
+                info!("Tracking pod {} found in cluster", pod_npub);
+                active_pods.insert(pod_npub.clone(), info);
+            }
+        }
+        Some(pod_npub)
+    }
+
+    async fn forget_pod(&self, pod_npub: &str) {
+        if let Some(info) = self.active_pods.write().await.remove(pod_npub) {
+            info!("Pod {} is gone, releasing port {}", pod_npub, info.allocated_port);
+            if let Ok(mut port_pool) = self.port_pool.lock() {
+                port_pool.release(info.allocated_port);
+            }
+        }
+    }
+
     // Calculate duration based on payment amount (using first available spec as default)
     pub fn calculate_duration_from_payment(&self, payment_msats: u64) -> u64 {
         let msats_per_sec = self.config.pod_specs.first()
@@ -540,6 +884,22 @@ impl SidecarState {
         payment_msats / msats_per_sec
     }
 
+    // Rate a pod was sold at, falling back to the first spec's for pods that didn't record one
+    pub async fn pod_rate_msats_per_sec(&self, pod_npub: &str) -> u64 {
+        let recorded = self.active_pods.read().await
+            .get(pod_npub)
+            .map(|info| info.rate_msats_per_sec)
+            .unwrap_or(0);
+        if recorded > 0 {
+            return recorded;
+        }
+
+        self.config.pod_specs.first()
+            .map(|spec| spec.rate_msats_per_sec)
+            .unwrap_or(100)
+            .max(1)
+    }
+
     // Generate secure random password
     pub fn generate_password() -> String {
         use rand::Rng;
@@ -578,10 +938,16 @@ impl SidecarState {
 
 
 
-    // Check what ports are actually in use by existing pods
+    // Service ports don't bind anything on this host, so only host networking needs the bind check
+    fn host_port_taken(&self, port: u16) -> bool {
+        self.config.pod_networking == PodNetworking::HostNetwork && self.is_port_in_use(port)
+    }
+
+    // Check what ports are actually in use by existing pods: SSH ports of host network
+    // pods, and the ports and node ports of SSH services
     async fn get_ports_in_use_by_pods(&self) -> Result<HashSet<u16>, String> {
         use kube::Api;
-        use k8s_openapi::api::core::v1::Pod;
+        use k8s_openapi::api::core::v1::{Pod, Service};
         
         let pods_api: Api<Pod> = Api::namespaced(self.k8s_client.client.clone(), &self.config.pod_namespace);
         let pods = pods_api.list(&kube::api::ListParams::default()).await
@@ -590,17 +956,33 @@ impl SidecarState {
         let mut used_ports = HashSet::new();
         for pod in pods.items {
             if let Some(spec) = &pod.spec {
+                let host_network = spec.host_network.unwrap_or(false);
                 for container in &spec.containers {
                     if let Some(ports) = &container.ports {
                         for port in ports {
                             if let Some(host_port) = port.host_port {
                                 used_ports.insert(host_port as u16);
+                            } else if host_network {
+                                used_ports.insert(port.container_port as u16);
                             }
                         }
                     }
                 }
             }
         }
+
+        let services_api: Api<Service> = Api::namespaced(self.k8s_client.client.clone(), &self.config.pod_namespace);
+        let services = services_api.list(&kube::api::ListParams::default().labels(MANAGED_BY_SELECTOR)).await
+            .map_err(|e| format!("Failed to list services: {}", e))?;
+        for port in services.items.iter()
+            .filter_map(|service| service.spec.as_ref()?.ports.as_ref())
+            .flatten()
+        {
+            used_ports.insert(port.port as u16);
+            if let Some(node_port) = port.node_port {
+                used_ports.insert(node_port as u16);
+            }
+        }
         
         info!("Found {} ports in use by existing pods: {:?}", used_ports.len(), used_ports);
         Ok(used_ports)
@@ -623,7 +1005,7 @@ impl SidecarState {
         
         // Clean up any ports that are marked as allocated but are actually free
         for port in allocated_ports {
-            if !self.is_port_in_use(port) && !pods_using_ports.contains(&port) {
+            if !self.host_port_taken(port) && !pods_using_ports.contains(&port) {
                 // Port is actually free, move it back to available
                 let mut port_pool = self.port_pool.lock().map_err(|e| format!("Failed to lock port pool: {}", e))?;
                 port_pool.allocated_ports.remove(&port);
@@ -640,13 +1022,13 @@ impl SidecarState {
         
         for port in available_ports {
             // Double-check if port is actually available on the system and not used by pods
-            if !self.is_port_in_use(port) && !pods_using_ports.contains(&port) {
+            if !self.host_port_taken(port) && !pods_using_ports.contains(&port) {
                 // Add small random delay to reduce race conditions (1-10ms)
                 let delay_ms = (port % 10) + 1;
                 tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms as u64)).await;
                 
                 // Double-check again after delay
-                if !self.is_port_in_use(port) && !pods_using_ports.contains(&port) {
+                if !self.host_port_taken(port) && !pods_using_ports.contains(&port) {
                     // Port is free, allocate it
                     let mut port_pool = self.port_pool.lock().map_err(|e| format!("Failed to lock port pool: {}", e))?;
                     port_pool.available_ports.remove(&port);
@@ -691,7 +1073,7 @@ impl SidecarState {
             }
             
             // Check if port is actually available on system
-            if !self.is_port_in_use(port) {
+            if !self.host_port_taken(port) {
                 // Found a free port outside our pool - allocate it
                 let mut port_pool = self.port_pool.lock().map_err(|e| format!("Failed to lock port pool: {}", e))?;
                 port_pool.allocated_ports.insert(port);
@@ -708,6 +1090,193 @@ impl SidecarState {
 
     
 
+}
+
+// Annotations recording how a pod was paid for, so refunds still work after a restart
+pub fn payment_annotations(payment_msats: u64, rate_msats_per_sec: u64, payment_mint: &str, payment_unit: &str, received_msats: u64) -> BTreeMap<String, String> {
+    let mut annotations = BTreeMap::new();
+    annotations.insert(ANNOTATION_PAYMENT_MSATS.to_string(), payment_msats.to_string());
+    annotations.insert(ANNOTATION_RATE_MSATS_PER_SEC.to_string(), rate_msats_per_sec.to_string());
+    annotations.insert(ANNOTATION_PAYMENT_MINT.to_string(), payment_mint.to_string());
+    annotations.insert(ANNOTATION_PAYMENT_UNIT.to_string(), payment_unit.to_string());
+    annotations.insert(ANNOTATION_RECEIVED_MSATS.to_string(), received_msats.to_string());
+    annotations
+}
+
+// Value of an env var on the pod's SSH container
+fn pod_env(pod: &k8s_openapi::api::core::v1::Pod, name: &str) -> Option<String> {
+    pod.spec.as_ref()?
+        .containers.first()?
+        .env.as_ref()?
+        .iter()
+        .find(|var| var.name == name)
+        .and_then(|var| var.value.clone())
+}
+
+// Pod NPUB from the container env, falling back to the pod-npub label (NPUB without "npub1")
+fn npub_of_pod(pod: &k8s_openapi::api::core::v1::Pod) -> Option<String> {
+    pod_env(pod, "POD_NPUB").or_else(|| {
+        pod.metadata.labels.as_ref()?
+            .get("pod-npub")
+            .map(|hex| format!("npub1{}", hex))
+    })
+}
+
+// Rebuild PodInfo from a pod created by create_ssh_pod: timing and payment from the
+// paygress.io/* annotations, identity from the pod-npub label and credentials from the env.
+// Returns None for pods that have finished or are being deleted.
+fn pod_info_from_pod(pod: &k8s_openapi::api::core::v1::Pod) -> Option<PodInfo> {
+    if pod.metadata.deletion_timestamp.is_some() {
+        return None;
+    }
+    if let Some(phase) = pod.status.as_ref().and_then(|s| s.phase.as_deref()) {
+        if phase == "Succeeded" || phase == "Failed" {
+            return None;
+        }
+    }
+
+    let annotations = pod.metadata.annotations.clone().unwrap_or_default();
+    let annotation = |key: &str| annotations.get(key).cloned();
+    let number = |key: &str| annotation(key).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+    let time = |key: &str| annotation(key)
+        .and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+        .map(|t| t.with_timezone(&Utc));
+
+    let pod_npub = npub_of_pod(pod)?;
+    let expires_at = time(ANNOTATION_EXPIRES_AT)?;
+    let created_at = time(ANNOTATION_CREATED_AT)
+        .or_else(|| pod.metadata.creation_timestamp.as_ref().map(|t| t.0))?;
+    // Pods from before the ssh-port annotation all used host networking
+    let port = match annotation(ANNOTATION_SSH_PORT).and_then(|v| v.parse::<u16>().ok()) {
+        Some(port) => port,
+        None => pod.spec.as_ref()?
+            .containers.first()?
+            .ports.as_ref()?
+            .first()
+            .map(|p| p.container_port as u16)?,
+    };
+
+    Some(PodInfo {
+        pod_npub: pod_npub.clone(),
+        namespace: pod.metadata.namespace.clone().unwrap_or_default(),
+        created_at,
+        expires_at,
+        allocated_port: port,
+        ssh_username: annotation(ANNOTATION_SSH_USERNAME)
+            .or_else(|| pod_env(pod, "USER_NAME"))
+            .unwrap_or_default(),
+        ssh_password: pod_env(pod, "USER_PASSWORD").unwrap_or_default(),
+        payment_amount_msats: number(ANNOTATION_PAYMENT_MSATS),
+        duration_seconds: number(ANNOTATION_DURATION_SECONDS),
+        rate_msats_per_sec: number(ANNOTATION_RATE_MSATS_PER_SEC),
+        payment_mint: annotation(ANNOTATION_PAYMENT_MINT).unwrap_or_default(),
+        payment_unit: annotation(ANNOTATION_PAYMENT_UNIT).unwrap_or_default(),
+        // Pods from before this annotation have no record of a redemption, so get no refunds
+        received_msats: number(ANNOTATION_RECEIVED_MSATS),
+        node_port: Some(port),
+        nostr_public_key: pod_npub,
+        nostr_private_key: pod_env(pod, "POD_NSEC").unwrap_or_default(),
+    })
+}
+
+// Name of the service exposing a pod's SSH port
+pub fn ssh_service_name(pod_name: &str) -> String {
+    format!("{}-ssh", pod_name)
+}
+
+// Owner reference that has Kubernetes delete an object along with `pod`
+fn owned_by(pod: &k8s_openapi::api::core::v1::Pod) -> k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference {
+    k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference {
+        api_version: "v1".to_string(),
+        kind: "Pod".to_string(),
+        name: pod.metadata.name.clone().unwrap_or_default(),
+        uid: pod.metadata.uid.clone().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+// NetworkPolicy for one SSH pod: SSH in from anywhere, egress as `network` allows.
+// With private ranges blocked the cluster DNS is let through explicitly.
+fn network_policy_spec(pod_name: &str, network: &NetworkPolicy) -> k8s_openapi::api::networking::v1::NetworkPolicySpec {
+    use k8s_openapi::api::networking::v1::{
+        IPBlock, NetworkPolicyEgressRule, NetworkPolicyIngressRule, NetworkPolicyPeer, NetworkPolicyPort, NetworkPolicySpec,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+    use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+
+    let port = |protocol: &str, port: Option<u16>, end_port: Option<u16>| NetworkPolicyPort {
+        protocol: Some(protocol.to_string()),
+        port: port.map(|p| IntOrString::Int(p as i32)),
+        end_port: end_port.map(|p| p as i32),
+    };
+    let selector = |key: &str, value: &str| LabelSelector {
+        match_labels: Some(BTreeMap::from([(key.to_string(), value.to_string())])),
+        ..Default::default()
+    };
+
+    // Every port, or TCP ranges around the blocked ones plus all of UDP
+    let mut blocked = network.blocked_tcp_ports();
+    let ports = if blocked.is_empty() {
+        None
+    } else {
+        blocked.sort_unstable();
+        let mut ports = vec![port("UDP", None, None)];
+        let mut start: u32 = 1;
+        for blocked_port in blocked {
+            if u32::from(blocked_port) > start {
+                ports.push(port("TCP", Some(start as u16), Some(blocked_port - 1)));
+            }
+            start = u32::from(blocked_port) + 1;
+        }
+        if start <= u32::from(u16::MAX) {
+            ports.push(port("TCP", Some(start as u16), Some(u16::MAX)));
+        }
+        Some(ports)
+    };
+
+    let (ipv6, ipv4): (Vec<&str>, Vec<&str>) = network.blocked_ranges().into_iter().partition(|range| range.contains(':'));
+    let egress = if !network.allows_egress() {
+        vec![]
+    } else if ipv4.is_empty() && ipv6.is_empty() {
+        vec![NetworkPolicyEgressRule { ports, to: None }]
+    } else {
+        let block = |cidr: &str, except: Vec<&str>| NetworkPolicyPeer {
+            ip_block: Some(IPBlock {
+                cidr: cidr.to_string(),
+                except: Some(except.into_iter().map(str::to_string).collect()),
+            }),
+            ..Default::default()
+        };
+        vec![
+            NetworkPolicyEgressRule {
+                ports: Some(vec![port("UDP", Some(DNS_PORT), None), port("TCP", Some(DNS_PORT), None)]),
+                to: Some(vec![NetworkPolicyPeer {
+                    namespace_selector: Some(selector("kubernetes.io/metadata.name", "kube-system")),
+                    pod_selector: Some(selector("k8s-app", "kube-dns")),
+                    ..Default::default()
+                }]),
+            },
+            NetworkPolicyEgressRule {
+                ports,
+                to: Some(vec![block("0.0.0.0/0", ipv4), block("::/0", ipv6)]),
+            },
+        ]
+    };
+
+    NetworkPolicySpec {
+        pod_selector: selector("pod-name", pod_name),
+        policy_types: Some(vec!["Ingress".to_string(), "Egress".to_string()]),
+        ingress: Some(vec![NetworkPolicyIngressRule {
+            from: None,
+            ports: Some(vec![port("TCP", Some(22), None)]),
+        }]),
+        egress: Some(egress),
+    }
+}
+
+// Kubernetes-safe pod name derived from the pod's NPUB (first 8 chars after the npub1 prefix)
+pub fn pod_name_for_npub(pod_npub: &str) -> String {
+    format!("pod-{}", pod_npub.replace("npub1", "").chars().take(8).collect::<String>())
 }
 
 // Extract token value in sats from Cashu token
@@ -716,4 +1285,100 @@ pub async fn extract_token_value(token: &str) -> Result<u64, String> {
         .map_err(|e| e.to_string())
 }
 
+/// Value of a Kubernetes resource quantity ("500m", "2", "16Gi", "100k") in base units
+pub fn parse_quantity(quantity: &str) -> Option<f64> {
+    const SUFFIXES: [(&str, f64); 13] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Pi", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Ei", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("m", 1e-3),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+
+    for (suffix, factor) in SUFFIXES {
+        if let Some(number) = quantity.strip_suffix(suffix) {
+            return number.parse::<f64>().ok().map(|n| n * factor);
+        }
+    }
+    quantity.parse().ok()
+}
+
+/// CPU, memory and ephemeral storage in a resource list, in the units specs are sold in
+fn resources_of(list: &BTreeMap<String, k8s_openapi::apimachinery::pkg::api::resource::Quantity>) -> Resources {
+    let amount = |key: &str| list.get(key).and_then(|q| parse_quantity(&q.0)).unwrap_or(0.0);
+    Resources {
+        cpu_millicores: (amount("cpu") * 1000.0) as u64,
+        memory_mb: (amount("memory") / (1024.0 * 1024.0)) as u64,
+        storage_gb: (amount("ephemeral-storage") / (1024.0 * 1024.0 * 1024.0)) as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("500m"), Some(0.5));
+        assert_eq!(parse_quantity("4"), Some(4.0));
+        assert_eq!(parse_quantity("16Gi"), Some(16.0 * 1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_quantity("1024Mi"), Some(1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_quantity("100k"), Some(100_000.0));
+        assert_eq!(parse_quantity("lots"), None);
+    }
+
+    #[test]
+    fn test_resources_of() {
+        use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 
+        let list: BTreeMap<String, Quantity> = [("cpu", "3920m"), ("memory", "2048Mi"), ("ephemeral-storage", "20Gi")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), Quantity(value.to_string())))
+            .collect();
+        assert_eq!(resources_of(&list), Resources { cpu_millicores: 3920, memory_mb: 2048, storage_gb: 20 });
+    }
+
+    #[test]
+    fn test_node_port_range() {
+        assert!(PodNetworking::NodePort.check_port_range(30000, 31000).is_ok());
+        assert!(PodNetworking::NodePort.check_port_range(30000, 32767).is_ok());
+        assert!(PodNetworking::NodePort.check_port_range(30000, 40000).is_err());
+        assert!(PodNetworking::NodePort.check_port_range(2222, 2300).is_err());
+        // Only node ports are limited to the range
+        assert!(PodNetworking::LoadBalancer.check_port_range(2222, 40000).is_ok());
+        assert!(PodNetworking::HostNetwork.check_port_range(2222, 40000).is_ok());
+    }
+
+    #[test]
+    fn test_pod_info_received_msats() {
+        use k8s_openapi::api::core::v1::Pod;
+
+        let pod_with = |annotations: BTreeMap<String, String>| {
+            let mut annotations = annotations;
+            annotations.insert(ANNOTATION_EXPIRES_AT.to_string(), Utc::now().to_rfc3339());
+            annotations.insert(ANNOTATION_CREATED_AT.to_string(), Utc::now().to_rfc3339());
+            annotations.insert(ANNOTATION_SSH_PORT.to_string(), "30022".to_string());
+
+            let mut pod = Pod::default();
+            pod.metadata.labels = Some([("pod-npub".to_string(), "abc".to_string())].into_iter().collect());
+            pod.metadata.annotations = Some(annotations);
+            pod
+        };
+
+        let paid = pod_info_from_pod(&pod_with(payment_annotations(60_000, 100, "https://mint.example", "sat", 59_000))).unwrap();
+        assert_eq!(paid.received_msats, 59_000);
+        assert_eq!(paid.payment_amount_msats, 60_000);
+
+        // No record of a redemption means nothing to refund
+        let unrecorded = pod_info_from_pod(&pod_with(BTreeMap::new())).unwrap();
+        assert_eq!(unrecorded.received_msats, 0);
+    }
+}
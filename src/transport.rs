@@ -0,0 +1,151 @@
+// Provider Transport
+//
+// How ProviderService talks to consumers: publishing offers and heartbeats,
+// receiving private requests and replying to them. NostrRelaySubscriber is the
+// real implementation; mock::MockRelay stands in for relays in tests.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::nostr::{
+    AccessDetailsContent, ErrorResponseContent, HeartbeatContent, NostrEvent, NostrRelaySubscriber,
+    ProviderOfferContent, StatusResponseContent, TerminateResponseContent, TopUpResponseContent,
+};
+
+/// Callback invoked for every decrypted request addressed to the provider
+pub type RequestHandler = Box<dyn Fn(NostrEvent) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+/// Box an async closure as a RequestHandler
+pub fn request_handler<F, Fut>(handler: F) -> RequestHandler
+where
+    F: Fn(NostrEvent) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    Box::new(move |event| Box::pin(handler(event)))
+}
+
+#[async_trait]
+pub trait ProviderTransport: Send + Sync {
+    /// Hex public key requests are addressed to
+    fn public_key_hex(&self) -> String;
+
+    async fn publish_provider_offer(&self, offer: ProviderOfferContent) -> Result<()>;
+
+    async fn publish_heartbeat(&self, heartbeat: HeartbeatContent) -> Result<()>;
+
+    /// Uptime percentage of `provider_npub` over the last `days`, from its heartbeats
+    async fn calculate_uptime(&self, provider_npub: &str, days: u32) -> Result<f32>;
+
+    /// Deliver incoming requests to `handler`; returns when the subscription ends
+    async fn subscribe_to_requests(&self, handler: RequestHandler) -> Result<()>;
+
+    /// Send an encrypted reply using `message_type` ("nip04" or "nip17")
+    async fn send_private_message(&self, receiver_pubkey: &str, content: String, message_type: &str) -> Result<()>;
+
+    // Send access details via private encrypted message
+    async fn send_access_details_private_message(
+        &self,
+        request_pubkey: &str,
+        details: AccessDetailsContent,
+        message_type: &str,
+    ) -> Result<()> {
+        let details_json = serde_json::to_string(&details)?;
+        self.send_private_message(request_pubkey, details_json, message_type).await
+    }
+
+    // Send terminate response via private encrypted message
+    async fn send_terminate_response(
+        &self,
+        request_pubkey: &str,
+        response: TerminateResponseContent,
+        message_type: &str,
+    ) -> Result<()> {
+        let response_json = serde_json::to_string(&response)?;
+        self.send_private_message(request_pubkey, response_json, message_type).await
+    }
+
+    // Send status response via private encrypted message
+    async fn send_status_response(
+        &self,
+        request_pubkey: &str,
+        response: StatusResponseContent,
+        message_type: &str,
+    ) -> Result<()> {
+        let response_json = serde_json::to_string(&response)?;
+        self.send_private_message(request_pubkey, response_json, message_type).await
+    }
+
+    // Convenience helper to send error response with individual fields
+    async fn send_error_response(
+        &self,
+        request_pubkey: &str,
+        error_type: &str,
+        message: &str,
+        details: Option<&str>,
+        message_type: &str,
+    ) -> Result<()> {
+        let error = ErrorResponseContent {
+            error_type: error_type.to_string(),
+            message: message.to_string(),
+            details: details.map(|s| s.to_string()),
+            accepted_mints: vec![],
+            refund_token: None,
+        };
+        self.send_error_response_private_message(request_pubkey, error, message_type).await
+    }
+
+    // Send error response via private encrypted message
+    async fn send_error_response_private_message(
+        &self,
+        request_pubkey: &str,
+        error: ErrorResponseContent,
+        message_type: &str,
+    ) -> Result<()> {
+        let error_json = serde_json::to_string(&error)?;
+        self.send_private_message(request_pubkey, error_json, message_type).await
+    }
+
+    // Send top-up response via private encrypted message
+    async fn send_topup_response_private_message(
+        &self,
+        request_pubkey: &str,
+        response: TopUpResponseContent,
+        message_type: &str,
+    ) -> Result<()> {
+        let response_json = serde_json::to_string(&response)?;
+        self.send_private_message(request_pubkey, response_json, message_type).await
+    }
+}
+
+#[async_trait]
+impl ProviderTransport for NostrRelaySubscriber {
+    fn public_key_hex(&self) -> String {
+        self.get_service_public_key()
+    }
+
+    async fn publish_provider_offer(&self, offer: ProviderOfferContent) -> Result<()> {
+        NostrRelaySubscriber::publish_provider_offer(self, offer).await?;
+        Ok(())
+    }
+
+    async fn publish_heartbeat(&self, heartbeat: HeartbeatContent) -> Result<()> {
+        NostrRelaySubscriber::publish_heartbeat(self, heartbeat).await?;
+        Ok(())
+    }
+
+    async fn calculate_uptime(&self, provider_npub: &str, days: u32) -> Result<f32> {
+        NostrRelaySubscriber::calculate_uptime(self, provider_npub, days).await
+    }
+
+    async fn subscribe_to_requests(&self, handler: RequestHandler) -> Result<()> {
+        self.subscribe_to_pod_events(handler).await
+    }
+
+    async fn send_private_message(&self, receiver_pubkey: &str, content: String, message_type: &str) -> Result<()> {
+        self.send_encrypted_private_message(receiver_pubkey, content, message_type).await?;
+        Ok(())
+    }
+}
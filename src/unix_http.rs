@@ -0,0 +1,47 @@
+// Unix Socket HTTP
+//
+// Plain HTTP/1.1 client for daemons that serve their API on a unix socket
+// (LXD, Docker/Podman). Opens one connection per request.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use hyper::Method;
+use serde_json::Value;
+use tracing::debug;
+
+/// Send `body` as JSON to `path` on the daemon behind `socket`; returns the status and raw body
+#[cfg(unix)]
+pub async fn request(socket: &Path, method: Method, path: &str, body: Option<&Value>) -> Result<(u16, Vec<u8>)> {
+    let stream = tokio::net::UnixStream::connect(socket).await
+        .with_context(|| format!("Failed to connect to {}", socket.display()))?;
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Unix socket connection closed: {}", e);
+        }
+    });
+
+    let body = match body {
+        Some(body) => hyper::Body::from(body.to_string()),
+        None => hyper::Body::empty(),
+    };
+    let request = hyper::Request::builder()
+        .method(method)
+        .uri(path)
+        .header("Host", "localhost")
+        .header("Content-Type", "application/json")
+        .body(body)?;
+
+    let response = sender.send_request(request).await
+        .with_context(|| format!("Request to {} failed", socket.display()))?;
+    let status = response.status().as_u16();
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+
+    Ok((status, bytes.to_vec()))
+}
+
+#[cfg(not(unix))]
+pub async fn request(socket: &Path, _method: Method, _path: &str, _body: Option<&Value>) -> Result<(u16, Vec<u8>)> {
+    Err(anyhow::anyhow!("{}: unix sockets are not supported on this platform", socket.display()))
+}
